@@ -1,3 +1,18 @@
+DROP TABLE IF EXISTS `accountmovement`;
+DROP TABLE IF EXISTS `savedsearchresult`;
+DROP TABLE IF EXISTS `savedsearch`;
+DROP TABLE IF EXISTS `clientnote`;
+DROP TABLE IF EXISTS `clientcontact`;
+DROP TABLE IF EXISTS `clientdocument`;
+DROP TABLE IF EXISTS `clientarchive`;
+DROP TABLE IF EXISTS `clientalias`;
+DROP TABLE IF EXISTS `guarantee`;
+DROP TABLE IF EXISTS `collateral`;
+DROP TABLE IF EXISTS `creditlimit`;
+DROP TABLE IF EXISTS `loanrecovery`;
+DROP TABLE IF EXISTS `loanwriteoff`;
+DROP TABLE IF EXISTS `loanschedule`;
+DROP TABLE IF EXISTS `loanagreement`;
 DROP TABLE IF EXISTS `accountmanagement`;
 DROP TABLE IF EXISTS `checkingaccount`;
 DROP TABLE IF EXISTS `own`;
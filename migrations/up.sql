@@ -28,9 +28,14 @@ CREATE TABLE `loan` (
   CONSTRAINT `FK_paidby` FOREIGN KEY (`subbranchName`) REFERENCES `subbranch` (`subbranchName`) ON DELETE RESTRICT ON UPDATE RESTRICT
 ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
 CREATE TABLE `payment` (
+  `paymentID` char(64) NOT NULL,
   `loanID` char(64) NOT NULL,
   `date` date NOT NULL,
   `amount` decimal(64, 2) NOT NULL,
+  `reversalOf` char(64) DEFAULT NULL,
+  `reason` varchar(256) DEFAULT NULL,
+  PRIMARY KEY (`paymentID`),
+  UNIQUE KEY `UK_reversal` (`reversalOf`),
   CONSTRAINT `FK_pay` FOREIGN KEY (`loanID`) REFERENCES `loan` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT
 ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
 CREATE TABLE `department` (
@@ -68,10 +73,6 @@ CREATE TABLE `client` (
   `clientName` varchar(64) DEFAULT NULL,
   `clientTel` varchar(64) DEFAULT NULL,
   `clientAddr` varchar(64) DEFAULT NULL,
-  `contactName` varchar(64) DEFAULT NULL,
-  `contactTel` varchar(64) DEFAULT NULL,
-  `contactEmail` varchar(64) DEFAULT NULL,
-  `contactRelationship` varchar(64) DEFAULT NULL,
   `serviceType` varchar(64) DEFAULT NULL,
   PRIMARY KEY (`clientID`),
   KEY `FK_servicetype` (`employeeID`),
@@ -112,3 +113,157 @@ CREATE TABLE `accountmanagement` (
   CONSTRAINT `FK_accountmanagement_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT,
   CONSTRAINT `FK_accountmanagement_subbranch` FOREIGN KEY (`subbranchName`) REFERENCES `subbranch` (`subbranchName`) ON DELETE RESTRICT ON UPDATE RESTRICT
 ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `loanagreement` (
+  `loanID` char(64) NOT NULL,
+  `version` int NOT NULL,
+  `effectiveDate` date NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  `termMonths` int NOT NULL,
+  `rate` float NOT NULL,
+  `borrowers` varchar(1024) NOT NULL,
+  `reason` varchar(256) DEFAULT NULL,
+  PRIMARY KEY (`loanID`, `version`),
+  CONSTRAINT `FK_agreement_loan` FOREIGN KEY (`loanID`) REFERENCES `loan` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `loanschedule` (
+  `loanID` char(64) NOT NULL,
+  `version` int NOT NULL,
+  `installment` int NOT NULL,
+  `dueDate` date NOT NULL,
+  `principal` decimal(64, 2) NOT NULL,
+  `interest` decimal(64, 2) NOT NULL,
+  PRIMARY KEY (`loanID`, `version`, `installment`),
+  CONSTRAINT `FK_schedule_agreement` FOREIGN KEY (`loanID`, `version`) REFERENCES `loanagreement` (`loanID`, `version`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `loanwriteoff` (
+  `loanID` char(64) NOT NULL,
+  `date` date NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  `reason` varchar(256) NOT NULL,
+  `approverID` char(64) NOT NULL,
+  PRIMARY KEY (`loanID`),
+  CONSTRAINT `FK_writeoff_loan` FOREIGN KEY (`loanID`) REFERENCES `loan` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT,
+  CONSTRAINT `FK_writeoff_approver` FOREIGN KEY (`approverID`) REFERENCES `employee` (`employeeID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `loanrecovery` (
+  `recoveryID` char(64) NOT NULL,
+  `loanID` char(64) NOT NULL,
+  `date` date NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  PRIMARY KEY (`recoveryID`),
+  CONSTRAINT `FK_recovery_writeoff` FOREIGN KEY (`loanID`) REFERENCES `loanwriteoff` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `creditlimit` (
+  `scope` varchar(64) NOT NULL,
+  `target` varchar(64) NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  `sharedAmongBorrowers` boolean NOT NULL,
+  PRIMARY KEY (`scope`, `target`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `collateral` (
+  `collateralID` char(64) NOT NULL,
+  `loanID` char(64) NOT NULL,
+  `collateralType` varchar(64) NOT NULL,
+  `description` varchar(256) DEFAULT NULL,
+  `appraisedValue` decimal(64, 2) NOT NULL,
+  `appraisalDate` date NOT NULL,
+  `releaseDate` date DEFAULT NULL,
+  PRIMARY KEY (`collateralID`),
+  CONSTRAINT `FK_collateral_loan` FOREIGN KEY (`loanID`) REFERENCES `loan` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `guarantee` (
+  `loanID` char(64) NOT NULL,
+  `clientID` char(64) NOT NULL,
+  PRIMARY KEY (`loanID`, `clientID`),
+  CONSTRAINT `FK_guarantee_loan` FOREIGN KEY (`loanID`) REFERENCES `loan` (`loanID`) ON DELETE RESTRICT ON UPDATE RESTRICT,
+  CONSTRAINT `FK_guarantee_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `clientalias` (
+  `aliasID` char(64) NOT NULL,
+  `clientID` char(64) NOT NULL,
+  `mergeDate` date NOT NULL,
+  PRIMARY KEY (`aliasID`),
+  KEY `FK_alias_client` (`clientID`),
+  CONSTRAINT `FK_alias_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `clientarchive` (
+  `clientID` char(64) NOT NULL,
+  `archiveDate` date NOT NULL,
+  `reason` varchar(256) DEFAULT NULL,
+  PRIMARY KEY (`clientID`),
+  CONSTRAINT `FK_archive_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `clientdocument` (
+  `documentID` char(64) NOT NULL,
+  `clientID` char(64) NOT NULL,
+  `documentType` varchar(64) NOT NULL,
+  `fileName` varchar(256) NOT NULL,
+  `contentType` varchar(128) NOT NULL,
+  `size` bigint NOT NULL,
+  `sha256` char(64) NOT NULL,
+  `uploadDate` date NOT NULL,
+  `expiryDate` date DEFAULT NULL,
+  PRIMARY KEY (`documentID`),
+  KEY `FK_document_client` (`clientID`),
+  CONSTRAINT `FK_document_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `clientcontact` (
+  `contactID` char(64) NOT NULL,
+  `clientID` char(64) NOT NULL,
+  `contactName` varchar(64) NOT NULL,
+  `contactTel` varchar(64) DEFAULT NULL,
+  `contactEmail` varchar(64) DEFAULT NULL,
+  `contactRelationship` varchar(64) DEFAULT NULL,
+  `isPrimary` boolean NOT NULL,
+  PRIMARY KEY (`contactID`),
+  KEY `FK_contact_client` (`clientID`),
+  CONSTRAINT `FK_contact_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `clientnote` (
+  `noteID` char(64) NOT NULL,
+  `clientID` char(64) NOT NULL,
+  `authorID` char(64) NOT NULL,
+  `noteType` varchar(64) NOT NULL,
+  `content` text NOT NULL,
+  `createdAt` datetime NOT NULL,
+  `updatedAt` datetime DEFAULT NULL,
+  PRIMARY KEY (`noteID`),
+  KEY `FK_note_client` (`clientID`),
+  KEY `FK_note_author` (`authorID`),
+  CONSTRAINT `FK_note_client` FOREIGN KEY (`clientID`) REFERENCES `client` (`clientID`) ON DELETE RESTRICT ON UPDATE RESTRICT,
+  CONSTRAINT `FK_note_author` FOREIGN KEY (`authorID`) REFERENCES `employee` (`employeeID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `savedsearch` (
+  `searchID` char(64) NOT NULL,
+  `searchName` varchar(64) NOT NULL,
+  `employeeID` char(64) NOT NULL,
+  `query` varchar(2048) NOT NULL,
+  `watch` boolean NOT NULL,
+  `createdAt` datetime NOT NULL,
+  `lastViewedAt` datetime DEFAULT NULL,
+  PRIMARY KEY (`searchID`),
+  KEY `FK_savedsearch_employee` (`employeeID`),
+  CONSTRAINT `FK_savedsearch_employee` FOREIGN KEY (`employeeID`) REFERENCES `employee` (`employeeID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `savedsearchresult` (
+  `searchID` char(64) NOT NULL,
+  `resultKey` varchar(192) NOT NULL,
+  `fingerprint` char(64) NOT NULL,
+  `addedAt` datetime NOT NULL,
+  `changedAt` datetime NOT NULL,
+  `removedAt` datetime DEFAULT NULL,
+  PRIMARY KEY (`searchID`, `resultKey`),
+  CONSTRAINT `FK_result_savedsearch` FOREIGN KEY (`searchID`) REFERENCES `savedsearch` (`searchID`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+CREATE TABLE `accountmovement` (
+  `movementID` char(64) NOT NULL,
+  `accountID` char(64) NOT NULL,
+  `accountType` varchar(64) NOT NULL,
+  `subbranchName` varchar(64) NOT NULL,
+  `date` date NOT NULL,
+  `kind` varchar(16) NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  PRIMARY KEY (`movementID`),
+  KEY `FK_movement_subbranch` (`subbranchName`),
+  CONSTRAINT `FK_movement_subbranch` FOREIGN KEY (`subbranchName`) REFERENCES `subbranch` (`subbranchName`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
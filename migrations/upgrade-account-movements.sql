@@ -0,0 +1,28 @@
+-- Upgrades a database created before account movements were recorded.
+-- Run it once on such a database; a database built from up.sql does not need it.
+CREATE TABLE IF NOT EXISTS `accountmovement` (
+  `movementID` char(64) NOT NULL,
+  `accountID` char(64) NOT NULL,
+  `accountType` varchar(64) NOT NULL,
+  `subbranchName` varchar(64) NOT NULL,
+  `date` date NOT NULL,
+  `kind` varchar(16) NOT NULL,
+  `amount` decimal(64, 2) NOT NULL,
+  PRIMARY KEY (`movementID`),
+  KEY `FK_movement_subbranch` (`subbranchName`),
+  CONSTRAINT `FK_movement_subbranch` FOREIGN KEY (`subbranchName`) REFERENCES `subbranch` (`subbranchName`) ON DELETE RESTRICT ON UPDATE RESTRICT
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_0900_ai_ci;
+-- Accounts opened before movements were recorded are given an `open` movement of their current balance,
+-- so that closing them later brings the balance of their subbranch back to zero
+INSERT INTO `accountmovement` (`movementID`, `accountID`, `accountType`, `subbranchName`, `date`, `kind`, `amount`)
+SELECT UUID(), `account`.`accountID`, 'savingAccount', `managed`.`subbranchName`, `account`.`openDate`, 'open', `account`.`balance`
+FROM `account`
+  JOIN (SELECT DISTINCT `savingAccountID`, `subbranchName` FROM `accountmanagement` WHERE `savingAccountID` IS NOT NULL) AS `managed`
+    ON `managed`.`savingAccountID` = `account`.`accountID`
+WHERE NOT EXISTS (SELECT * FROM `accountmovement` WHERE `accountmovement`.`accountID` = `account`.`accountID`);
+INSERT INTO `accountmovement` (`movementID`, `accountID`, `accountType`, `subbranchName`, `date`, `kind`, `amount`)
+SELECT UUID(), `account`.`accountID`, 'checkingAccount', `managed`.`subbranchName`, `account`.`openDate`, 'open', `account`.`balance`
+FROM `account`
+  JOIN (SELECT DISTINCT `checkingAccountID`, `subbranchName` FROM `accountmanagement` WHERE `checkingAccountID` IS NOT NULL) AS `managed`
+    ON `managed`.`checkingAccountID` = `account`.`accountID`
+WHERE NOT EXISTS (SELECT * FROM `accountmovement` WHERE `accountmovement`.`accountID` = `account`.`accountID`);
@@ -1,6 +1,9 @@
 use crate::{preludes::rocket_prelude::*, utility::GenericError};
 
-use super::query::SpecificAccount;
+use super::{
+    movement::{record_movement, MovementKind},
+    query::SpecificAccount,
+};
 
 #[derive(Clone, Debug)]
 pub enum AccountType {
@@ -50,7 +53,19 @@ pub async fn delete_account_and_own(
 
     eprintln!("own relations removed");
 
-    delete_account_entity(db, id.clone(), AccountType::from(&account.0)).await?;
+    let account_type = AccountType::from(&account.0);
+    let balance = Account::from(account.0).balance;
+    record_movement(
+        db,
+        &id,
+        &account_type,
+        &account.1,
+        MovementKind::Close,
+        &-balance,
+    )
+    .await?;
+
+    delete_account_entity(db, id.clone(), account_type).await?;
 
     Ok(())
 }
@@ -126,6 +141,7 @@ pub async fn delete_owning_relation(
             .execute(&mut **db)
             .await?;
     }
+    crate::service_type::refresh_service_type(db, &client_id).await?;
 
     Ok(())
 }
@@ -2,10 +2,16 @@ use crate::{
     preludes::rocket_prelude::*,
     subbranch_manage::{query_subbranch, set_subbranch_asset},
     utility::GenericError,
+    validation::{FieldErrors, Validate, Validator},
 };
 use chrono::prelude::*;
 use sqlx::types::BigDecimal;
 
+use super::{
+    delete::AccountType,
+    movement::{record_movement, MovementKind},
+};
+
 #[derive(Debug, FromForm, Default, Serialize)]
 pub struct AccountSubmit {
     pub clientIDs: String,
@@ -17,13 +23,46 @@ pub struct AccountSubmit {
     pub interest: String,
 }
 
+impl Validate for AccountSubmit {
+    fn validate(&self) -> FieldErrors {
+        let mut validator = Validator::default();
+        validator
+            .required("clientIDs", &self.clientIDs)
+            .check(
+                "clientIDs",
+                crate::utility::get_client_id_list_from_input::<Vec<_>>(&self.clientIDs),
+            )
+            .one_of(
+                "accountType",
+                &self.accountType,
+                &["savingAccount", "checkingAccount"],
+            )
+            .required("subbranchName", &self.subbranchName)
+            .required("balance", &self.balance)
+            .pattern("balance", &self.balance, "amount");
+        match &self.accountType as &str {
+            "savingAccount" => validator
+                .required("currencyType", &self.currencyType)
+                .pattern("currencyType", &self.currencyType, "currency_type")
+                .required("interest", &self.interest)
+                .pattern("interest", &self.interest, "float"),
+            _ => validator.required("overdraft", &self.overdraft).pattern(
+                "overdraft",
+                &self.overdraft,
+                "amount",
+            ),
+        };
+        validator.finish()
+    }
+}
+
 /// Add account entity, owning relation and update the assets of subbranch
 pub async fn add_new_account_and_own(
     db: &mut Connection<BankManage>,
     submission: &AccountSubmit,
 ) -> Result<String, GenericError> {
     let account_id = add_account_entity(db, submission).await?;
-    let clientIDs: Vec<_> = crate::utility::get_list_from_input(&submission.clientIDs);
+    let clientIDs: Vec<_> = crate::utility::get_client_id_list_from_input(&submission.clientIDs)?;
     for client_id in clientIDs {
         add_owning_relation(
             db,
@@ -37,8 +76,22 @@ pub async fn add_new_account_and_own(
     let subbranch_asset = query_subbranch(db, &submission.subbranchName)
         .await?
         .subbranchAsset;
-    let new_subbranch_asset = subbranch_asset + submission.balance.parse::<BigDecimal>()?;
+    let balance = submission.balance.parse::<BigDecimal>()?;
+    let new_subbranch_asset = subbranch_asset + &balance;
     set_subbranch_asset(db, &submission.subbranchName, &new_subbranch_asset).await?;
+    let account_type = match &submission.accountType as &str {
+        "savingAccount" => AccountType::SavingAccount,
+        _ => AccountType::CheckingAccount,
+    };
+    record_movement(
+        db,
+        &account_id,
+        &account_type,
+        &submission.subbranchName,
+        MovementKind::Open,
+        &balance,
+    )
+    .await?;
 
     Ok(account_id)
 }
@@ -181,5 +234,6 @@ pub async fn add_owning_relation(
         (savingAccount, savingAccountID),
         (checkingAccount, checkingAccountID)
     );
+    crate::service_type::refresh_service_type(db, &client_id).await?;
     Ok(())
 }
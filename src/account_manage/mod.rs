@@ -1,4 +1,5 @@
 pub mod delete;
 pub mod insert;
+pub mod movement;
 pub mod query;
 pub mod update;
@@ -0,0 +1,88 @@
+use bigdecimal::Zero;
+use chrono::Local;
+use sqlx::types::BigDecimal;
+
+use crate::{preludes::rocket_prelude::*, utility::GenericError};
+
+use super::delete::AccountType;
+
+/// Kinds of `accountmovement`. The amount is positive for money coming in and negative for money going out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementKind {
+    /// The balance the account is opened with
+    Open,
+    Deposit,
+    Withdrawal,
+    /// The balance left when the account is closed
+    Close,
+}
+
+impl MovementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovementKind::Open => "open",
+            MovementKind::Deposit => "deposit",
+            MovementKind::Withdrawal => "withdrawal",
+            MovementKind::Close => "close",
+        }
+    }
+}
+
+/// Add entity to `accountmovement`. The movements outlive the account, so that past balances can still be told.
+pub async fn record_movement(
+    db: &mut Connection<BankManage>,
+    account_id: &str,
+    account_type: &AccountType,
+    subbranch: &str,
+    kind: MovementKind,
+    amount: &BigDecimal,
+) -> Result<(), GenericError> {
+    sqlx::query(
+        "INSERT INTO accountmovement (movementID, accountID, accountType, subbranchName, date, kind, amount) VALUES
+    (?, ?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(account_id)
+    .bind(account_type.to_string())
+    .bind(subbranch)
+    .bind(Local::today().naive_local())
+    .bind(kind.as_str())
+    .bind(amount)
+    .execute(&mut **db)
+    .await?;
+    Ok(())
+}
+
+/// Records the change of the balance of an account as a deposit or a withdrawal, or nothing if it is unchanged
+pub async fn record_balance_change(
+    db: &mut Connection<BankManage>,
+    account_id: &str,
+    account_type: &AccountType,
+    subbranch: &str,
+    change: &BigDecimal,
+) -> Result<(), GenericError> {
+    if change.is_zero() {
+        return Ok(());
+    }
+    let kind = if change > &BigDecimal::zero() {
+        MovementKind::Deposit
+    } else {
+        MovementKind::Withdrawal
+    };
+    record_movement(db, account_id, account_type, subbranch, kind, change).await
+}
+
+/// Movements of the accounts managed by the subbranch, the earliest first
+pub async fn query_subbranch_movements(
+    db: &mut Connection<BankManage>,
+    subbranch: &str,
+) -> Result<Vec<AccountMovement>, GenericError> {
+    Ok(sqlx::query_as!(
+        AccountMovement,
+        "SELECT * FROM accountmovement WHERE subbranchName=? ORDER BY date",
+        subbranch
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
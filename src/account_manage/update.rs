@@ -2,9 +2,13 @@ use std::collections::HashSet;
 
 use sqlx::types::BigDecimal;
 
-use crate::{preludes::rocket_prelude::*, utility::GenericError};
+use crate::{
+    preludes::rocket_prelude::*,
+    utility::GenericError,
+    validation::{FieldErrors, Validate, Validator},
+};
 
-use super::{delete::AccountType, query::query_account_by_id};
+use super::{delete::AccountType, movement::record_balance_change, query::query_account_by_id};
 
 // Modify table `account` and `subbranch`
 async fn update_generic_account(
@@ -13,13 +17,16 @@ async fn update_generic_account(
     balance: &String,
 ) -> std::result::Result<(), GenericError> {
     let (specific_account, subbranch) = query_account_by_id(db, &id).await?;
+    let account_type = AccountType::from(&specific_account);
     let account = Account::from(specific_account);
     let balance: BigDecimal = balance.parse()?;
+    let change = &balance - account.balance;
     let subbranch_asset = crate::subbranch_manage::query_subbranch(db, &subbranch)
         .await?
         .subbranchAsset
-        + (&balance - account.balance);
+        + &change;
     crate::subbranch_manage::set_subbranch_asset(db, &subbranch, &subbranch_asset).await?;
+    record_balance_change(db, &id, &account_type, &subbranch, &change).await?;
     sqlx::query("UPDATE account SET balance=? WHERE accountID=?")
         .bind(&balance)
         .bind(&id)
@@ -36,6 +43,24 @@ pub struct SavingAccountSubmit {
     pub interest: String,
 }
 
+impl Validate for SavingAccountSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("clientIDs", &self.clientIDs)
+            .check(
+                "clientIDs",
+                crate::utility::get_client_id_list_from_input::<Vec<_>>(&self.clientIDs),
+            )
+            .required("balance", &self.balance)
+            .pattern("balance", &self.balance, "amount")
+            .required("currencyType", &self.currencyType)
+            .pattern("currencyType", &self.currencyType, "currency_type")
+            .required("interest", &self.interest)
+            .pattern("interest", &self.interest, "float")
+            .finish()
+    }
+}
+
 // Modify table `saving account` and `account`
 async fn update_saving_account(
     db: &mut Connection<BankManage>,
@@ -86,6 +111,22 @@ pub struct CheckingAccountSubmit {
     pub overdraft: String,
 }
 
+impl Validate for CheckingAccountSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("clientIDs", &self.clientIDs)
+            .check(
+                "clientIDs",
+                crate::utility::get_client_id_list_from_input::<Vec<_>>(&self.clientIDs),
+            )
+            .required("balance", &self.balance)
+            .pattern("balance", &self.balance, "amount")
+            .required("overdraft", &self.overdraft)
+            .pattern("overdraft", &self.overdraft, "amount")
+            .finish()
+    }
+}
+
 // Modify table `checking account` and `account`
 async fn update_checking_account(
     db: &mut Connection<BankManage>,
@@ -139,9 +180,15 @@ async fn update_owning_relation(
             .await?
             .into_iter()
             .collect();
-    let to_add = new_associated_client_IDs.difference(&current_associated_client_IDs);
+    let to_add: Vec<String> = new_associated_client_IDs
+        .difference(&current_associated_client_IDs)
+        .cloned()
+        .collect();
     let to_remove = current_associated_client_IDs.difference(&new_associated_client_IDs);
 
+    // New owners should hold valid identity documents, as when an account is opened
+    crate::kyc::check_kyc(db, &to_add).await?;
+
     use super::insert::*;
 
     for to_add_client in to_add {
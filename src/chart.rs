@@ -0,0 +1,152 @@
+//! Charts rendered on the server as inline SVG, so that pages need no script to show them
+
+use std::fmt::Write;
+
+const WIDTH: f64 = 760.0;
+const HEIGHT: f64 = 260.0;
+const MARGIN_LEFT: f64 = 70.0;
+const MARGIN_RIGHT: f64 = 10.0;
+const MARGIN_TOP: f64 = 10.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+/// Horizontal grid lines, including the bottom and the top ones
+const Y_TICKS: usize = 5;
+/// Most labels on the horizontal axis, so that they do not overlap
+const MAX_X_LABELS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    /// For levels, such as balances at the end of each period
+    Line,
+    /// For flows, such as amounts paid during each period
+    Bar,
+}
+
+#[derive(Debug)]
+pub struct ChartPoint {
+    pub label: String,
+    pub value: f64,
+    /// The value as shown when hovering over the point
+    pub display: String,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Short form of an axis value, such as 1.5k or 2M
+fn compact(value: f64) -> String {
+    let magnitude = value.abs();
+    let (scaled, suffix) = if magnitude >= 1e9 {
+        (value / 1e9, "B")
+    } else if magnitude >= 1e6 {
+        (value / 1e6, "M")
+    } else if magnitude >= 1e3 {
+        (value / 1e3, "k")
+    } else {
+        (value, "")
+    };
+    let text = format!("{scaled:.2}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    format!("{text}{suffix}")
+}
+
+/// Renders the points, the earliest first, as an SVG element.
+/// Hovering over a point or a bar shows its value; `title` is read out by screen readers.
+pub fn render(kind: ChartKind, title: &str, points: &[ChartPoint]) -> String {
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+
+    // The axis always includes zero, so that bars start from it
+    let min = points.iter().map(|p| p.value).fold(0.0, f64::min);
+    let mut max = points.iter().map(|p| p.value).fold(0.0, f64::max);
+    if max - min < f64::EPSILON {
+        max = min + 1.0;
+    }
+    let y = |value: f64| MARGIN_TOP + (max - value) / (max - min) * plot_height;
+    let slot = plot_width / points.len().max(1) as f64;
+    let x = |i: usize| MARGIN_LEFT + slot * (i as f64 + 0.5);
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg class="chart" viewBox="0 0 {WIDTH} {HEIGHT}" role="img" aria-label="{}"><title>{}</title>"#,
+        escape(title),
+        escape(title)
+    );
+
+    for tick in 0..Y_TICKS {
+        let value = min + (max - min) * tick as f64 / (Y_TICKS - 1) as f64;
+        let _ = write!(
+            svg,
+            r#"<line class="chart-grid" x1="{MARGIN_LEFT}" x2="{}" y1="{y:.1}" y2="{y:.1}"/><text class="chart-axis" x="{}" y="{y:.1}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+            WIDTH - MARGIN_RIGHT,
+            MARGIN_LEFT - 6.0,
+            compact(value),
+            y = y(value),
+        );
+    }
+    let _ = write!(
+        svg,
+        r#"<line class="chart-zero" x1="{MARGIN_LEFT}" x2="{}" y1="{y:.1}" y2="{y:.1}"/>"#,
+        WIDTH - MARGIN_RIGHT,
+        y = y(0.0),
+    );
+
+    let label_step = points.len().div_ceil(MAX_X_LABELS);
+    for (i, point) in points.iter().enumerate() {
+        if i % label_step.max(1) == 0 {
+            let _ = write!(
+                svg,
+                r#"<text class="chart-axis" x="{:.1}" y="{}" text-anchor="middle">{}</text>"#,
+                x(i),
+                HEIGHT - MARGIN_BOTTOM + 16.0,
+                escape(&point.label),
+            );
+        }
+    }
+
+    match kind {
+        ChartKind::Bar => {
+            let width = slot * 0.7;
+            for (i, point) in points.iter().enumerate() {
+                let (top, bottom) = (y(point.value.max(0.0)), y(point.value.min(0.0)));
+                let _ = write!(
+                    svg,
+                    r#"<rect class="chart-bar" x="{:.1}" y="{top:.1}" width="{width:.1}" height="{:.1}"><title>{}: {}</title></rect>"#,
+                    x(i) - width / 2.0,
+                    (bottom - top).max(1.0),
+                    escape(&point.label),
+                    escape(&point.display),
+                );
+            }
+        }
+        ChartKind::Line => {
+            let path: Vec<String> = points
+                .iter()
+                .enumerate()
+                .map(|(i, point)| format!("{:.1},{:.1}", x(i), y(point.value)))
+                .collect();
+            let _ = write!(
+                svg,
+                r#"<polyline class="chart-line" points="{}"/>"#,
+                path.join(" ")
+            );
+            for (i, point) in points.iter().enumerate() {
+                let _ = write!(
+                    svg,
+                    r#"<circle class="chart-point" cx="{:.1}" cy="{:.1}" r="4"><title>{}: {}</title></circle>"#,
+                    x(i),
+                    y(point.value),
+                    escape(&point.label),
+                    escape(&point.display),
+                );
+            }
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+
+use super::preludes::rocket_prelude::*;
+use crate::utility::{get_restriction, GenericError, Restriction};
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use sqlx::Executor;
+
+/// Contacts of a client, the primary one first
+pub async fn query_contacts(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Vec<ClientContact>, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientContact,
+        "SELECT * FROM clientcontact WHERE clientID=? ORDER BY isPrimary DESC, contactName",
+        client_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+async fn query_contact(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<ClientContact, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientContact,
+        "SELECT * FROM clientcontact WHERE contactID=?",
+        id
+    )
+    .fetch_one(&mut **db)
+    .await?)
+}
+
+fn none_if_empty(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Inserts the contact. A primary contact takes over from the previous one.
+pub async fn add_contact(
+    db: &mut Connection<BankManage>,
+    contact: &ClientContact,
+) -> Result<(), GenericError> {
+    if contact.isPrimary {
+        clear_primary(db, &contact.clientID).await?;
+    }
+    sqlx::query(
+        "INSERT INTO clientcontact (contactID, clientID, contactName, contactTel, contactEmail, contactRelationship, isPrimary) VALUES
+    (?, ?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(&contact.contactID)
+    .bind(&contact.clientID)
+    .bind(&contact.contactName)
+    .bind(&contact.contactTel)
+    .bind(&contact.contactEmail)
+    .bind(&contact.contactRelationship)
+    .bind(contact.isPrimary)
+    .execute(&mut **db)
+    .await?;
+    Ok(())
+}
+
+async fn clear_primary(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<(), GenericError> {
+    sqlx::query("UPDATE clientcontact SET isPrimary=false WHERE clientID=?")
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(())
+}
+
+/// Makes sure the client has a primary contact as long as it has any contact
+async fn ensure_primary(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<(), GenericError> {
+    let contacts = query_contacts(db, client_id).await?;
+    if let Some(contact) = contacts.first() {
+        if !contact.isPrimary {
+            sqlx::query("UPDATE clientcontact SET isPrimary=true WHERE contactID=?")
+                .bind(&contact.contactID)
+                .execute(&mut **db)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_contacts(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<(), GenericError> {
+    sqlx::query("DELETE FROM clientcontact WHERE clientID=?")
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct ContactSubmit {
+    contactName: String,
+    contactTel: String,
+    contactEmail: String,
+    contactRelationship: String,
+    isPrimary: bool,
+}
+
+impl Validate for ContactSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("contactName", &self.contactName)
+            .pattern("contactName", &self.contactName, "name")
+            .pattern("contactTel", &self.contactTel, "tel")
+            .pattern("contactEmail", &self.contactEmail, "email")
+            .finish()
+    }
+}
+
+impl ContactSubmit {
+    fn into_contact(self, contact_id: String, client_id: String) -> ClientContact {
+        ClientContact {
+            contactID: contact_id,
+            clientID: client_id,
+            contactName: self.contactName,
+            contactTel: none_if_empty(&self.contactTel),
+            contactEmail: none_if_empty(&self.contactEmail),
+            contactRelationship: none_if_empty(&self.contactRelationship),
+            isPrimary: self.isPrimary,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ContactContext {
+    /// Where the form is posted to
+    action: String,
+    clientID: String,
+    contact: ClientContact,
+    restriction: Restriction,
+    errors: FieldErrors,
+}
+
+#[get("/new/contact?<id>")]
+pub async fn get_new_contact(id: String) -> Template {
+    Template::render(
+        "edit-contact",
+        ContactContext {
+            action: format!("/new/contact?id={id}"),
+            clientID: id.clone(),
+            contact: ClientContact {
+                clientID: id,
+                ..ClientContact::default()
+            },
+            restriction: get_restriction(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[post("/new/contact?<id>", data = "<form>")]
+pub async fn submit_new_contact(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, ContactSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let submission = match form.into_inner().value {
+        Some(value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error adding contact: failed to receive form"),
+            )
+        }
+    };
+    let errors = submission.validate();
+    let contact = submission.into_contact(uuid::Uuid::new_v4().to_string(), id.clone());
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "edit-contact",
+                ContactContext {
+                    action: format!("/new/contact?id={id}"),
+                    clientID: id,
+                    contact,
+                    restriction: get_restriction(),
+                    errors,
+                },
+            ),
+        );
+    }
+
+    start_transaction!(db);
+    unwrap_or!(add_contact(&mut db, &contact).await, e, {
+        rollback!(db);
+        return (status, error_template!(e, "Error adding contact"));
+    });
+    unwrap_or!(ensure_primary(&mut db, &id).await, e, {
+        rollback!(db);
+        return (status, error_template!(e, "Error adding contact"));
+    });
+    commit!(db);
+    (
+        status,
+        Template::render("update-client-success", &HashMap::from([("id", id)])),
+    )
+}
+
+#[get("/edit/contact?<id>")]
+pub async fn get_edit_contact(mut db: Connection<BankManage>, id: String) -> Template {
+    let contact = unwrap_or_return!(query_contact(&mut db, &id).await, "Error querying contact");
+    Template::render(
+        "edit-contact",
+        ContactContext {
+            action: format!("/edit/contact?id={id}"),
+            clientID: contact.clientID.clone(),
+            contact,
+            restriction: get_restriction(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[post("/edit/contact?<id>", data = "<form>")]
+pub async fn submit_edit_contact(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, ContactSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let submission = match form.into_inner().value {
+        Some(value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error updating contact: failed to receive form"),
+            )
+        }
+    };
+    let client_id = match query_contact(&mut db, &id).await {
+        Ok(contact) => contact.clientID,
+        Err(e) => return (status, error_template!(e, "Error querying contact")),
+    };
+    let errors = submission.validate();
+    let contact = submission.into_contact(id.clone(), client_id.clone());
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "edit-contact",
+                ContactContext {
+                    action: format!("/edit/contact?id={id}"),
+                    clientID: client_id,
+                    contact,
+                    restriction: get_restriction(),
+                    errors,
+                },
+            ),
+        );
+    }
+
+    start_transaction!(db);
+    if contact.isPrimary {
+        unwrap_or!(clear_primary(&mut db, &client_id).await, e, {
+            rollback!(db);
+            return (status, error_template!(e, "Error updating contact"));
+        });
+    }
+    unwrap_or!(
+        sqlx::query(
+            "UPDATE clientcontact SET contactName=?, contactTel=?, contactEmail=?, contactRelationship=?, isPrimary=? WHERE contactID=?",
+        )
+        .bind(&contact.contactName)
+        .bind(&contact.contactTel)
+        .bind(&contact.contactEmail)
+        .bind(&contact.contactRelationship)
+        .bind(contact.isPrimary)
+        .bind(&id)
+        .execute(&mut *db)
+        .await,
+        e,
+        {
+            rollback!(db);
+            return (status, error_template!(e, "Error updating contact"));
+        }
+    );
+    unwrap_or!(ensure_primary(&mut db, &client_id).await, e, {
+        rollback!(db);
+        return (status, error_template!(e, "Error updating contact"));
+    });
+    commit!(db);
+    (
+        status,
+        Template::render("update-client-success", &HashMap::from([("id", client_id)])),
+    )
+}
+
+#[get("/delete/contact?<id>")]
+pub async fn delete_contact(mut db: Connection<BankManage>, id: String) -> Template {
+    let contact = unwrap_or_return!(query_contact(&mut db, &id).await, "Error querying contact");
+    let contacts = unwrap_or_return!(
+        query_contacts(&mut db, &contact.clientID).await,
+        "Error querying contacts of the client"
+    );
+
+    // Rule 1: a client keeps at least one contact
+    if contacts.len() <= 1 {
+        return error_template!("The only contact of a client cannot be removed");
+    }
+
+    start_transaction!(db);
+    unwrap_or!(
+        sqlx::query("DELETE FROM clientcontact WHERE contactID=?")
+            .bind(&id)
+            .execute(&mut *db)
+            .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error removing contact");
+        }
+    );
+    unwrap_or!(ensure_primary(&mut db, &contact.clientID).await, e, {
+        rollback!(db);
+        return error_template!(e, "Error removing contact");
+    });
+    commit!(db);
+    Template::render(
+        "update-client-success",
+        &HashMap::from([("id", contact.clientID)]),
+    )
+}
@@ -0,0 +1,276 @@
+use std::collections::{HashMap, HashSet};
+
+use super::preludes::rocket_prelude::*;
+use crate::account_manage::delete::delete_account_and_own;
+use crate::utility::GenericError;
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use bigdecimal::Zero;
+use chrono::Local;
+use rocket::futures::TryStreamExt;
+use sqlx::types::BigDecimal;
+use sqlx::Executor;
+
+#[derive(Serialize)]
+pub struct OwnedAccountView {
+    accountID: String,
+    balance: String,
+    owners: i64,
+    /// Zero balance and no other owner, so the account can be closed along with the client
+    closable: bool,
+}
+
+/// Rows referring to a client which keep it from being deleted
+#[derive(Serialize, Default)]
+pub struct ClientDependencies {
+    accounts: Vec<OwnedAccountView>,
+    loans: Vec<String>,
+    guarantees: Vec<String>,
+    account_management: Vec<AccountManagement>,
+}
+
+impl ClientDependencies {
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+            && self.loans.is_empty()
+            && self.guarantees.is_empty()
+            && self.account_management.is_empty()
+    }
+}
+
+async fn query_loan_ids(
+    db: &mut Connection<BankManage>,
+    table: &str,
+    client_id: &str,
+) -> Result<Vec<String>, GenericError> {
+    let mut result = vec![];
+    let statement = format!("SELECT loanID FROM {table} WHERE clientID=? ORDER BY loanID");
+    let mut rows = sqlx::query(&statement).bind(client_id).fetch(&mut **db);
+    while let Some(row) = rows.try_next().await? {
+        result.push(row.try_get::<'_, String, _>(0)?);
+    }
+    Ok(result)
+}
+
+pub async fn query_client_dependencies(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<ClientDependencies, GenericError> {
+    let mut dependencies = ClientDependencies::default();
+    {
+        let mut rows = sqlx::query(
+            "SELECT own.accountID, account.balance,
+                (SELECT COUNT(*) FROM own AS owner WHERE owner.accountID=own.accountID)
+            FROM own JOIN account ON own.accountID=account.accountID
+            WHERE own.clientID=? ORDER BY own.accountID",
+        )
+        .bind(client_id)
+        .fetch(&mut **db);
+        while let Some(row) = rows.try_next().await? {
+            let balance = row.try_get::<'_, BigDecimal, _>(1)?;
+            let owners = row.try_get::<'_, i64, _>(2)?;
+            dependencies.accounts.push(OwnedAccountView {
+                accountID: row.try_get::<'_, String, _>(0)?,
+                closable: balance.is_zero() && owners == 1,
+                balance: balance.to_string(),
+                owners,
+            });
+        }
+    }
+    dependencies.loans = query_loan_ids(db, "receiveloan", client_id).await?;
+    dependencies.guarantees = query_loan_ids(db, "guarantee", client_id).await?;
+    dependencies.account_management = sqlx::query_as!(
+        AccountManagement,
+        "SELECT * FROM accountmanagement WHERE clientID=? ORDER BY subbranchName",
+        client_id
+    )
+    .fetch_all(&mut **db)
+    .await?;
+    Ok(dependencies)
+}
+
+/// Deletes the client along with the records that only describe it.
+/// Returns the hashes of the stored files of its documents, to be removed once the deletion is committed.
+pub async fn delete_client_entity(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Vec<String>, GenericError> {
+    let document_files = crate::kyc::delete_documents(db, client_id).await?;
+    crate::client_contact::delete_contacts(db, client_id).await?;
+    for statement in [
+        "DELETE FROM clientnote WHERE clientID=?",
+        "DELETE FROM clientalias WHERE clientID=?",
+        "DELETE FROM clientarchive WHERE clientID=?",
+    ] {
+        sqlx::query(statement)
+            .bind(client_id)
+            .execute(&mut **db)
+            .await?;
+    }
+    sqlx::query("DELETE FROM creditlimit WHERE scope=? and target=?")
+        .bind(crate::credit_limit::SCOPE_CLIENT)
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("DELETE FROM client WHERE clientID=?")
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(document_files)
+}
+
+#[derive(Serialize)]
+struct DeleteClientContext {
+    client: Client,
+    dependencies: ClientDependencies,
+    closable: bool,
+    archive: Option<ArchiveView>,
+}
+
+/// Lists what keeps the client from being deleted
+pub async fn render_dependency_report(
+    db: &mut Connection<BankManage>,
+    client: Client,
+    dependencies: ClientDependencies,
+) -> Template {
+    let archive = unwrap_or_return!(
+        query_archive(db, &client.clientID).await,
+        "Error querying archive of the client"
+    );
+    Template::render(
+        "delete-client",
+        DeleteClientContext {
+            closable: dependencies.accounts.iter().any(|account| account.closable),
+            client,
+            dependencies,
+            archive: archive.map(ArchiveView::from),
+        },
+    )
+}
+
+#[get("/close/client-accounts?<id>")]
+pub async fn close_idle_accounts(mut db: Connection<BankManage>, id: String) -> Template {
+    let client = unwrap_or_return!(
+        crate::client_profile::query_client_by_id(&mut db, id.clone()).await,
+        "Error querying client"
+    );
+    let dependencies = unwrap_or_return!(
+        query_client_dependencies(&mut db, &id).await,
+        "Error querying dependencies of the client"
+    );
+
+    start_transaction!(db);
+    for account in dependencies
+        .accounts
+        .iter()
+        .filter(|account| account.closable)
+    {
+        unwrap_or!(
+            delete_account_and_own(&mut db, account.accountID.clone()).await,
+            e,
+            {
+                rollback!(db);
+                return error_template!(e, "Error closing account");
+            }
+        );
+    }
+    commit!(db);
+
+    let dependencies = unwrap_or_return!(
+        query_client_dependencies(&mut db, &id).await,
+        "Error querying dependencies of the client"
+    );
+    render_dependency_report(&mut db, client, dependencies).await
+}
+
+#[derive(Serialize)]
+pub struct ArchiveView {
+    archiveDate: String,
+    reason: Option<String>,
+}
+
+impl From<ClientArchive> for ArchiveView {
+    fn from(archive: ClientArchive) -> Self {
+        ArchiveView {
+            archiveDate: archive.archiveDate.to_string(),
+            reason: archive.reason,
+        }
+    }
+}
+
+pub async fn query_archive(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Option<ClientArchive>, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientArchive,
+        "SELECT * FROM clientarchive WHERE clientID=?",
+        client_id
+    )
+    .fetch_optional(&mut **db)
+    .await?)
+}
+
+pub async fn query_archived_client_ids(
+    db: &mut Connection<BankManage>,
+) -> Result<HashSet<String>, GenericError> {
+    let mut result = HashSet::new();
+    let mut rows = sqlx::query("SELECT clientID FROM clientarchive").fetch(&mut **db);
+    while let Some(row) = rows.try_next().await? {
+        result.insert(row.try_get::<'_, String, _>(0)?);
+    }
+    Ok(result)
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct ArchiveSubmit {
+    reason: String,
+}
+
+#[post("/archive/client?<id>", data = "<form>")]
+pub async fn archive_client(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, ArchiveSubmit>>,
+) -> Template {
+    let value = match form.value {
+        Some(ref value) => value,
+        None => return error_template!("Error archiving client: failed to receive form"),
+    };
+
+    // Rule 1: a client is archived at most once
+    if unwrap_or_return!(
+        query_archive(&mut db, &id).await,
+        "Error querying archive of the client"
+    )
+    .is_some()
+    {
+        return error_template!("The client is already archived");
+    }
+
+    match sqlx::query("INSERT INTO clientarchive (clientID, archiveDate, reason) VALUES (?, ?, ?)")
+        .bind(&id)
+        .bind(Local::now().format("%Y-%m-%d").to_string())
+        .bind(if value.reason.trim().is_empty() {
+            None
+        } else {
+            Some(value.reason.trim())
+        })
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render("update-client-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error archiving client"),
+    }
+}
+
+#[get("/restore/client?<id>")]
+pub async fn restore_client(mut db: Connection<BankManage>, id: String) -> Template {
+    match sqlx::query("DELETE FROM clientarchive WHERE clientID=?")
+        .bind(&id)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render("update-client-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error restoring client"),
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+
+use super::preludes::rocket_prelude::*;
+use crate::relationship_manager::check_employee;
+use crate::utility::GenericError;
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{error_template, unwrap_or_return};
+use chrono::Local;
+
+/// Note type -> description. Besides plain notes, interactions with the client are recorded.
+pub const NOTE_TYPES: [(&str, &str); 4] = [
+    ("note", "Note"),
+    ("call", "Call"),
+    ("visit", "Visit"),
+    ("email", "Email"),
+];
+
+fn describe_note_type(note_type: &str) -> &str {
+    NOTE_TYPES
+        .iter()
+        .find(|(name, _)| *name == note_type)
+        .map(|(_, description)| *description)
+        .unwrap_or(note_type)
+}
+
+/// Notes of a client, the latest first
+pub async fn query_notes(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Vec<ClientNote>, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientNote,
+        "SELECT * FROM clientnote WHERE clientID=? ORDER BY createdAt DESC",
+        client_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+async fn query_note(db: &mut Connection<BankManage>, id: &str) -> Result<ClientNote, GenericError> {
+    Ok(
+        sqlx::query_as!(ClientNote, "SELECT * FROM clientnote WHERE noteID=?", id)
+            .fetch_one(&mut **db)
+            .await?,
+    )
+}
+
+#[derive(Serialize)]
+pub struct NoteView {
+    noteID: String,
+    noteType: String,
+    authorID: String,
+    content: String,
+    createdAt: String,
+    updatedAt: Option<String>,
+}
+
+impl From<ClientNote> for NoteView {
+    fn from(note: ClientNote) -> Self {
+        NoteView {
+            noteType: describe_note_type(&note.noteType).to_string(),
+            noteID: note.noteID,
+            authorID: note.authorID,
+            content: note.content,
+            createdAt: note.createdAt.format("%Y-%m-%d %H:%M").to_string(),
+            updatedAt: note
+                .updatedAt
+                .map(|date| date.format("%Y-%m-%d %H:%M").to_string()),
+        }
+    }
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct NoteSubmit {
+    noteType: String,
+    authorID: String,
+    content: String,
+}
+
+impl Validate for NoteSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .one_of(
+                "noteType",
+                &self.noteType,
+                &NOTE_TYPES.map(|(name, _)| name),
+            )
+            .required("authorID", &self.authorID)
+            .required("content", &self.content)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct NoteTypeOption {
+    name: &'static str,
+    description: &'static str,
+    selected: bool,
+}
+
+#[derive(Serialize)]
+struct NoteContext<'a> {
+    /// Where the form is posted to
+    action: String,
+    clientID: String,
+    /// The note being edited, which can also be deleted
+    noteID: Option<String>,
+    value: &'a NoteSubmit,
+    note_types: Vec<NoteTypeOption>,
+    /// An existing note is being edited, so its author cannot be changed
+    editing: bool,
+    errors: FieldErrors,
+}
+
+fn render_note_form(
+    action: String,
+    client_id: String,
+    note_id: Option<String>,
+    value: &NoteSubmit,
+    errors: FieldErrors,
+) -> Template {
+    Template::render(
+        "edit-note",
+        NoteContext {
+            action,
+            clientID: client_id,
+            note_types: NOTE_TYPES
+                .iter()
+                .map(|(name, description)| NoteTypeOption {
+                    name,
+                    description,
+                    selected: *name == value.noteType,
+                })
+                .collect(),
+            editing: note_id.is_some(),
+            noteID: note_id,
+            value,
+            errors,
+        },
+    )
+}
+
+#[get("/new/note?<id>")]
+pub async fn get_new_note(id: String) -> Template {
+    render_note_form(
+        format!("/new/note?id={id}"),
+        id,
+        None,
+        &<NoteSubmit as Default>::default(),
+        FieldErrors::new(),
+    )
+}
+
+#[post("/new/note?<id>", data = "<form>")]
+pub async fn submit_new_note(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, NoteSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let submission = match form.into_inner().value {
+        Some(value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error adding note: failed to receive form"),
+            )
+        }
+    };
+    let mut errors = submission.validate();
+    if let Err(e) = check_employee(&mut db, &submission.authorID).await {
+        errors.insert("authorID".to_string(), e.to_string());
+    }
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            render_note_form(format!("/new/note?id={id}"), id, None, &submission, errors),
+        );
+    }
+
+    let note = ClientNote {
+        noteID: uuid::Uuid::new_v4().to_string(),
+        clientID: id.clone(),
+        authorID: submission.authorID,
+        noteType: submission.noteType,
+        content: submission.content.trim().to_string(),
+        createdAt: Local::now().naive_local(),
+        updatedAt: None,
+    };
+    match sqlx::query(
+        "INSERT INTO clientnote (noteID, clientID, authorID, noteType, content, createdAt) VALUES
+    (?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(&note.noteID)
+    .bind(&note.clientID)
+    .bind(&note.authorID)
+    .bind(&note.noteType)
+    .bind(&note.content)
+    .bind(note.createdAt)
+    .execute(&mut *db)
+    .await
+    {
+        Ok(_) => (
+            status,
+            Template::render("update-client-success", &HashMap::from([("id", id)])),
+        ),
+        Err(e) => (status, error_template!(e, "Error adding note")),
+    }
+}
+
+#[get("/edit/note?<id>")]
+pub async fn get_edit_note(mut db: Connection<BankManage>, id: String) -> Template {
+    let note = unwrap_or_return!(query_note(&mut db, &id).await, "Error querying note");
+    render_note_form(
+        format!("/edit/note?id={id}"),
+        note.clientID,
+        Some(note.noteID),
+        &NoteSubmit {
+            noteType: note.noteType,
+            // The editor confirms being the author
+            authorID: String::new(),
+            content: note.content,
+        },
+        FieldErrors::new(),
+    )
+}
+
+#[post("/edit/note?<id>", data = "<form>")]
+pub async fn submit_edit_note(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, NoteSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let submission = match form.into_inner().value {
+        Some(value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error updating note: failed to receive form"),
+            )
+        }
+    };
+    let note = match query_note(&mut db, &id).await {
+        Ok(note) => note,
+        Err(e) => return (status, error_template!(e, "Error querying note")),
+    };
+    let mut errors = submission.validate();
+
+    // Rule 1: a note is edited only by its author
+    if submission.authorID != note.authorID {
+        errors.insert(
+            "authorID".to_string(),
+            "Only the author can edit this note".to_string(),
+        );
+    }
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            render_note_form(
+                format!("/edit/note?id={id}"),
+                note.clientID,
+                Some(note.noteID),
+                &submission,
+                errors,
+            ),
+        );
+    }
+
+    let updated_at = Local::now().naive_local();
+    let edited = ClientNote {
+        authorID: submission.authorID,
+        noteType: submission.noteType,
+        content: submission.content.trim().to_string(),
+        updatedAt: Some(updated_at),
+        ..note
+    };
+
+    match sqlx::query("UPDATE clientnote SET noteType=?, content=?, updatedAt=? WHERE noteID=?")
+        .bind(&edited.noteType)
+        .bind(&edited.content)
+        .bind(updated_at)
+        .bind(&id)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => (
+            status,
+            Template::render(
+                "update-client-success",
+                &HashMap::from([("id", edited.clientID)]),
+            ),
+        ),
+        Err(e) => (status, error_template!(e, "Error updating note")),
+    }
+}
+
+#[derive(Debug, FromForm, Default)]
+pub struct DeleteNoteSubmit {
+    authorID: String,
+}
+
+#[post("/delete/note?<id>", data = "<form>")]
+pub async fn delete_note(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, DeleteNoteSubmit>>,
+) -> Template {
+    let value = match form.value {
+        Some(ref value) => value,
+        None => return error_template!("Error deleting note: failed to receive form"),
+    };
+    let note = unwrap_or_return!(query_note(&mut db, &id).await, "Error querying note");
+
+    // Rule 1: a note is deleted only by its author
+    if value.authorID != note.authorID {
+        return error_template!("Only the author can delete this note");
+    }
+
+    match sqlx::query("DELETE FROM clientnote WHERE noteID=?")
+        .bind(&id)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render(
+            "update-client-success",
+            &HashMap::from([("id", note.clientID)]),
+        ),
+        Err(e) => error_template!(e, "Error deleting note"),
+    }
+}
@@ -0,0 +1,202 @@
+use super::preludes::rocket_prelude::*;
+use crate::account_manage::query::{query_account_by_id, SpecificAccount};
+use crate::loan_profile::{query_loan, LoanStatus};
+use crate::loan_write_off::{query_recoveries, query_write_off};
+use crate::utility::GenericError;
+use chrono::NaiveDate;
+use rocket::futures::TryStreamExt;
+use sqlx::types::BigDecimal;
+use std::collections::HashSet;
+
+/// Number of entries listed under recent activity
+const RECENT_ACTIVITY_COUNT: usize = 10;
+
+#[derive(Serialize)]
+pub struct AccountSummary {
+    accountID: String,
+    account_type: String,
+    balance: String,
+    currency: Option<String>,
+    subbranch: String,
+}
+
+#[derive(Serialize)]
+pub struct LoanSummary {
+    loanID: String,
+    subbranch: String,
+    amount: String,
+    status: String,
+    paid: String,
+    unpaid: String,
+}
+
+#[derive(Serialize)]
+pub struct ActivityView {
+    date: String,
+    description: String,
+    /// Profile page of the account or loan involved
+    link: String,
+}
+
+/// Everything the client holds with the bank
+#[derive(Serialize, Default)]
+pub struct Portfolio {
+    accounts: Vec<AccountSummary>,
+    loans: Vec<LoanSummary>,
+    /// Only when the saving accounts are all in the same currency
+    total_deposits: Option<String>,
+    /// Outstanding amount of the loans still on the books, the same as the exposure
+    total_debt: String,
+    net_position: Option<String>,
+    recent_activity: Vec<ActivityView>,
+}
+
+async fn query_ids(
+    db: &mut Connection<BankManage>,
+    statement: &str,
+    client_id: &str,
+) -> Result<Vec<String>, GenericError> {
+    let mut result = vec![];
+    let mut rows = sqlx::query(statement).bind(client_id).fetch(&mut **db);
+    while let Some(row) = rows.try_next().await? {
+        result.push(row.try_get::<'_, String, _>(0)?);
+    }
+    Ok(result)
+}
+
+pub async fn query_portfolio(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Portfolio, GenericError> {
+    let mut portfolio = Portfolio::default();
+    let mut activity: Vec<(NaiveDate, ActivityView)> = vec![];
+    let mut total_deposits = BigDecimal::default();
+    let mut currencies = HashSet::new();
+    let mut total_debt = BigDecimal::default();
+
+    let account_ids = query_ids(
+        db,
+        "SELECT accountID FROM own WHERE clientID=? ORDER BY accountID",
+        client_id,
+    )
+    .await?;
+    for account_id in account_ids {
+        let (specific_account, subbranch) = query_account_by_id(db, &account_id).await?;
+        let (account_type, currency) = match specific_account {
+            SpecificAccount::SavingAccount(ref saving_account) => {
+                ("saving account", Some(saving_account.currencyType.clone()))
+            }
+            SpecificAccount::CheckingAccount(_) => ("checking account", None),
+        };
+        let account = Account::from(specific_account);
+        total_deposits += &account.balance;
+        currencies.extend(currency.clone());
+        activity.push((
+            account.openDate,
+            ActivityView {
+                date: account.openDate.to_string(),
+                description: format!("Opened {account_type} {account_id}"),
+                link: format!("/profile/account?id={account_id}"),
+            },
+        ));
+        portfolio.accounts.push(AccountSummary {
+            accountID: account.accountID,
+            account_type: account_type.to_string(),
+            balance: account.balance.to_string(),
+            currency,
+            subbranch,
+        });
+    }
+
+    let loan_ids = query_ids(
+        db,
+        "SELECT loanID FROM receiveloan WHERE clientID=? ORDER BY loanID",
+        client_id,
+    )
+    .await?;
+    for loan_id in loan_ids {
+        let (loan, _, payments) = query_loan(db, &loan_id).await?;
+        let write_off = query_write_off(db, &loan_id).await?;
+        let paid: BigDecimal = payments.iter().map(|payment| &payment.amount).sum();
+        let unpaid = &loan.amount - &paid;
+        let status = LoanStatus::new(&paid, &loan.amount)?.with_write_off(&write_off);
+        if write_off.is_none() {
+            total_debt += &unpaid;
+        }
+
+        let link = format!("/profile/loan?id={loan_id}");
+        for agreement in crate::loan_agreement::query_agreements(db, &loan_id).await? {
+            activity.push((
+                agreement.effectiveDate,
+                ActivityView {
+                    date: agreement.effectiveDate.to_string(),
+                    description: if agreement.version == 1 {
+                        format!("Received loan {loan_id} of {}", agreement.amount)
+                    } else {
+                        format!("Restructured loan {loan_id}")
+                    },
+                    link: link.clone(),
+                },
+            ));
+        }
+        for payment in payments {
+            activity.push((
+                payment.date,
+                ActivityView {
+                    date: payment.date.to_string(),
+                    description: match payment.reversalOf {
+                        Some(_) => format!(
+                            "Reversed a payment of {} on loan {loan_id}",
+                            -payment.amount
+                        ),
+                        None => format!("Paid {} on loan {loan_id}", payment.amount),
+                    },
+                    link: link.clone(),
+                },
+            ));
+        }
+        if let Some(ref write_off) = write_off {
+            activity.push((
+                write_off.date,
+                ActivityView {
+                    date: write_off.date.to_string(),
+                    description: format!("Loan {loan_id} written off ({})", write_off.amount),
+                    link: link.clone(),
+                },
+            ));
+            for recovery in query_recoveries(db, &loan_id).await? {
+                activity.push((
+                    recovery.date,
+                    ActivityView {
+                        date: recovery.date.to_string(),
+                        description: format!("Recovered {} on loan {loan_id}", recovery.amount),
+                        link: link.clone(),
+                    },
+                ));
+            }
+        }
+
+        portfolio.loans.push(LoanSummary {
+            loanID: loan.loanID,
+            subbranch: loan.subbranchName,
+            amount: loan.amount.to_string(),
+            status: status.to_string(),
+            paid: paid.to_string(),
+            unpaid: unpaid.to_string(),
+        });
+    }
+
+    // The latest first
+    activity.sort_by(|a, b| b.0.cmp(&a.0));
+    portfolio.recent_activity = activity
+        .into_iter()
+        .take(RECENT_ACTIVITY_COUNT)
+        .map(|(_, view)| view)
+        .collect();
+    if currencies.len() <= 1 {
+        portfolio.net_position = Some((&total_deposits - &total_debt).to_string());
+        portfolio.total_deposits = Some(total_deposits.to_string());
+    }
+    portfolio.total_debt = total_debt.to_string();
+    Ok(portfolio)
+}
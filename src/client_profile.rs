@@ -1,13 +1,32 @@
 use super::preludes::rocket_prelude::*;
-use crate::{error_template, utility::GenericError};
-use rocket::futures::TryStreamExt;
-use std::vec;
+use crate::{
+    client_contact::query_contacts,
+    client_dependency::{query_archive, ArchiveView},
+    client_note::{query_notes, NoteView},
+    client_portfolio::{query_portfolio, Portfolio},
+    credit_limit::{query_applicable_limit, query_exposure},
+    error_template,
+    kyc::{query_documents, DocumentView, KycStatus},
+    merge_client::{query_aliases, resolve_client_alias},
+    service_type::derive_service_type,
+    utility::GenericError,
+};
 
 #[derive(Serialize)]
 pub struct ClientProfileContext {
     client: Client,
-    accounts: Vec<String>,
-    loans: Vec<String>,
+    service_type: String,
+    portfolio: Portfolio,
+    exposure: String,
+    shared_exposure: String,
+    credit_limit: Option<String>,
+    aliases: Vec<String>,
+    archive: Option<ArchiveView>,
+    kyc_status: String,
+    kyc_verified: bool,
+    documents: Vec<DocumentView>,
+    contacts: Vec<ClientContact>,
+    notes: Vec<NoteView>,
 }
 
 pub async fn query_client_by_id(
@@ -21,51 +40,81 @@ pub async fn query_client_by_id(
     )
 }
 
-async fn query_associated_accounts(
-    db: &mut Connection<BankManage>,
-    id: String,
-) -> Result<Vec<String>, GenericError> {
-    let mut result = vec![];
-    let mut rows = sqlx::query("SELECT accountID FROM own WHERE clientID=?")
-        .bind(id)
-        .fetch(&mut **db);
-    while let Some(row) = rows.try_next().await? {
-        result.push(row.try_get::<'_, String, _>(0)?);
-    }
-    Ok(result)
-}
-
-async fn query_associated_loans(
-    db: &mut Connection<BankManage>,
-    id: String,
-) -> Result<Vec<String>, GenericError> {
-    let mut result = vec![];
-    let mut rows = sqlx::query("SELECT loanID FROM receiveLoan WHERE clientID=?")
-        .bind(id)
-        .fetch(&mut **db);
-    while let Some(row) = rows.try_next().await? {
-        result.push(row.try_get::<'_, String, _>(0)?);
-    }
-    Ok(result)
-}
-
 #[get("/profile/client?<id>")]
 pub async fn client_profile(mut db: Connection<BankManage>, id: String) -> Template {
-    let accounts = query_associated_accounts(&mut db, id.clone())
+    let id = match resolve_client_alias(&mut db, id).await {
+        Ok(id) => id,
+        Err(e) => return error_template!(e, "Error querying client"),
+    };
+    let portfolio = match query_portfolio(&mut db, &id).await {
+        Ok(portfolio) => portfolio,
+        Err(e) => return error_template!(e, "Error querying portfolio of the client"),
+    };
+    let exposure = query_exposure(&mut db, &id, None).await.unwrap_or_default();
+    let aliases = query_aliases(&mut db, &id)
         .await
-        .unwrap_or_default();
-    let loans = query_associated_loans(&mut db, id.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|alias| alias.aliasID)
+        .collect();
+    let archive = query_archive(&mut db, &id)
+        .await
+        .unwrap_or_default()
+        .map(ArchiveView::from);
+    let today = chrono::Local::today().naive_local();
+    let documents = query_documents(&mut db, &id).await.unwrap_or_default();
+    let kyc_status = KycStatus::new(&documents, &today);
+    let contacts = query_contacts(&mut db, &id).await.unwrap_or_default();
+    let notes = query_notes(&mut db, &id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(NoteView::from)
+        .collect();
+    let service_type = derive_service_type(&mut db, &id)
         .await
+        .map(|service_type| service_type.to_string())
         .unwrap_or_default();
     match query_client_by_id(&mut db, id.clone()).await {
-        Ok(client) => Template::render(
-            "client-profile",
-            &ClientProfileContext {
-                client,
-                loans,
-                accounts,
-            },
-        ),
+        Ok(client) => {
+            let credit_limit = query_applicable_limit(&mut db, &client)
+                .await
+                .unwrap_or_default()
+                .map(|limit| {
+                    format!(
+                        "{} ({}{} {})",
+                        limit.amount,
+                        if limit.sharedAmongBorrowers {
+                            "shared, "
+                        } else {
+                            ""
+                        },
+                        limit.scope,
+                        limit.target
+                    )
+                });
+            Template::render(
+                "client-profile",
+                &ClientProfileContext {
+                    client,
+                    service_type,
+                    portfolio,
+                    exposure: exposure.total.to_string(),
+                    shared_exposure: exposure.shared.to_string(),
+                    credit_limit,
+                    aliases,
+                    archive,
+                    kyc_verified: matches!(kyc_status, KycStatus::Verified),
+                    kyc_status: kyc_status.to_string(),
+                    documents: documents
+                        .into_iter()
+                        .map(|document| DocumentView::new(document, &today))
+                        .collect(),
+                    contacts,
+                    notes,
+                },
+            )
+        }
         Err(e) => error_template!(e, "Error querying client"),
     }
 }
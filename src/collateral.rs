@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+
+use super::loan_profile::LoanStatus;
+use super::preludes::rocket_prelude::*;
+use crate::utility::{get_restriction, validate_client_id, GenericError, Restriction};
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{error_template, unwrap_or_return};
+use chrono::{Local, NaiveDate};
+use sqlx::types::BigDecimal;
+
+#[derive(Serialize)]
+struct NewCollateralContext<'a> {
+    id: String,
+    restriction: Restriction,
+    value: &'a CollateralSubmit,
+    errors: FieldErrors,
+}
+
+#[get("/new/collateral?<id>")]
+pub async fn get_new_collateral(id: String) -> Template {
+    Template::render(
+        "new-collateral",
+        NewCollateralContext {
+            id,
+            restriction: get_restriction(),
+            value: &<CollateralSubmit as Default>::default(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct CollateralSubmit {
+    collateralType: String,
+    description: String,
+    appraisedValue: String,
+    appraisalDate: String,
+}
+
+/// The appraisal should not be in the future
+fn parse_appraisal_date(input: &str) -> Result<NaiveDate, String> {
+    let date = NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .map_err(|_| "Invalid format of date".to_string())?;
+    if date > Local::today().naive_local() {
+        return Err("The appraisal date should not be in the future".to_string());
+    }
+    Ok(date)
+}
+
+impl Validate for CollateralSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("collateralType", &self.collateralType)
+            .required("appraisedValue", &self.appraisedValue)
+            .pattern("appraisedValue", &self.appraisedValue, "amount")
+            .required("appraisalDate", &self.appraisalDate)
+            .check("appraisalDate", parse_appraisal_date(&self.appraisalDate))
+            .finish()
+    }
+}
+
+#[post("/new/collateral?<id>", data = "<form>")]
+pub async fn submit_collateral(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, CollateralSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error adding collateral: failed to receive form"),
+            )
+        }
+    };
+    let errors = value.validate();
+    let (appraised_value, appraisal_date) = match (
+        value.appraisedValue.parse::<BigDecimal>(),
+        parse_appraisal_date(&value.appraisalDate),
+    ) {
+        (Ok(appraised_value), Ok(appraisal_date)) if errors.is_empty() => {
+            (appraised_value, appraisal_date)
+        }
+        _ => {
+            return (
+                Status::UnprocessableEntity,
+                Template::render(
+                    "new-collateral",
+                    NewCollateralContext {
+                        id,
+                        restriction: get_restriction(),
+                        value,
+                        errors,
+                    },
+                ),
+            )
+        }
+    };
+
+    let template = match sqlx::query(
+        "INSERT INTO collateral (collateralID, loanID, collateralType, description, appraisedValue, appraisalDate) VALUES
+    (?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&id)
+    .bind(value.collateralType.trim())
+    .bind(if value.description.is_empty() {
+        None
+    } else {
+        Some(&value.description)
+    })
+    .bind(&appraised_value)
+    .bind(appraisal_date)
+    .execute(&mut *db)
+    .await
+    {
+        Ok(_) => Template::render("update-loan-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error adding collateral"),
+    };
+    (status, template)
+}
+
+#[get("/release/collateral?<id>")]
+pub async fn release_collateral(mut db: Connection<BankManage>, id: String) -> Template {
+    let collateral = unwrap_or_return!(
+        sqlx::query_as!(
+            Collateral,
+            "SELECT * FROM collateral WHERE collateralID=?",
+            id
+        )
+        .fetch_one(&mut *db)
+        .await,
+        "Error querying collateral"
+    );
+    if collateral.releaseDate.is_some() {
+        return error_template!("The collateral is already released");
+    }
+    let (loan, _, associated_payments) = unwrap_or_return!(
+        super::loan_profile::query_loan(&mut db, &collateral.loanID).await,
+        "Error querying loan"
+    );
+    let write_off = unwrap_or_return!(
+        super::loan_write_off::query_write_off(&mut db, &loan.loanID).await,
+        "Error querying write-off of the loan"
+    );
+    let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+    let status = unwrap_or_return!(
+        LoanStatus::new(&pay_amount, &loan.amount),
+        "Unexpected: Aggregated payment is more than the loaded amount"
+    )
+    .with_write_off(&write_off);
+    if !matches!(status, LoanStatus::Paid) {
+        return error_template!(format!(
+            "The loan is {status}, collateral can only be released once it is paid"
+        ));
+    }
+    match sqlx::query("UPDATE collateral SET releaseDate=? WHERE collateralID=?")
+        .bind(Local::now().format("%Y-%m-%d").to_string())
+        .bind(&id)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render(
+            "update-loan-success",
+            &HashMap::from([("id", collateral.loanID)]),
+        ),
+        Err(e) => error_template!(e, "Error releasing collateral"),
+    }
+}
+
+#[derive(Serialize)]
+struct NewGuarantorContext<'a> {
+    id: String,
+    restriction: Restriction,
+    value: &'a GuarantorSubmit,
+    errors: FieldErrors,
+}
+
+#[get("/new/guarantor?<id>")]
+pub async fn get_new_guarantor(id: String) -> Template {
+    Template::render(
+        "new-guarantor",
+        NewGuarantorContext {
+            id,
+            restriction: get_restriction(),
+            value: &<GuarantorSubmit as Default>::default(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct GuarantorSubmit {
+    clientID: String,
+}
+
+impl Validate for GuarantorSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("clientID", &self.clientID)
+            .check("clientID", validate_client_id(&self.clientID))
+            .finish()
+    }
+}
+
+#[post("/new/guarantor?<id>", data = "<form>")]
+pub async fn submit_guarantor(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, GuarantorSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error adding guarantor: failed to receive form"),
+            )
+        }
+    };
+    let mut errors = value.validate();
+    let (_, associated_clients, _) = match super::loan_profile::query_loan(&mut db, &id).await {
+        Ok(loan) => loan,
+        Err(e) => return (status, error_template!(e, "Error querying loan")),
+    };
+
+    // Rule 1: a borrower cannot guarantee its own loan
+    let client_id = validate_client_id(&value.clientID).ok();
+    if let Some(client_id) = &client_id {
+        if associated_clients
+            .iter()
+            .any(|receive_loan| &receive_loan.clientID == client_id)
+        {
+            errors.insert(
+                "clientID".to_string(),
+                "A borrower of the loan cannot be its guarantor".to_string(),
+            );
+        }
+    }
+    let client_id = match client_id {
+        Some(client_id) if errors.is_empty() => client_id,
+        _ => {
+            return (
+                Status::UnprocessableEntity,
+                Template::render(
+                    "new-guarantor",
+                    NewGuarantorContext {
+                        id,
+                        restriction: get_restriction(),
+                        value,
+                        errors,
+                    },
+                ),
+            )
+        }
+    };
+
+    let template = match sqlx::query("INSERT INTO guarantee (loanID, clientID) VALUES (?, ?)")
+        .bind(&id)
+        .bind(&client_id)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render("update-loan-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error adding guarantor"),
+    };
+    (status, template)
+}
+
+#[get("/delete/guarantor?<id>&<client>")]
+pub async fn delete_guarantor(
+    mut db: Connection<BankManage>,
+    id: String,
+    client: String,
+) -> Template {
+    match sqlx::query("DELETE FROM guarantee WHERE loanID=? and clientID=?")
+        .bind(&id)
+        .bind(&client)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => Template::render("update-loan-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error removing guarantor"),
+    }
+}
+
+pub async fn query_collaterals(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+) -> Result<Vec<Collateral>, GenericError> {
+    Ok(sqlx::query_as!(
+        Collateral,
+        "SELECT * FROM collateral WHERE loanID=? ORDER BY appraisalDate",
+        loan_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+pub async fn query_guarantors(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+) -> Result<Vec<Guarantee>, GenericError> {
+    Ok(
+        sqlx::query_as!(Guarantee, "SELECT * FROM guarantee WHERE loanID=?", loan_id)
+            .fetch_all(&mut **db)
+            .await?,
+    )
+}
+
+/// Outstanding amount over the appraised value of the collateral still held, in percent.
+/// Returns `None` if no collateral is held.
+pub fn loan_to_value(outstanding: &BigDecimal, collaterals: &[Collateral]) -> Option<BigDecimal> {
+    let value: BigDecimal = collaterals
+        .iter()
+        .filter(|collateral| collateral.releaseDate.is_none())
+        .map(|collateral| &collateral.appraisedValue)
+        .sum();
+    if value == BigDecimal::default() {
+        None
+    } else {
+        Some((outstanding * BigDecimal::from(100) / value).with_scale(2))
+    }
+}
+
+pub async fn delete_collaterals_and_guarantors(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+) -> Result<(), GenericError> {
+    sqlx::query("DELETE FROM collateral WHERE loanID=?")
+        .bind(loan_id)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("DELETE FROM guarantee WHERE loanID=?")
+        .bind(loan_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(())
+}
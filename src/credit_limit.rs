@@ -0,0 +1,297 @@
+use super::preludes::rocket_prelude::*;
+use crate::service_type::ServiceType;
+use crate::utility::{get_restriction, validate_client_id, GenericError, Restriction};
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{error_template, unwrap_or_return};
+use rocket::futures::TryStreamExt;
+use sqlx::types::BigDecimal;
+
+pub const SCOPE_CLIENT: &str = "client";
+pub const SCOPE_SERVICE_TYPE: &str = "serviceType";
+
+/// Outstanding principal of the loans a client receives. Written-off loans are off the books and not counted.
+#[derive(Debug, Default)]
+pub struct Exposure {
+    /// Every co-borrower is exposed to the whole outstanding amount
+    pub total: BigDecimal,
+    /// The outstanding amount is split evenly among co-borrowers
+    pub shared: BigDecimal,
+}
+
+impl Exposure {
+    pub fn under(&self, limit: &CreditLimit) -> &BigDecimal {
+        if limit.sharedAmongBorrowers {
+            &self.shared
+        } else {
+            &self.total
+        }
+    }
+}
+
+pub async fn query_exposure(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+    excluded_loan: Option<&str>,
+) -> Result<Exposure, GenericError> {
+    let mut exposure = Exposure::default();
+    let mut rows = sqlx::query(
+        "SELECT loan.loanID, loan.amount,
+            (SELECT COALESCE(SUM(payment.amount), 0) FROM payment WHERE payment.loanID=loan.loanID),
+            (SELECT COUNT(*) FROM receiveloan AS borrower WHERE borrower.loanID=loan.loanID)
+        FROM loan JOIN receiveloan ON loan.loanID=receiveloan.loanID
+        WHERE receiveloan.clientID=?
+            and loan.loanID NOT IN (SELECT loanID FROM loanwriteoff)",
+    )
+    .bind(client_id)
+    .fetch(&mut **db);
+    while let Some(row) = rows.try_next().await? {
+        if Some(row.try_get::<'_, String, _>(0)?.as_str()) == excluded_loan {
+            continue;
+        }
+        let outstanding =
+            row.try_get::<'_, BigDecimal, _>(1)? - row.try_get::<'_, BigDecimal, _>(2)?;
+        let borrowers = row.try_get::<'_, i64, _>(3)?.max(1);
+        exposure.shared += &outstanding / BigDecimal::from(borrowers);
+        exposure.total += outstanding;
+    }
+    Ok(exposure)
+}
+
+/// A limit set on the client itself takes precedence over the one of its service type
+pub async fn query_applicable_limit(
+    db: &mut Connection<BankManage>,
+    client: &Client,
+) -> Result<Option<CreditLimit>, GenericError> {
+    let client_limit = sqlx::query_as!(
+        CreditLimit,
+        "SELECT * FROM creditlimit WHERE scope=? and target=?",
+        SCOPE_CLIENT,
+        client.clientID
+    )
+    .fetch_optional(&mut **db)
+    .await?;
+    if client_limit.is_some() {
+        return Ok(client_limit);
+    }
+    // A client using both services falls back to the limit of loan service
+    let candidates = match ServiceType::parse(client.serviceType.as_deref())? {
+        ServiceType::None => vec![],
+        ServiceType::Both => vec![ServiceType::Both, ServiceType::Loan],
+        service_type => vec![service_type],
+    };
+    for service_type in candidates {
+        let limit = sqlx::query_as!(
+            CreditLimit,
+            "SELECT * FROM creditlimit WHERE scope=? and target=?",
+            SCOPE_SERVICE_TYPE,
+            service_type.as_str()
+        )
+        .fetch_optional(&mut **db)
+        .await?;
+        if limit.is_some() {
+            return Ok(limit);
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug)]
+pub struct ExposureLimitError {
+    pub info: String,
+}
+
+impl std::fmt::Display for ExposureLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for ExposureLimitError {}
+
+/// Checks that lending `outstanding` to `client_ids` jointly keeps each of them within its limit.
+/// `excluded_loan` is left out of the current exposure, so that a loan being restructured is not counted twice.
+pub async fn check_exposure(
+    db: &mut Connection<BankManage>,
+    client_ids: &[String],
+    outstanding: &BigDecimal,
+    excluded_loan: Option<&str>,
+) -> Result<(), GenericError> {
+    for client_id in client_ids {
+        let client = crate::client_profile::query_client_by_id(db, client_id.clone()).await?;
+        let limit = match query_applicable_limit(db, &client).await? {
+            Some(limit) => limit,
+            None => continue,
+        };
+        let exposure = query_exposure(db, client_id, excluded_loan).await?;
+        let new_exposure = exposure.under(&limit)
+            + if limit.sharedAmongBorrowers {
+                outstanding / BigDecimal::from(client_ids.len() as i64)
+            } else {
+                outstanding.clone()
+            };
+        if new_exposure > limit.amount {
+            return Err(Box::new(ExposureLimitError {
+                info: format!(
+                    "The exposure of client {client_id} would be {new_exposure} (currently {current}), exceeding the limit {limit_amount} set by {scope} {target}",
+                    current = exposure.under(&limit),
+                    limit_amount = limit.amount,
+                    scope = limit.scope,
+                    target = limit.target
+                ),
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct CreditLimitView {
+    scope: String,
+    target: String,
+    amount: String,
+    sharedAmongBorrowers: bool,
+}
+
+#[derive(Serialize)]
+struct CreditLimitsContext<'a> {
+    limits: Vec<CreditLimitView>,
+    restriction: Restriction,
+    value: &'a Submit,
+    errors: FieldErrors,
+}
+
+async fn render_limits(
+    db: &mut Connection<BankManage>,
+    value: &Submit,
+    errors: FieldErrors,
+) -> Template {
+    let limits = unwrap_or_return!(
+        sqlx::query_as!(
+            CreditLimit,
+            "SELECT * FROM creditlimit ORDER BY scope, target"
+        )
+        .fetch_all(&mut **db)
+        .await,
+        "Error querying credit limits"
+    );
+    Template::render(
+        "credit-limits",
+        CreditLimitsContext {
+            limits: limits
+                .into_iter()
+                .map(|limit| CreditLimitView {
+                    scope: limit.scope,
+                    target: limit.target,
+                    amount: limit.amount.to_string(),
+                    sharedAmongBorrowers: limit.sharedAmongBorrowers,
+                })
+                .collect(),
+            restriction: get_restriction(),
+            value,
+            errors,
+        },
+    )
+}
+
+#[get("/limits")]
+pub async fn credit_limits(mut db: Connection<BankManage>) -> Template {
+    render_limits(&mut db, &<Submit as Default>::default(), FieldErrors::new()).await
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct Submit {
+    scope: String,
+    target: String,
+    amount: String,
+    sharedAmongBorrowers: bool,
+}
+
+impl Submit {
+    /// The normalized client ID or service type the limit is set on
+    fn parse_target(&self) -> Result<String, String> {
+        if self.scope == SCOPE_CLIENT {
+            validate_client_id(&self.target).map_err(|e| e.to_string())
+        } else {
+            match ServiceType::parse(Some(self.target.trim())) {
+                Ok(ServiceType::None) => {
+                    Err("A credit limit cannot be set on clients using no service".to_string())
+                }
+                Ok(service_type) => Ok(service_type.as_str().to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        }
+    }
+}
+
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .one_of("scope", &self.scope, &[SCOPE_CLIENT, SCOPE_SERVICE_TYPE])
+            .required("target", &self.target)
+            .check("target", self.parse_target())
+            .required("amount", &self.amount)
+            .pattern("amount", &self.amount, "amount")
+            .finish()
+    }
+}
+
+#[post("/limits", data = "<form>")]
+pub async fn submit(
+    mut db: Connection<BankManage>,
+    form: Form<Contextual<'_, Submit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error setting credit limit: failed to receive form"),
+            )
+        }
+    };
+    let errors = value.validate();
+    let (target, amount) = match (value.parse_target(), value.amount.parse::<BigDecimal>()) {
+        (Ok(target), Ok(amount)) if errors.is_empty() => (target, amount),
+        _ => {
+            return (
+                Status::UnprocessableEntity,
+                render_limits(&mut db, value, errors).await,
+            )
+        }
+    };
+    match sqlx::query(
+        "INSERT INTO creditlimit (scope, target, amount, sharedAmongBorrowers) VALUES (?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE amount=VALUES(amount), sharedAmongBorrowers=VALUES(sharedAmongBorrowers)",
+    )
+    .bind(&value.scope)
+    .bind(&target)
+    .bind(&amount)
+    .bind(value.sharedAmongBorrowers)
+    .execute(&mut *db)
+    .await
+    {
+        Ok(_) => (
+            status,
+            render_limits(&mut db, &<Submit as Default>::default(), FieldErrors::new()).await,
+        ),
+        Err(e) => (status, error_template!(e, "Error setting credit limit")),
+    }
+}
+
+#[get("/delete/limit?<scope>&<target>")]
+pub async fn delete_limit(
+    mut db: Connection<BankManage>,
+    scope: String,
+    target: String,
+) -> Template {
+    match sqlx::query("DELETE FROM creditlimit WHERE scope=? and target=?")
+        .bind(&scope)
+        .bind(&target)
+        .execute(&mut *db)
+        .await
+    {
+        Ok(_) => render_limits(&mut db, &<Submit as Default>::default(), FieldErrors::new()).await,
+        Err(e) => error_template!(e, "Error deleting credit limit"),
+    }
+}
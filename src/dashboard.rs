@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bigdecimal::{ToPrimitive, Zero};
+use chrono::NaiveDate;
+use rocket::futures::TryStreamExt;
+use rocket::http::RawStr;
+use sqlx::types::BigDecimal;
+
+use super::preludes::rocket_prelude::*;
+use crate::account_manage::movement::MovementKind;
+use crate::reporting_period::{Granularity, GranularityOption, ReportingPeriod};
+use crate::subbranch_manage::{get_balances, get_statistics};
+use crate::unwrap_or_return;
+use crate::utility::GenericError;
+
+/// Figures compared between the latest period and the one before, in the order of `Figures::periods`
+const METRICS: [&str; 5] = [
+    "Balance at the end",
+    "Deposits",
+    "New accounts",
+    "Loans disbursed",
+    "Loan repayments",
+];
+
+/// Current figures of one or several subbranches, along with the metrics of each period
+#[derive(Debug, Clone)]
+struct Figures {
+    asset: BigDecimal,
+    accounts: i64,
+    balance: BigDecimal,
+    loans: i64,
+    outstanding: BigDecimal,
+    /// Metric -> value of each period, the earliest first
+    periods: Vec<Vec<BigDecimal>>,
+}
+
+impl Figures {
+    fn new(period_count: usize) -> Self {
+        Figures {
+            asset: BigDecimal::zero(),
+            accounts: 0,
+            balance: BigDecimal::zero(),
+            loans: 0,
+            outstanding: BigDecimal::zero(),
+            periods: vec![vec![BigDecimal::zero(); period_count]; METRICS.len()],
+        }
+    }
+
+    fn add(&mut self, other: &Figures) {
+        self.asset += &other.asset;
+        self.accounts += other.accounts;
+        self.balance += &other.balance;
+        self.loans += other.loans;
+        self.outstanding += &other.outstanding;
+        for (values, other_values) in self.periods.iter_mut().zip(&other.periods) {
+            for (value, other_value) in values.iter_mut().zip(other_values) {
+                *value += other_value;
+            }
+        }
+    }
+}
+
+/// Everything dated that the metrics are made of, by subbranch
+#[derive(Default)]
+struct History {
+    movements: HashMap<String, Vec<AccountMovement>>,
+    disbursements: HashMap<String, Vec<(BigDecimal, NaiveDate)>>,
+    repayments: HashMap<String, Vec<(BigDecimal, NaiveDate)>>,
+}
+
+async fn query_history(db: &mut Connection<BankManage>) -> Result<History, GenericError> {
+    let mut history = History::default();
+    for movement in sqlx::query_as!(
+        AccountMovement,
+        "SELECT * FROM accountmovement ORDER BY date"
+    )
+    .fetch_all(&mut **db)
+    .await?
+    {
+        history
+            .movements
+            .entry(movement.subbranchName.clone())
+            .or_default()
+            .push(movement);
+    }
+
+    // The first version of the agreement is made when the loan is granted
+    for (statement, entries) in [
+        (
+            "SELECT loan.subbranchName, loanagreement.amount, loanagreement.effectiveDate
+            FROM loanagreement JOIN loan ON loan.loanID=loanagreement.loanID
+            WHERE loanagreement.version=1",
+            &mut history.disbursements,
+        ),
+        (
+            "SELECT loan.subbranchName, payment.amount, payment.date
+            FROM payment JOIN loan ON loan.loanID=payment.loanID",
+            &mut history.repayments,
+        ),
+    ] {
+        let mut rows = sqlx::query(statement).fetch(&mut **db);
+        while let Some(row) = rows.try_next().await? {
+            entries
+                .entry(row.try_get::<'_, String, _>(0)?)
+                .or_default()
+                .push((
+                    row.try_get::<'_, BigDecimal, _>(1)?,
+                    row.try_get::<'_, NaiveDate, _>(2)?,
+                ));
+        }
+    }
+    Ok(history)
+}
+
+/// Subbranch -> (number, total), given a statement grouping by subbranch
+async fn query_totals(
+    db: &mut Connection<BankManage>,
+    statement: &str,
+) -> Result<HashMap<String, (i64, BigDecimal)>, GenericError> {
+    let mut totals = HashMap::new();
+    let mut rows = sqlx::query(statement).fetch(&mut **db);
+    while let Some(row) = rows.try_next().await? {
+        totals.insert(
+            row.try_get::<'_, String, _>(0)?,
+            (
+                row.try_get::<'_, i64, _>(1)?,
+                row.try_get::<'_, BigDecimal, _>(2)?,
+            ),
+        );
+    }
+    Ok(totals)
+}
+
+/// Values of each metric in `METRICS` of the subbranch
+fn period_values(
+    reporting_period: &ReportingPeriod,
+    history: &History,
+    subbranch: &str,
+) -> Vec<Vec<BigDecimal>> {
+    let no_movement = vec![];
+    let movements = history.movements.get(subbranch).unwrap_or(&no_movement);
+    let no_entry = vec![];
+    let tuple_add_assign = |left: &mut (BigDecimal, u32), right: &(BigDecimal, u32)| {
+        left.0 += &right.0;
+        left.1 += &right.1;
+    };
+    let flows = |entries: &Vec<(BigDecimal, NaiveDate)>| -> Vec<BigDecimal> {
+        get_statistics(
+            entries
+                .iter()
+                .map(|(amount, date)| ((amount.clone(), 1), *date)),
+            reporting_period,
+            tuple_add_assign,
+        )
+        .into_iter()
+        .map(|(amount, _)| amount)
+        .collect()
+    };
+
+    let balances = get_balances(movements.iter(), reporting_period)
+        .into_iter()
+        .map(|(balance, _)| balance)
+        .collect();
+    let deposits = flows(
+        &movements
+            .iter()
+            .filter(|movement| movement.amount > BigDecimal::zero())
+            .map(|movement| (movement.amount.clone(), movement.date))
+            .collect(),
+    );
+    let new_accounts = get_statistics(
+        movements
+            .iter()
+            .filter(|movement| movement.kind == MovementKind::Open.as_str())
+            .map(|movement| ((BigDecimal::zero(), 1), movement.date)),
+        reporting_period,
+        tuple_add_assign,
+    )
+    .into_iter()
+    .map(|(_, count)| BigDecimal::from(count))
+    .collect();
+    let disbursements = flows(history.disbursements.get(subbranch).unwrap_or(&no_entry));
+    let repayments = flows(history.repayments.get(subbranch).unwrap_or(&no_entry));
+    vec![balances, deposits, new_accounts, disbursements, repayments]
+}
+
+#[derive(Serialize)]
+struct Comparison {
+    description: &'static str,
+    latest: String,
+    previous: Option<String>,
+    /// Relative change from the previous period, if there is a previous period with a non-zero value
+    change: Option<String>,
+    increased: bool,
+}
+
+#[derive(Serialize)]
+struct DashboardRow {
+    name: String,
+    href: Option<String>,
+    asset: String,
+    accounts: String,
+    balance: String,
+    loans: String,
+    outstanding: String,
+    comparisons: Vec<Comparison>,
+}
+
+impl DashboardRow {
+    fn new(name: String, href: Option<String>, figures: &Figures) -> Self {
+        DashboardRow {
+            name,
+            href,
+            asset: figures.asset.to_string(),
+            accounts: figures.accounts.to_string(),
+            balance: figures.balance.to_string(),
+            loans: figures.loans.to_string(),
+            outstanding: figures.outstanding.to_string(),
+            comparisons: METRICS
+                .iter()
+                .zip(&figures.periods)
+                .map(|(description, values)| {
+                    // A reporting period has at least one period
+                    let latest = &values[values.len() - 1];
+                    let previous = values.len().checked_sub(2).map(|index| &values[index]);
+                    let change = previous
+                        .filter(|previous| !previous.is_zero())
+                        .and_then(|previous| ((latest - previous) / previous).to_f64());
+                    Comparison {
+                        description,
+                        latest: latest.to_string(),
+                        previous: previous.map(|previous| previous.to_string()),
+                        change: change.map(|change| format!("{:+.1}%", change * 100.0)),
+                        increased: change.is_some_and(|change| change > 0.0),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DashboardContext {
+    city: Option<String>,
+    from: String,
+    to: String,
+    granularity: &'static str,
+    granularities: Vec<GranularityOption>,
+    latest_period: Option<String>,
+    previous_period: Option<String>,
+    metrics: [&'static str; 5],
+    total: DashboardRow,
+    rows: Vec<DashboardRow>,
+}
+
+/// Rolls up the subbranches of the whole bank by city, or of the city by subbranch
+#[get("/dashboard?<city>&<from>&<to>&<granularity>")]
+pub async fn dashboard(
+    mut db: Connection<BankManage>,
+    city: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    granularity: Option<Granularity>,
+) -> Template {
+    let reporting_period = unwrap_or_return!(
+        ReportingPeriod::new(from, to, granularity),
+        "Invalid reporting period"
+    );
+    let city = city.map(str::trim).filter(|city| !city.is_empty());
+
+    let subbranches = unwrap_or_return!(
+        sqlx::query_as!(Subbranch, "SELECT * FROM subbranch ORDER BY subbranchName")
+            .fetch_all(&mut *db)
+            .await,
+        "Error querying subbranches"
+    );
+    // An account is listed once per owner in `accountmanagement`
+    let accounts = unwrap_or_return!(
+        query_totals(
+            &mut db,
+            "SELECT managed.subbranchName, COUNT(*), COALESCE(SUM(account.balance), 0)
+            FROM (SELECT subbranchName, savingAccountID AS accountID FROM accountmanagement WHERE savingAccountID IS NOT NULL
+                UNION SELECT subbranchName, checkingAccountID FROM accountmanagement WHERE checkingAccountID IS NOT NULL) AS managed
+            JOIN account ON account.accountID=managed.accountID
+            GROUP BY managed.subbranchName",
+        )
+        .await,
+        "Error querying accounts"
+    );
+    let loans = unwrap_or_return!(
+        query_totals(
+            &mut db,
+            "SELECT loan.subbranchName, COUNT(*), COALESCE(SUM(loan.amount - COALESCE(paid.amount, 0)), 0)
+            FROM loan LEFT JOIN (SELECT loanID, SUM(amount) AS amount FROM payment GROUP BY loanID) AS paid
+                ON paid.loanID=loan.loanID
+            WHERE loan.loanID NOT IN (SELECT loanID FROM loanwriteoff)
+                and loan.amount > COALESCE(paid.amount, 0)
+            GROUP BY loan.subbranchName",
+        )
+        .await,
+        "Error querying loans"
+    );
+    let history = unwrap_or_return!(
+        query_history(&mut db).await,
+        "Error querying account movements and loans"
+    );
+
+    let period_query = format!(
+        "from={}&to={}&granularity={}",
+        reporting_period.from,
+        reporting_period.to,
+        reporting_period.granularity.as_str()
+    );
+    let period_count = reporting_period.periods.len();
+    let mut total = Figures::new(period_count);
+    // Name -> figures, of cities or of the subbranches of the city
+    let mut groups = BTreeMap::<String, Figures>::new();
+    for subbranch in subbranches
+        .into_iter()
+        .filter(|subbranch| city.is_none_or(|city| subbranch.city == city))
+    {
+        let name = &subbranch.subbranchName;
+        let (account_count, balance) = accounts.get(name).cloned().unwrap_or_default();
+        let (loan_count, outstanding) = loans.get(name).cloned().unwrap_or_default();
+        let figures = Figures {
+            asset: subbranch.subbranchAsset.clone(),
+            accounts: account_count,
+            balance,
+            loans: loan_count,
+            outstanding,
+            periods: period_values(&reporting_period, &history, name),
+        };
+        total.add(&figures);
+        let group = match city {
+            Some(_) => subbranch.subbranchName,
+            None => subbranch.city,
+        };
+        groups
+            .entry(group)
+            .or_insert_with(|| Figures::new(period_count))
+            .add(&figures);
+    }
+
+    let rows = groups
+        .iter()
+        .map(|(name, figures)| {
+            let encoded = RawStr::new(name).percent_encode();
+            let href = match city {
+                Some(_) => format!("/profile/subbranch?name={encoded}&{period_query}"),
+                None => format!("/dashboard?city={encoded}&{period_query}"),
+            };
+            DashboardRow::new(name.clone(), Some(href), figures)
+        })
+        .collect();
+    let label =
+        |index: Option<usize>| index.map(|index| reporting_period.periods[index].label.clone());
+    Template::render(
+        "dashboard",
+        DashboardContext {
+            city: city.map(str::to_string),
+            from: reporting_period.from.to_string(),
+            to: reporting_period.to.to_string(),
+            granularity: reporting_period.granularity.as_str(),
+            granularities: reporting_period.granularity_options(),
+            latest_period: label(period_count.checked_sub(1)),
+            previous_period: label(period_count.checked_sub(2)),
+            metrics: METRICS,
+            total: DashboardRow::new(city.unwrap_or("Whole bank").to_string(), None, &total),
+            rows,
+        },
+    )
+}
@@ -4,18 +4,29 @@ use sqlx::Executor;
 
 #[get("/delete/loan?<id>")]
 pub async fn delete_payment(mut db: Connection<BankManage>, id: String) -> Template {
-    let (loan, _, associated_payments) = unwrap_or_return!(
+    let (loan, associated_clients, associated_payments) = unwrap_or_return!(
         super::loan_profile::query_loan(&mut db, &id).await,
         "Error querying loan"
     );
     let pay_amount: sqlx::types::BigDecimal =
         associated_payments.iter().map(|pay| &pay.amount).sum();
+    let write_off = unwrap_or_return!(
+        super::loan_write_off::query_write_off(&mut db, &id).await,
+        "Error querying write-off of the loan"
+    );
     let status = unwrap_or_return!(
         super::loan_profile::LoanStatus::new(&pay_amount, &loan.amount),
         "Unexpected: Aggregated payment is more than the loaded amount"
-    );
-    if matches!(status, super::loan_profile::LoanStatus::BeingPaid) {
-        return error_template!("The loan being paid, deletion is forbidden");
+    )
+    .with_write_off(&write_off);
+    match status {
+        super::loan_profile::LoanStatus::BeingPaid => {
+            return error_template!("The loan being paid, deletion is forbidden")
+        }
+        super::loan_profile::LoanStatus::WrittenOff => {
+            return error_template!("The loan is written off, deletion is forbidden")
+        }
+        _ => (),
     }
 
     start_transaction!(db);
@@ -32,6 +43,24 @@ pub async fn delete_payment(mut db: Connection<BankManage>, id: String) -> Templ
         }
     );
 
+    unwrap_or!(
+        crate::collateral::delete_collaterals_and_guarantors(&mut db, &id).await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error removing collateral and guarantors");
+        }
+    );
+
+    unwrap_or!(
+        crate::loan_agreement::delete_agreements(&mut db, &id).await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error removing loan agreements");
+        }
+    );
+
     unwrap_or!(
         sqlx::query("DELETE FROM receiveloan WHERE loanID=?")
             .bind(&id)
@@ -56,6 +85,17 @@ pub async fn delete_payment(mut db: Connection<BankManage>, id: String) -> Templ
         }
     );
 
+    for receive_loan in associated_clients {
+        unwrap_or!(
+            crate::service_type::refresh_service_type(&mut db, &receive_loan.clientID).await,
+            e,
+            {
+                rollback!(db);
+                return error_template!(e, "Error updating service type of the borrowers");
+            }
+        );
+    }
+
     commit!(db);
 
     Template::render("delete-payment-success", &Context::default())
@@ -6,7 +6,8 @@ use super::preludes::rocket_prelude::*;
 use crate::{
     account_manage::{delete::*, update::*},
     commit, error_template, rollback, start_transaction,
-    utility::{get_list_from_input, get_restriction, Restriction},
+    utility::{get_client_id_list_from_input, get_restriction, Restriction},
+    validation::{FieldErrors, Validate},
 };
 
 #[derive(Serialize)]
@@ -17,6 +18,7 @@ struct EditSavingAccountContext {
     currencyType: String,
     interest: String,
     restriction: Restriction,
+    errors: FieldErrors,
 }
 
 #[derive(Serialize)]
@@ -26,6 +28,7 @@ struct EditCheckingAccountContext {
     balance: String,
     overdraft: String,
     restriction: Restriction,
+    errors: FieldErrors,
 }
 
 #[get("/edit/account?<id>")]
@@ -49,6 +52,7 @@ pub async fn get_edit_account(mut db: Connection<BankManage>, id: String) -> Tem
                     currencyType: saving_account.currencyType,
                     interest: saving_account.interest.to_string(),
                     restriction: get_restriction(),
+                    errors: FieldErrors::new(),
                 },
             ),
             SpecificAccount::CheckingAccount(checking_account) => Template::render(
@@ -59,6 +63,7 @@ pub async fn get_edit_account(mut db: Connection<BankManage>, id: String) -> Tem
                     balance: checking_account.balance.to_string(),
                     overdraft: checking_account.overdraft.to_string(),
                     restriction: get_restriction(),
+                    errors: FieldErrors::new(),
                 },
             ),
         },
@@ -78,9 +83,35 @@ pub async fn act_edit_saving_account(
         );
     }
     let submission = form.value.as_ref().unwrap();
-    start_transaction!(db);
+    let errors = submission.validate();
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "edit-saving-account",
+                EditSavingAccountContext {
+                    id,
+                    clientIDs: submission.clientIDs.clone(),
+                    balance: submission.balance.clone(),
+                    currencyType: submission.currencyType.clone(),
+                    interest: submission.interest.clone(),
+                    restriction: get_restriction(),
+                    errors,
+                },
+            ),
+        );
+    }
     let updated_associated_client_IDs: std::collections::HashSet<String> =
-        get_list_from_input(&submission.clientIDs);
+        match get_client_id_list_from_input(&submission.clientIDs) {
+            Ok(ids) => ids,
+            Err(e) => {
+                return (
+                    form.context.status(),
+                    error_template!(e, "Error updating account"),
+                )
+            }
+        };
+    start_transaction!(db);
     match update_saving_account_and_own(
         &mut db,
         id.clone(),
@@ -119,9 +150,34 @@ pub async fn act_edit_checking_account(
         );
     }
     let submission = form.value.as_ref().unwrap();
-    start_transaction!(db);
+    let errors = submission.validate();
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "edit-checking-account",
+                EditCheckingAccountContext {
+                    id,
+                    clientIDs: submission.clientIDs.clone(),
+                    balance: submission.balance.clone(),
+                    overdraft: submission.overdraft.clone(),
+                    restriction: get_restriction(),
+                    errors,
+                },
+            ),
+        );
+    }
     let updated_associated_client_IDs: std::collections::HashSet<String> =
-        get_list_from_input(&submission.clientIDs);
+        match get_client_id_list_from_input(&submission.clientIDs) {
+            Ok(ids) => ids,
+            Err(e) => {
+                return (
+                    form.context.status(),
+                    error_template!(e, "Error updating account"),
+                )
+            }
+        };
+    start_transaction!(db);
     match update_checking_account_and_own(
         &mut db,
         id.clone(),
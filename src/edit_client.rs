@@ -1,41 +1,60 @@
 use std::collections::HashMap;
 
-use crate::{error_template, utility::{Restriction, validate_string_value}};
+use crate::{
+    client_contact::query_contacts,
+    client_dependency::{
+        delete_client_entity, query_client_dependencies, render_dependency_report,
+    },
+    commit, error_template,
+    relationship_manager::check_employee,
+    rollback,
+    service_type::derive_service_type,
+    start_transaction,
+    utility::{validate_string_value, Restriction},
+    validation::{FieldErrors, Validate, Validator},
+};
 
 use super::preludes::rocket_prelude::*;
+use sqlx::Executor;
 
 #[derive(Serialize)]
 pub struct ClientProfileContext {
     client: Client,
     restriction: Restriction,
-    service_type: (bool, bool, bool),
+    service_type: String,
+    contacts: Vec<ClientContact>,
+    errors: FieldErrors,
+}
+
+async fn render_edit_client(
+    db: &mut Connection<BankManage>,
+    client: Client,
+    errors: FieldErrors,
+) -> Template {
+    let service_type = match derive_service_type(db, &client.clientID).await {
+        Ok(service_type) => service_type,
+        Err(e) => return error_template!(e, "Error deriving service type"),
+    };
+    let contacts = match query_contacts(db, &client.clientID).await {
+        Ok(contacts) => contacts,
+        Err(e) => return error_template!(e, "Error loading contacts"),
+    };
+    Template::render(
+        "edit-client",
+        &ClientProfileContext {
+            client,
+            restriction: crate::utility::get_restriction(),
+            service_type: service_type.to_string(),
+            contacts,
+            errors,
+        },
+    )
 }
 
 #[get("/edit/client?<id>")]
 pub async fn get_edit_client(mut db: Connection<BankManage>, id: String) -> Template {
     match super::client_profile::query_client_by_id(&mut db, id).await {
-        Ok(client) => {
-            let service_type = match client.serviceType {
-                None => (false, false, true),
-                Some(ref service_type) => {
-                    if service_type == "account" {
-                        (true, false, false)
-                    } else if service_type == "loan" {
-                        (false, true, false)
-                    } else {
-                        return error_template!("Unexpected service type");
-                    }
-                }
-            };
-            Template::render(
-                "edit-client",
-                &ClientProfileContext {
-                    client,
-                    restriction: crate::utility::get_restriction(),
-                    service_type,
-                },
-            )
-        }
+        Ok(client) => render_edit_client(&mut db, client, FieldErrors::new()).await,
         Err(e) => error_template!(e, "Error loading client"),
     }
 }
@@ -45,12 +64,17 @@ pub struct ClientFromForm {
     pub clientName: String,
     pub clientTel: String,
     pub clientAddr: String,
-    pub contactName: String,
-    pub contactEmail: String,
     pub employeeID: String,
-    pub contactTel: String,
-    pub contactRelationship: String,
-    pub serviceType: String,
+}
+
+impl Validate for ClientFromForm {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("clientName", &self.clientName)
+            .pattern("clientName", &self.clientName, "name")
+            .pattern("clientTel", &self.clientTel, "tel")
+            .finish()
+    }
 }
 
 #[post("/edit/client?<id>", data = "<form>")]
@@ -61,28 +85,36 @@ pub async fn act_edit_client(
 ) -> (Status, Template) {
     let template;
     if let Some(submission) = form.value.clone() {
+        let mut errors = submission.validate();
+        if let Err(e) = check_employee(&mut db, &submission.employeeID).await {
+            errors.insert("employeeID".to_string(), e.to_string());
+        }
+        if !errors.is_empty() {
+            let client = Client {
+                clientID: id,
+                employeeID: Some(submission.employeeID),
+                clientName: Some(submission.clientName),
+                clientTel: Some(submission.clientTel),
+                clientAddr: Some(submission.clientAddr),
+                serviceType: None,
+            };
+            return (
+                Status::UnprocessableEntity,
+                render_edit_client(&mut db, client, errors).await,
+            );
+        }
         let update_result = sqlx::query(&format!(
             "UPDATE client SET 
                 employeeID={},
                 clientName = {},
                 clientTel = {},
-                clientAddr = {},
-                contactName = {},
-                contactTel={},
-                contactEmail = {},
-                contactRelationship={},
-                serviceType={}
+                clientAddr = {}
                 WHERE clientID={}
                 ",
             validate_string_value(&submission.employeeID),
             validate_string_value(&submission.clientName),
             validate_string_value(&submission.clientTel),
             validate_string_value(&submission.clientAddr),
-            validate_string_value(&submission.contactName),
-            validate_string_value(&submission.contactTel),
-            validate_string_value(&submission.contactEmail),
-            validate_string_value(&submission.contactRelationship),
-            validate_string_value(&submission.serviceType),
             validate_string_value(&id),
         ))
         .execute(&mut *db)
@@ -100,13 +132,35 @@ pub async fn act_edit_client(
 
 #[get("/delete/client?<id>")]
 pub async fn delete_client(mut db: Connection<BankManage>, id: String) -> Template {
-    eprintln!("delete {id}");
-    match sqlx::query("delete from client where clientID=?")
-        .bind(id)
-        .execute(&mut *db)
-        .await
-    {
-        Ok(_) => Template::render("delete-client-success", &Context::default()),
-        Err(e) => error_template!(e, "Error deleting client"),
+    let client = match super::client_profile::query_client_by_id(&mut db, id.clone()).await {
+        Ok(client) => client,
+        Err(e) => return error_template!(e, "Error querying client"),
+    };
+    let dependencies = match query_client_dependencies(&mut db, &id).await {
+        Ok(dependencies) => dependencies,
+        Err(e) => return error_template!(e, "Error querying dependencies of the client"),
+    };
+
+    // Rule 1: a client still holding accounts, loans or guarantees cannot be deleted
+    if !dependencies.is_empty() {
+        return render_dependency_report(&mut db, client, dependencies).await;
+    }
+
+    start_transaction!(db);
+    match delete_client_entity(&mut db, &id).await {
+        Ok(document_files) => {
+            commit!(db);
+            // The files are kept until the rows referring to them are gone for good
+            if let Err(e) =
+                crate::kyc::remove_unreferenced_files(&mut db, &document_files).await
+            {
+                eprintln!("Error removing the stored files of client {id}: {e}");
+            }
+            Template::render("delete-client-success", &Context::default())
+        }
+        Err(e) => {
+            rollback!(db);
+            error_template!(e, "Error deleting client")
+        }
     }
 }
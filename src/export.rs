@@ -0,0 +1,323 @@
+use std::io::Cursor;
+
+use bigdecimal::ToPrimitive;
+use rocket::http::Header;
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+use simple_excel_writer::{CellValue, Column, Row, Workbook};
+use sqlx::types::BigDecimal;
+
+use super::preludes::rocket_prelude::*;
+use crate::utility::GenericError;
+
+/// Byte order mark, so that spreadsheets open the CSV as UTF-8 rather than the locale's encoding
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// Significant digits a spreadsheet keeps in a number
+const SPREADSHEET_DIGITS: u64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum ExportFormat {
+    #[field(value = "csv")]
+    Csv,
+    #[field(value = "xlsx")]
+    Xlsx,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    fn content_type(&self) -> ContentType {
+        match self {
+            Self::Csv => ContentType::new("text", "csv").with_params(("charset", "utf-8")),
+            Self::Xlsx => ContentType::new(
+                "application",
+                "vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Text(String),
+    /// Written with all of its digits, so that no amount is rounded in CSV
+    Decimal(BigDecimal),
+    Integer(i64),
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+impl From<Option<String>> for Cell {
+    fn from(text: Option<String>) -> Self {
+        Cell::Text(text.unwrap_or_default())
+    }
+}
+
+impl From<BigDecimal> for Cell {
+    fn from(decimal: BigDecimal) -> Self {
+        Cell::Decimal(decimal)
+    }
+}
+
+impl From<&BigDecimal> for Cell {
+    fn from(decimal: &BigDecimal) -> Self {
+        Cell::Decimal(decimal.clone())
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(integer: i64) -> Self {
+        Cell::Integer(integer)
+    }
+}
+
+impl Cell {
+    /// The field as in RFC 4180, quoted if it holds a comma, a quote or a line break.
+    /// Text which a spreadsheet would take for a formula is prefixed with a quote.
+    fn to_csv_field(&self) -> String {
+        let text = match self {
+            Cell::Text(text) => {
+                if is_formula(text) {
+                    format!("'{text}")
+                } else {
+                    text.clone()
+                }
+            }
+            Cell::Decimal(decimal) => decimal.to_string(),
+            Cell::Integer(integer) => integer.to_string(),
+        };
+        if text.contains([',', '"', '\r', '\n']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text
+        }
+    }
+
+    /// Numbers are written as numbers so that they can be summed, unless they have more digits than
+    /// a spreadsheet keeps, in which case they are written as text rather than rounded.
+    /// Text is never taken for a formula.
+    fn to_cell_value(&self) -> CellValue {
+        match self {
+            Cell::Text(text) => CellValue::String(text.clone()),
+            Cell::Decimal(decimal) => match decimal.to_f64() {
+                Some(number) if decimal.digits() <= SPREADSHEET_DIGITS => CellValue::Number(number),
+                _ => CellValue::String(decimal.to_string()),
+            },
+            Cell::Integer(integer) => CellValue::Number(*integer as f64),
+        }
+    }
+}
+
+/// Whether a spreadsheet would evaluate the text. A signed number is read as a number, so that
+/// telephone numbers such as `+8613800000000` are kept as they are.
+fn is_formula(text: &str) -> bool {
+    match text.chars().next() {
+        Some('=' | '@' | '\t' | '\r') => true,
+        Some('+' | '-') => {
+            let number = &text[1..];
+            !(number.starts_with(|c: char| c.is_ascii_digit())
+                && number.parse::<BigDecimal>().is_ok())
+        }
+        _ => false,
+    }
+}
+
+/// A table to be downloaded as a spreadsheet
+#[derive(Debug)]
+pub struct Table {
+    /// Name of the file, without the extension, and of the sheet
+    name: String,
+    header: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(name: impl Into<String>, header: Vec<&'static str>) -> Self {
+        Table {
+            name: name.into(),
+            header,
+            rows: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    fn to_csv(&self) -> Vec<u8> {
+        let mut csv = UTF8_BOM.to_vec();
+        let header: Vec<Cell> = self.header.iter().map(|&title| Cell::from(title)).collect();
+        for row in std::iter::once(&header).chain(&self.rows) {
+            let fields: Vec<String> = row.iter().map(Cell::to_csv_field).collect();
+            csv.extend_from_slice(fields.join(",").as_bytes());
+            csv.extend_from_slice(b"\r\n");
+        }
+        csv
+    }
+
+    /// Sheet names are at most 31 characters long, without any of `[]:*?/\`
+    fn sheet_name(&self) -> String {
+        self.name
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+            .take(31)
+            .collect()
+    }
+
+    fn to_xlsx(&self) -> Result<Vec<u8>, GenericError> {
+        let mut workbook = Workbook::create_in_memory();
+        let mut sheet = workbook.create_sheet(&self.sheet_name());
+        for _ in &self.header {
+            sheet.add_column(Column { width: 20.0 });
+        }
+        workbook.write_sheet(&mut sheet, |writer| {
+            let mut header = Row::new();
+            for title in &self.header {
+                header.add_cell(CellValue::String(title.to_string()));
+            }
+            writer.append_row(header)?;
+            for cells in &self.rows {
+                let mut row = Row::new();
+                for cell in cells {
+                    row.add_cell(cell.to_cell_value());
+                }
+                writer.append_row(row)?;
+            }
+            Ok(())
+        })?;
+        Ok(workbook
+            .close()?
+            .ok_or("The workbook was not written in memory")?)
+    }
+
+    pub fn export(self, format: ExportFormat) -> Result<Export, GenericError> {
+        let data = match format {
+            ExportFormat::Csv => self.to_csv(),
+            ExportFormat::Xlsx => self.to_xlsx()?,
+        };
+        Ok(Export {
+            file_name: format!("{}.{}", self.name, format.extension()),
+            format,
+            data,
+        })
+    }
+}
+
+/// A spreadsheet downloaded as an attachment
+pub struct Export {
+    file_name: String,
+    format: ExportFormat,
+    data: Vec<u8>,
+}
+
+/// `Content-Disposition` with an ASCII file name for older clients,
+/// and the UTF-8 one of RFC 5987 so that Chinese names are kept
+fn content_disposition(file_name: &str) -> String {
+    let ascii: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_. ".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"-_.".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{byte:02X}")
+            }
+        })
+        .collect();
+    format!("attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+}
+
+impl<'r> Responder<'r, 'static> for Export {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(self.format.content_type())
+            .header(Header::new(
+                "Content-Disposition",
+                content_disposition(&self.file_name),
+            ))
+            .sized_body(self.data.len(), Cursor::new(self.data))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn field(text: &str) -> String {
+        Cell::from(text).to_csv_field()
+    }
+
+    #[test]
+    fn quotes_fields_as_in_rfc_4180() {
+        assert_eq!(field("plain"), "plain");
+        assert_eq!(field("a,b"), "\"a,b\"");
+        assert_eq!(field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(field("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn guards_formulas_but_not_signed_numbers() {
+        assert_eq!(field("=SUM(A1:A2)"), "'=SUM(A1:A2)");
+        assert_eq!(field("@cmd"), "'@cmd");
+        assert_eq!(field("+A1"), "'+A1");
+        assert_eq!(field("-A1"), "'-A1");
+        assert_eq!(field("\tx"), "'\tx");
+        assert_eq!(field("-"), "'-");
+        assert_eq!(field("+8613800000000"), "+8613800000000");
+        assert_eq!(field("-12.5"), "-12.5");
+        assert_eq!(field("+1+cmd|' /C calc'!A0"), "'+1+cmd|' /C calc'!A0");
+        assert_eq!(field("-2+HYPERLINK(\"x\")"), "\"'-2+HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(field("+86 138"), "'+86 138");
+        assert_eq!(field("=1,2"), "\"'=1,2\"");
+    }
+
+    #[test]
+    fn writes_numbers_with_all_of_their_digits() {
+        let decimal = BigDecimal::from_str("-12345678901234567890.01").unwrap();
+        assert_eq!(
+            Cell::from(decimal).to_csv_field(),
+            "-12345678901234567890.01"
+        );
+        assert_eq!(Cell::from(-42i64).to_csv_field(), "-42");
+    }
+
+    #[test]
+    fn writes_a_bom_and_crlf_line_endings() {
+        let mut table = Table::new("Clients", vec!["ID", "Telephone"]);
+        table.push(vec!["c1".into(), "+8613800000000".into()]);
+        table.push(vec!["c2".into(), "=HYPERLINK(\"x\")".into()]);
+        let csv = table.to_csv();
+        assert!(csv.starts_with(UTF8_BOM));
+        assert_eq!(
+            std::str::from_utf8(&csv[UTF8_BOM.len()..]).unwrap(),
+            "ID,Telephone\r\nc1,+8613800000000\r\nc2,\"'=HYPERLINK(\"\"x\"\")\"\r\n"
+        );
+    }
+}
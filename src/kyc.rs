@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::preludes::rocket_prelude::*;
+use crate::utility::GenericError;
+use crate::{error_template, unwrap_or_return};
+use chrono::{Local, NaiveDate};
+use rocket::fs::NamedFile;
+use rocket::tokio::fs;
+use sha2::{Digest, Sha256};
+
+/// Uploaded documents are stored under this directory, named after the SHA-256 of their content
+pub const DOCUMENT_DIR: &str = "uploads/documents";
+pub const MAX_DOCUMENT_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Document type -> description
+pub const DOCUMENT_TYPES: [(&str, &str); 3] = [
+    ("identity", "ID scan"),
+    ("address", "Proof of address"),
+    ("other", "Other"),
+];
+/// A client should hold a valid document of each of these types to open accounts or receive loans
+pub const REQUIRED_DOCUMENT_TYPES: [&str; 2] = ["identity", "address"];
+
+fn describe_document_type(document_type: &str) -> &str {
+    DOCUMENT_TYPES
+        .iter()
+        .find(|(name, _)| *name == document_type)
+        .map(|(_, description)| *description)
+        .unwrap_or(document_type)
+}
+
+fn is_accepted_content_type(content_type: &ContentType) -> bool {
+    [ContentType::PDF, ContentType::JPEG, ContentType::PNG].contains(content_type)
+}
+
+pub enum KycStatus {
+    Verified,
+    Missing(Vec<String>),
+    Expired(Vec<String>),
+}
+
+impl KycStatus {
+    pub fn new(documents: &[ClientDocument], today: &NaiveDate) -> Self {
+        let mut missing = vec![];
+        let mut expired = vec![];
+        for document_type in REQUIRED_DOCUMENT_TYPES {
+            let mut of_type = documents
+                .iter()
+                .filter(|document| document.documentType == document_type)
+                .peekable();
+            if of_type.peek().is_none() {
+                missing.push(describe_document_type(document_type).to_string());
+            } else if !of_type.any(|document| !is_expired(document, today)) {
+                expired.push(describe_document_type(document_type).to_string());
+            }
+        }
+        if !missing.is_empty() {
+            Self::Missing(missing)
+        } else if !expired.is_empty() {
+            Self::Expired(expired)
+        } else {
+            Self::Verified
+        }
+    }
+}
+
+impl std::fmt::Display for KycStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Verified => write!(f, "Verified"),
+            Self::Missing(types) => write!(f, "Missing {}", types.join(", ")),
+            Self::Expired(types) => write!(f, "Expired {}", types.join(", ")),
+        }
+    }
+}
+
+fn is_expired(document: &ClientDocument, today: &NaiveDate) -> bool {
+    matches!(document.expiryDate, Some(expiry_date) if expiry_date < *today)
+}
+
+pub async fn query_documents(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Vec<ClientDocument>, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientDocument,
+        "SELECT * FROM clientdocument WHERE clientID=? ORDER BY uploadDate DESC",
+        client_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+#[derive(Debug)]
+pub struct KycError {
+    pub info: String,
+}
+
+impl std::fmt::Display for KycError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for KycError {}
+
+/// Checks that each of the clients holds valid identity documents
+pub async fn check_kyc(
+    db: &mut Connection<BankManage>,
+    client_ids: &[String],
+) -> Result<(), GenericError> {
+    let today = Local::today().naive_local();
+    for client_id in client_ids {
+        let status = KycStatus::new(&query_documents(db, client_id).await?, &today);
+        if !matches!(status, KycStatus::Verified) {
+            return Err(Box::new(KycError {
+                info: format!("KYC of client {client_id} is incomplete: {status}"),
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct DocumentView {
+    documentID: String,
+    documentType: String,
+    fileName: String,
+    size: String,
+    uploadDate: String,
+    expiryDate: Option<String>,
+    expired: bool,
+}
+
+impl DocumentView {
+    pub fn new(document: ClientDocument, today: &NaiveDate) -> Self {
+        DocumentView {
+            expired: is_expired(&document, today),
+            documentType: describe_document_type(&document.documentType).to_string(),
+            documentID: document.documentID,
+            fileName: document.fileName,
+            size: format!("{:.1} KiB", document.size as f64 / 1024.0),
+            uploadDate: document.uploadDate.to_string(),
+            expiryDate: document.expiryDate.map(|date| date.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct NewDocumentContext {
+    id: String,
+    document_types: Vec<HashMap<&'static str, &'static str>>,
+}
+
+#[get("/new/document?<id>")]
+pub async fn get_new_document(id: String) -> Template {
+    Template::render(
+        "new-document",
+        NewDocumentContext {
+            id,
+            document_types: DOCUMENT_TYPES
+                .iter()
+                .map(|(name, description)| {
+                    HashMap::from([("name", *name), ("description", *description)])
+                })
+                .collect(),
+        },
+    )
+}
+
+#[derive(FromForm)]
+pub struct DocumentSubmit<'r> {
+    documentType: String,
+    expiryDate: String,
+    file: TempFile<'r>,
+}
+
+#[post("/new/document?<id>", data = "<form>")]
+pub async fn submit_document(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, DocumentSubmit<'_>>>,
+) -> Template {
+    let mut form = form.into_inner();
+    let value = match form.value {
+        Some(ref mut value) => value,
+        None => return error_template!("Error uploading document: failed to receive form"),
+    };
+    unwrap_or_return!(
+        crate::client_profile::query_client_by_id(&mut db, id.clone()).await,
+        "Error querying client"
+    );
+
+    // Rule 1: the document type should be a known one
+    if !DOCUMENT_TYPES
+        .iter()
+        .any(|(name, _)| *name == value.documentType)
+    {
+        return error_template!("Unexpected document type");
+    }
+
+    // Rule 2: the file should be a non-empty scan within the size limit
+    if value.file.len() == 0 || value.file.len() > MAX_DOCUMENT_SIZE {
+        return error_template!(format!(
+            "The document should be a non-empty file of at most {} MiB",
+            MAX_DOCUMENT_SIZE / 1024 / 1024
+        ));
+    }
+    let content_type = match value.file.content_type() {
+        Some(content_type) if is_accepted_content_type(content_type) => content_type.to_string(),
+        _ => return error_template!("The document should be a PDF, JPEG or PNG file"),
+    };
+
+    // Rule 3: an expired document is of no use
+    let expiry_date = if value.expiryDate.is_empty() {
+        None
+    } else {
+        Some(unwrap_or_return!(
+            NaiveDate::parse_from_str(&value.expiryDate, "%Y-%m-%d"),
+            "Invalid input: cannot parse expiry date"
+        ))
+    };
+    if matches!(expiry_date, Some(expiry_date) if expiry_date < Local::today().naive_local()) {
+        return error_template!("The document is already expired");
+    }
+
+    let file_name = value
+        .file
+        .name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| value.documentType.clone());
+    let size = value.file.len();
+    let sha256 = unwrap_or_return!(
+        store_document(&mut value.file).await,
+        "Error storing document"
+    );
+    match sqlx::query(
+        "INSERT INTO clientdocument (documentID, clientID, documentType, fileName, contentType, size, sha256, uploadDate, expiryDate) VALUES
+    (?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(&id)
+    .bind(&value.documentType)
+    .bind(&file_name)
+    .bind(&content_type)
+    .bind(size as i64)
+    .bind(&sha256)
+    .bind(Local::now().format("%Y-%m-%d").to_string())
+    .bind(expiry_date)
+    .execute(&mut *db)
+    .await
+    {
+        Ok(_) => Template::render("update-client-success", &HashMap::from([("id", id)])),
+        Err(e) => error_template!(e, "Error recording document"),
+    }
+}
+
+fn document_path(sha256: &str) -> PathBuf {
+    Path::new(DOCUMENT_DIR).join(sha256)
+}
+
+/// Stores the file under its content hash, so that a document uploaded twice is kept once. Returns the hash.
+async fn store_document(file: &mut TempFile<'_>) -> Result<String, GenericError> {
+    fs::create_dir_all(DOCUMENT_DIR).await?;
+    let staging = Path::new(DOCUMENT_DIR).join(format!("{}.part", uuid::Uuid::new_v4()));
+    file.copy_to(&staging).await?;
+    let sha256 = hex::encode(Sha256::digest(&fs::read(&staging).await?));
+    let path = document_path(&sha256);
+    if path.exists() {
+        fs::remove_file(&staging).await?;
+    } else {
+        fs::rename(&staging, &path).await?;
+    }
+    Ok(sha256)
+}
+
+/// Removes the stored file once no document refers to it
+async fn remove_unreferenced_file(
+    db: &mut Connection<BankManage>,
+    sha256: &str,
+) -> Result<(), GenericError> {
+    let references = sqlx::query("SELECT COUNT(*) FROM clientdocument WHERE sha256=?")
+        .bind(sha256)
+        .fetch_one(&mut **db)
+        .await?
+        .try_get::<'_, i64, _>(0)?;
+    if references == 0 && document_path(sha256).exists() {
+        fs::remove_file(document_path(sha256)).await?;
+    }
+    Ok(())
+}
+
+async fn query_document(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<ClientDocument, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientDocument,
+        "SELECT * FROM clientdocument WHERE documentID=?",
+        id
+    )
+    .fetch_one(&mut **db)
+    .await?)
+}
+
+#[get("/document?<id>")]
+pub async fn download_document(
+    mut db: Connection<BankManage>,
+    id: String,
+) -> Result<(ContentType, NamedFile), Template> {
+    let document = match query_document(&mut db, &id).await {
+        Ok(document) => document,
+        Err(e) => return Err(error_template!(e, "Error querying document")),
+    };
+    match NamedFile::open(document_path(&document.sha256)).await {
+        Ok(file) => Ok((
+            ContentType::parse_flexible(&document.contentType).unwrap_or(ContentType::Binary),
+            file,
+        )),
+        Err(e) => Err(error_template!(e, "Error reading document")),
+    }
+}
+
+#[get("/delete/document?<id>")]
+pub async fn delete_document(mut db: Connection<BankManage>, id: String) -> Template {
+    let document = unwrap_or_return!(
+        query_document(&mut db, &id).await,
+        "Error querying document"
+    );
+    unwrap_or_return!(
+        sqlx::query("DELETE FROM clientdocument WHERE documentID=?")
+            .bind(&id)
+            .execute(&mut *db)
+            .await,
+        "Error deleting document"
+    );
+    unwrap_or_return!(
+        remove_unreferenced_file(&mut db, &document.sha256).await,
+        "Error removing the stored file"
+    );
+    Template::render(
+        "update-client-success",
+        &HashMap::from([("id", document.clientID)]),
+    )
+}
+
+/// Deletes the documents of a client and returns the hashes of their stored files.
+/// The files are left on disk, so that they can be removed with `remove_unreferenced_files` once the deletion is committed.
+pub async fn delete_documents(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<Vec<String>, GenericError> {
+    let documents = query_documents(db, client_id).await?;
+    sqlx::query("DELETE FROM clientdocument WHERE clientID=?")
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(documents
+        .into_iter()
+        .map(|document| document.sha256)
+        .collect())
+}
+
+/// Removes the stored files which no document refers to any more
+pub async fn remove_unreferenced_files(
+    db: &mut Connection<BankManage>,
+    hashes: &[String],
+) -> Result<(), GenericError> {
+    for sha256 in hashes {
+        remove_unreferenced_file(db, sha256).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use super::preludes::rocket_prelude::*;
+use crate::utility::{add_months, GenericError};
+use bigdecimal::Zero;
+use chrono::NaiveDate;
+use sqlx::types::BigDecimal;
+
+/// Returns all versions of the agreement of the loan, the latest one first
+pub async fn query_agreements(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+) -> Result<Vec<LoanAgreement>, GenericError> {
+    Ok(sqlx::query_as!(
+        LoanAgreement,
+        "SELECT * FROM loanagreement WHERE loanID=? ORDER BY version DESC",
+        loan_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+pub async fn query_schedule(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+    version: i32,
+) -> Result<Vec<LoanInstallment>, GenericError> {
+    Ok(sqlx::query_as!(
+        LoanInstallment,
+        "SELECT * FROM loanschedule WHERE loanID=? and version=? ORDER BY installment",
+        loan_id,
+        version
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+/// Splits `outstanding` into `term_months` monthly installments of equal principal, starting one month after `effective_date`.
+/// The interest of each installment is charged on the principal still outstanding, at the annual `rate` (in percent).
+pub fn generate_schedule(
+    loan_id: &str,
+    version: i32,
+    effective_date: &NaiveDate,
+    outstanding: &BigDecimal,
+    term_months: i32,
+    rate: f32,
+) -> Result<Vec<LoanInstallment>, GenericError> {
+    if term_months <= 0 {
+        return Err("The term should be at least one month".into());
+    }
+    if outstanding.is_zero() {
+        return Ok(vec![]);
+    }
+    let monthly_rate = rate.to_string().parse::<BigDecimal>()? / BigDecimal::from(1200);
+    let principal = (outstanding / BigDecimal::from(term_months)).with_scale(2);
+    let mut remaining = outstanding.clone();
+    let mut schedule = vec![];
+    for i in 1..=term_months {
+        let this_principal = if i == term_months {
+            remaining.clone()
+        } else {
+            principal.clone()
+        };
+        schedule.push(LoanInstallment {
+            loanID: loan_id.to_string(),
+            version,
+            installment: i,
+            dueDate: add_months(effective_date, i as u32),
+            interest: (&remaining * &monthly_rate).with_scale(2),
+            principal: this_principal.clone(),
+        });
+        remaining -= this_principal;
+        if remaining.is_zero() {
+            break;
+        }
+    }
+    Ok(schedule)
+}
+
+/// Records a new version of the agreement together with the schedule of the outstanding amount.
+/// Returns the version number of the inserted agreement.
+pub async fn add_agreement(
+    db: &mut Connection<BankManage>,
+    agreement: &LoanAgreement,
+    outstanding: &BigDecimal,
+) -> Result<i32, GenericError> {
+    let version = sqlx::query("SELECT COALESCE(MAX(version), 0) FROM loanagreement WHERE loanID=?")
+        .bind(&agreement.loanID)
+        .fetch_one(&mut **db)
+        .await?
+        .try_get::<'_, i64, _>(0)? as i32
+        + 1;
+    sqlx::query(
+        "INSERT INTO loanagreement (loanID, version, effectiveDate, amount, termMonths, rate, borrowers, reason) VALUES
+    (?, ?, ?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(&agreement.loanID)
+    .bind(version)
+    .bind(agreement.effectiveDate)
+    .bind(&agreement.amount)
+    .bind(agreement.termMonths)
+    .bind(agreement.rate)
+    .bind(&agreement.borrowers)
+    .bind(&agreement.reason)
+    .execute(&mut **db)
+    .await?;
+
+    let schedule = generate_schedule(
+        &agreement.loanID,
+        version,
+        &agreement.effectiveDate,
+        outstanding,
+        agreement.termMonths,
+        agreement.rate,
+    )?;
+    for installment in schedule {
+        sqlx::query(
+            "INSERT INTO loanschedule (loanID, version, installment, dueDate, principal, interest) VALUES
+        (?, ?, ?, ?, ?, ?)
+        ",
+        )
+        .bind(&installment.loanID)
+        .bind(installment.version)
+        .bind(installment.installment)
+        .bind(installment.dueDate)
+        .bind(&installment.principal)
+        .bind(&installment.interest)
+        .execute(&mut **db)
+        .await?;
+    }
+    Ok(version)
+}
+
+/// Interest of the installments that fell due before `today` under the agreement and is still unpaid.
+/// The payments made since the agreement took effect settle the due installments in order,
+/// the interest of each before its principal.
+pub fn overdue_interest(
+    agreement: &LoanAgreement,
+    schedule: &[LoanInstallment],
+    payments: &[Payment],
+    today: &NaiveDate,
+) -> BigDecimal {
+    let mut paid: BigDecimal = payments
+        .iter()
+        .filter(|payment| payment.date >= agreement.effectiveDate)
+        .map(|payment| &payment.amount)
+        .sum();
+    let mut unpaid = BigDecimal::zero();
+    for installment in schedule
+        .iter()
+        .filter(|installment| &installment.dueDate < today)
+    {
+        let settled_interest = paid
+            .clone()
+            .min(installment.interest.clone())
+            .max(BigDecimal::zero());
+        unpaid += &installment.interest - &settled_interest;
+        paid -= settled_interest;
+        let settled_principal = paid
+            .clone()
+            .min(installment.principal.clone())
+            .max(BigDecimal::zero());
+        paid -= settled_principal;
+    }
+    unpaid
+}
+
+pub async fn delete_agreements(
+    db: &mut Connection<BankManage>,
+    loan_id: &str,
+) -> Result<(), GenericError> {
+    sqlx::query("DELETE FROM loanschedule WHERE loanID=?")
+        .bind(loan_id)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("DELETE FROM loanagreement WHERE loanID=?")
+        .bind(loan_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    fn decimal(text: &str) -> BigDecimal {
+        text.parse().unwrap()
+    }
+
+    fn agreement(effective_date: &str) -> LoanAgreement {
+        LoanAgreement {
+            loanID: "L1".to_string(),
+            version: 1,
+            effectiveDate: date(effective_date),
+            amount: decimal("3000"),
+            termMonths: 3,
+            rate: 12.0,
+            borrowers: "C1".to_string(),
+            reason: None,
+        }
+    }
+
+    fn payment(on: &str, amount: &str) -> Payment {
+        Payment {
+            paymentID: format!("P{on}"),
+            loanID: "L1".to_string(),
+            date: date(on),
+            amount: decimal(amount),
+            reversalOf: None,
+            reason: None,
+        }
+    }
+
+    fn schedule() -> Vec<LoanInstallment> {
+        generate_schedule("L1", 1, &date("2022-01-31"), &decimal("3000"), 3, 12.0).unwrap()
+    }
+
+    #[test]
+    fn splits_the_principal_into_monthly_installments() {
+        let schedule = schedule();
+        let due: Vec<_> = schedule
+            .iter()
+            .map(|installment| installment.dueDate)
+            .collect();
+        assert_eq!(
+            due,
+            [date("2022-02-28"), date("2022-03-31"), date("2022-04-30")]
+        );
+        let principal: Vec<_> = schedule
+            .iter()
+            .map(|installment| &installment.principal)
+            .collect();
+        assert_eq!(
+            principal,
+            [&decimal("1000"), &decimal("1000"), &decimal("1000")]
+        );
+        let interest: Vec<_> = schedule
+            .iter()
+            .map(|installment| &installment.interest)
+            .collect();
+        assert_eq!(interest, [&decimal("30"), &decimal("20"), &decimal("10")]);
+    }
+
+    #[test]
+    fn puts_the_rounding_into_the_last_installment() {
+        let schedule =
+            generate_schedule("L1", 1, &date("2022-01-15"), &decimal("100"), 3, 0.0).unwrap();
+        let principal: Vec<_> = schedule
+            .iter()
+            .map(|installment| &installment.principal)
+            .collect();
+        assert_eq!(
+            principal,
+            [&decimal("33.33"), &decimal("33.33"), &decimal("33.34")]
+        );
+        assert!(
+            generate_schedule("L1", 1, &date("2022-01-15"), &decimal("0"), 3, 5.0)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(generate_schedule("L1", 1, &date("2022-01-15"), &decimal("100"), 0, 5.0).is_err());
+    }
+
+    #[test]
+    fn counts_only_the_interest_of_due_installments() {
+        let agreement = agreement("2022-01-31");
+        let schedule = schedule();
+        assert_eq!(
+            overdue_interest(&agreement, &schedule, &[], &date("2022-02-28")),
+            decimal("0")
+        );
+        assert_eq!(
+            overdue_interest(&agreement, &schedule, &[], &date("2022-04-01")),
+            decimal("50")
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_interest_already_paid() {
+        let agreement = agreement("2022-01-31");
+        let schedule = schedule();
+        let today = date("2022-04-01");
+        // The first installment is paid in full, the second only its interest
+        let payments = [payment("2022-02-28", "1030"), payment("2022-03-31", "20")];
+        assert_eq!(
+            overdue_interest(&agreement, &schedule, &payments, &today),
+            decimal("0")
+        );
+        // Only part of the interest of the first installment is paid
+        let payments = [payment("2022-02-28", "10")];
+        assert_eq!(
+            overdue_interest(&agreement, &schedule, &payments, &today),
+            decimal("40")
+        );
+        // Payments before the agreement settle an earlier schedule, and reversals cancel out
+        let payments = [
+            payment("2022-01-01", "5000"),
+            payment("2022-02-28", "1030"),
+            payment("2022-03-01", "-1030"),
+        ];
+        assert_eq!(
+            overdue_interest(&agreement, &schedule, &payments, &today),
+            decimal("50")
+        );
+    }
+}
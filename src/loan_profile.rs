@@ -1,5 +1,6 @@
 use super::preludes::rocket_prelude::*;
-use crate::{unwrap_or_return, utility::GenericError};
+use crate::export::{Export, ExportFormat, Table};
+use crate::{error_template, unwrap_or, unwrap_or_return, utility::GenericError};
 use bigdecimal::Zero;
 use sqlx::types::BigDecimal;
 
@@ -13,21 +14,88 @@ pub struct LoanProfileContext {
     pub status: String,
     pub paid: String,
     pub unpaid: String,
+    pub agreements: Vec<AgreementProfileContext>,
+    pub schedule: Vec<InstallmentProfileContext>,
+    pub write_off: Option<WriteOffProfileContext>,
+    pub collaterals: Vec<CollateralProfileContext>,
+    pub guarantors: Vec<String>,
+    pub loan_to_value: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CollateralProfileContext {
+    pub collateralID: String,
+    pub collateralType: String,
+    pub description: Option<String>,
+    pub appraisedValue: String,
+    pub appraisalDate: String,
+    pub releaseDate: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WriteOffProfileContext {
+    pub date: String,
+    pub amount: String,
+    pub reason: String,
+    pub approverID: String,
+    pub recovered: String,
+    pub recoveries: Vec<PaymentProfileContext>,
+}
+
+#[derive(Serialize)]
+pub struct AgreementProfileContext {
+    pub version: String,
+    pub effectiveDate: String,
+    pub amount: String,
+    pub termMonths: String,
+    pub rate: String,
+    pub borrowers: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct InstallmentProfileContext {
+    pub installment: String,
+    pub dueDate: String,
+    pub principal: String,
+    pub interest: String,
 }
 
 #[derive(Serialize)]
 pub struct PaymentProfileContext {
+    pub paymentID: String,
     pub date: String,
     pub amount: String,
+    pub reversalOf: Option<String>,
+    pub reason: Option<String>,
+    pub reversed: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
 pub enum LoanStatus {
+    #[field(value = "unpaid")]
     Unpaid,
+    #[field(value = "being_paid")]
     BeingPaid,
+    #[field(value = "paid")]
     Paid,
+    #[field(value = "written_off")]
+    WrittenOff,
 }
 
 impl LoanStatus {
+    pub const ALL: [LoanStatus; 4] = [Self::Unpaid, Self::BeingPaid, Self::Paid, Self::WrittenOff];
+
+    /// Value of the status in the query string of a search
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unpaid => "unpaid",
+            Self::BeingPaid => "being_paid",
+            Self::Paid => "paid",
+            Self::WrittenOff => "written_off",
+        }
+    }
+
     pub fn new(pay_amount: &BigDecimal, loan_amount: &BigDecimal) -> Result<Self, String> {
         if pay_amount.is_zero() && !loan_amount.is_zero() {
             Ok(Self::Unpaid)
@@ -42,6 +110,14 @@ impl LoanStatus {
             ))
         }
     }
+
+    /// A written-off loan stays written off regardless of its payments
+    pub fn with_write_off(self, write_off: &Option<LoanWriteOff>) -> Self {
+        match write_off {
+            Some(_) => Self::WrittenOff,
+            None => self,
+        }
+    }
 }
 
 impl std::fmt::Display for LoanStatus {
@@ -53,6 +129,7 @@ impl std::fmt::Display for LoanStatus {
                 Self::Unpaid => "Unpaid",
                 Self::BeingPaid => "Being paid",
                 Self::Paid => "Paid",
+                Self::WrittenOff => "Written off",
             }
         )
     }
@@ -64,10 +141,38 @@ pub async fn loan_profile(mut db: Connection<BankManage>, id: String) -> Templat
         unwrap_or_return!(query_loan(&mut db, &id).await, "Error querying loan");
     let pay_amount: sqlx::types::BigDecimal =
         associated_payments.iter().map(|pay| &pay.amount).sum();
+    let write_off = unwrap_or_return!(
+        crate::loan_write_off::query_write_off(&mut db, &id).await,
+        "Error querying write-off of the loan"
+    );
+    let recoveries = unwrap_or_return!(
+        crate::loan_write_off::query_recoveries(&mut db, &id).await,
+        "Error querying recoveries of the loan"
+    );
     let status = unwrap_or_return!(
         LoanStatus::new(&pay_amount, &loan.amount),
         "Unexpected: Aggregated payment is more than the loaded amount"
+    )
+    .with_write_off(&write_off);
+    let agreements = unwrap_or_return!(
+        crate::loan_agreement::query_agreements(&mut db, &id).await,
+        "Error querying loan agreements"
+    );
+    let collaterals = unwrap_or_return!(
+        crate::collateral::query_collaterals(&mut db, &id).await,
+        "Error querying collateral"
+    );
+    let guarantors = unwrap_or_return!(
+        crate::collateral::query_guarantors(&mut db, &id).await,
+        "Error querying guarantors"
     );
+    let schedule = match agreements.first() {
+        Some(agreement) => unwrap_or_return!(
+            crate::loan_agreement::query_schedule(&mut db, &id, agreement.version).await,
+            "Error querying loan schedule"
+        ),
+        None => vec![],
+    };
     let context = LoanProfileContext {
         loanID: id,
         subbranch: loan.subbranchName,
@@ -77,18 +182,126 @@ pub async fn loan_profile(mut db: Connection<BankManage>, id: String) -> Templat
             .map(|receive_loan| receive_loan.clientID)
             .collect(),
         payments: associated_payments
-            .into_iter()
+            .iter()
             .map(|payment| PaymentProfileContext {
+                paymentID: payment.paymentID.clone(),
                 date: payment.date.to_string(),
                 amount: payment.amount.to_string(),
+                reversalOf: payment.reversalOf.clone(),
+                reason: payment.reason.clone(),
+                reversed: associated_payments
+                    .iter()
+                    .any(|other| other.reversalOf.as_ref() == Some(&payment.paymentID)),
             })
             .collect(),
         status: status.to_string(),
         paid: pay_amount.to_string(),
+        loan_to_value: crate::collateral::loan_to_value(
+            &(&loan.amount - &pay_amount),
+            &collaterals,
+        )
+        .map(|ratio| ratio.to_string()),
+        collaterals: collaterals
+            .into_iter()
+            .map(|collateral| CollateralProfileContext {
+                collateralID: collateral.collateralID,
+                collateralType: collateral.collateralType,
+                description: collateral.description,
+                appraisedValue: collateral.appraisedValue.to_string(),
+                appraisalDate: collateral.appraisalDate.to_string(),
+                releaseDate: collateral.releaseDate.map(|date| date.to_string()),
+            })
+            .collect(),
+        guarantors: guarantors
+            .into_iter()
+            .map(|guarantee| guarantee.clientID)
+            .collect(),
         unpaid: (loan.amount - pay_amount).to_string(),
+        agreements: agreements
+            .into_iter()
+            .map(|agreement| AgreementProfileContext {
+                version: agreement.version.to_string(),
+                effectiveDate: agreement.effectiveDate.to_string(),
+                amount: agreement.amount.to_string(),
+                termMonths: agreement.termMonths.to_string(),
+                rate: agreement.rate.to_string(),
+                borrowers: agreement.borrowers,
+                reason: agreement.reason,
+            })
+            .collect(),
+        schedule: schedule
+            .into_iter()
+            .map(|installment| InstallmentProfileContext {
+                installment: installment.installment.to_string(),
+                dueDate: installment.dueDate.to_string(),
+                principal: installment.principal.to_string(),
+                interest: installment.interest.to_string(),
+            })
+            .collect(),
+        write_off: write_off.map(|write_off| WriteOffProfileContext {
+            date: write_off.date.to_string(),
+            amount: write_off.amount.to_string(),
+            reason: write_off.reason,
+            approverID: write_off.approverID,
+            recovered: recoveries
+                .iter()
+                .map(|recovery| &recovery.amount)
+                .sum::<BigDecimal>()
+                .to_string(),
+            recoveries: recoveries
+                .into_iter()
+                .map(|recovery| PaymentProfileContext {
+                    paymentID: recovery.recoveryID,
+                    date: recovery.date.to_string(),
+                    amount: recovery.amount.to_string(),
+                    reversalOf: None,
+                    reason: None,
+                    reversed: false,
+                })
+                .collect(),
+        }),
     };
     Template::render("loan-profile", &context)
 }
+
+/// The schedule of the latest agreement of the loan
+#[get("/export/loan-schedule?<id>&<format>")]
+pub async fn export_schedule(
+    mut db: Connection<BankManage>,
+    id: String,
+    format: ExportFormat,
+) -> Result<Export, Template> {
+    let agreements = unwrap_or!(
+        crate::loan_agreement::query_agreements(&mut db, &id).await,
+        e,
+        { return Err(error_template!(e, "Error querying loan agreements")) }
+    );
+    let schedule = match agreements.first() {
+        Some(agreement) => unwrap_or!(
+            crate::loan_agreement::query_schedule(&mut db, &id, agreement.version).await,
+            e,
+            { return Err(error_template!(e, "Error querying loan schedule")) }
+        ),
+        None => vec![],
+    };
+    let mut table = Table::new(
+        format!("Schedule of loan {id}"),
+        vec!["Installment", "Due date", "Principal", "Interest", "Total"],
+    );
+    for installment in schedule {
+        let total = &installment.principal + &installment.interest;
+        table.push(vec![
+            i64::from(installment.installment).into(),
+            installment.dueDate.to_string().into(),
+            installment.principal.into(),
+            installment.interest.into(),
+            total.into(),
+        ]);
+    }
+    table
+        .export(format)
+        .map_err(|e| error_template!(e, "Error exporting loan schedule"))
+}
 pub async fn query_loan(
     db: &mut Connection<BankManage>,
     id: &str,
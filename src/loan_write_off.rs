@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+
+use super::loan_profile::LoanStatus;
+use super::preludes::rocket_prelude::*;
+use crate::subbranch_manage::*;
+use crate::utility::{get_restriction, GenericError, Restriction};
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use bigdecimal::Zero;
+use chrono::Local;
+use sqlx::types::BigDecimal;
+use sqlx::Executor;
+
+#[derive(Serialize)]
+struct WriteOffContext<'a> {
+    id: String,
+    unpaid: String,
+    restriction: Restriction,
+    value: &'a WriteOffSubmit,
+    errors: FieldErrors,
+}
+
+#[get("/write-off/loan?<id>")]
+pub async fn get_write_off(mut db: Connection<BankManage>, id: String) -> Template {
+    let (loan, _, associated_payments) = unwrap_or_return!(
+        super::loan_profile::query_loan(&mut db, &id).await,
+        "Error querying loan"
+    );
+    let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+    Template::render(
+        "write-off-loan",
+        WriteOffContext {
+            id,
+            unpaid: (loan.amount - pay_amount).to_string(),
+            restriction: get_restriction(),
+            value: &<WriteOffSubmit as Default>::default(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct WriteOffSubmit {
+    reason: String,
+    approverID: String,
+}
+
+impl Validate for WriteOffSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("reason", &self.reason)
+            .required("approverID", &self.approverID)
+            .finish()
+    }
+}
+
+#[post("/write-off/loan?<id>", data = "<form>")]
+pub async fn submit_write_off(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, WriteOffSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error writing off loan: failed to receive form"),
+            )
+        }
+    };
+    let (loan, _, associated_payments) = match super::loan_profile::query_loan(&mut db, &id).await {
+        Ok(loan) => loan,
+        Err(e) => return (status, error_template!(e, "Error querying loan")),
+    };
+
+    // A write-off needs a reason and an approver
+    let errors = value.validate();
+    if !errors.is_empty() {
+        let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "write-off-loan",
+                WriteOffContext {
+                    id,
+                    unpaid: (loan.amount - pay_amount).to_string(),
+                    restriction: get_restriction(),
+                    value,
+                    errors,
+                },
+            ),
+        );
+    }
+    (
+        status,
+        write_off_loan(&mut db, id, value, loan, associated_payments).await,
+    )
+}
+
+async fn write_off_loan(
+    db: &mut Connection<BankManage>,
+    id: String,
+    value: &WriteOffSubmit,
+    loan: Loan,
+    associated_payments: Vec<Payment>,
+) -> Template {
+    let write_off = unwrap_or_return!(
+        query_write_off(db, &id).await,
+        "Error querying write-off of the loan"
+    );
+    let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+    let status = unwrap_or_return!(
+        LoanStatus::new(&pay_amount, &loan.amount),
+        "Unexpected: Aggregated payment is more than the loaded amount"
+    )
+    .with_write_off(&write_off);
+
+    // Rule 1: only loans with an outstanding amount can be written off
+    match status {
+        LoanStatus::WrittenOff => return error_template!("The loan is already written off"),
+        LoanStatus::Paid => {
+            return error_template!("The loan is paid, there is nothing to write off")
+        }
+        _ => (),
+    }
+
+    let subbranch = unwrap_or_return!(
+        query_subbranch(db, &loan.subbranchName).await,
+        "Fail to fetch information of the subbranch"
+    );
+    let outstanding = &loan.amount - &pay_amount;
+
+    // Updating the database
+    start_transaction!(db);
+    let new_asset = &subbranch.subbranchAsset - &outstanding;
+    unwrap_or!(
+        set_subbranch_asset(db, &subbranch.subbranchName, &new_asset).await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error updating the subbranch asset");
+        }
+    );
+    unwrap_or!(
+        sqlx::query(
+            "INSERT INTO loanwriteoff (loanID, date, amount, reason, approverID)VALUES(?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(Local::now().format("%Y-%m-%d").to_string())
+        .bind(&outstanding)
+        .bind(value.reason.trim())
+        .bind(value.approverID.trim())
+        .execute(&mut **db)
+        .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error recording the write-off");
+        }
+    );
+    commit!(db);
+    Template::render("write-off-loan-success", &HashMap::from([("id", id)]))
+}
+
+#[derive(Serialize)]
+struct RecoveryContext<'a> {
+    id: String,
+    written_off: String,
+    recovered: String,
+    restriction: Restriction,
+    value: &'a RecoverySubmit,
+    errors: FieldErrors,
+}
+
+#[get("/recover/loan?<id>")]
+pub async fn get_recovery(mut db: Connection<BankManage>, id: String) -> Template {
+    let write_off = match unwrap_or_return!(
+        query_write_off(&mut db, &id).await,
+        "Error querying write-off of the loan"
+    ) {
+        Some(write_off) => write_off,
+        None => return error_template!("Only written-off loans accept recoveries"),
+    };
+    let recoveries = unwrap_or_return!(
+        query_recoveries(&mut db, &id).await,
+        "Error querying recoveries of the loan"
+    );
+    Template::render(
+        "recover-loan",
+        RecoveryContext {
+            id,
+            written_off: write_off.amount.to_string(),
+            recovered: recoveries
+                .iter()
+                .map(|recovery| &recovery.amount)
+                .sum::<BigDecimal>()
+                .to_string(),
+            restriction: get_restriction(),
+            value: &<RecoverySubmit as Default>::default(),
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct RecoverySubmit {
+    amount: String,
+}
+
+impl Validate for RecoverySubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("amount", &self.amount)
+            .pattern("amount", &self.amount, "amount")
+            .finish()
+    }
+}
+
+#[post("/recover/loan?<id>", data = "<form>")]
+pub async fn submit_recovery(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, RecoverySubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error booking recovery: failed to receive form"),
+            )
+        }
+    };
+    let (loan, _, _) = match super::loan_profile::query_loan(&mut db, &id).await {
+        Ok(loan) => loan,
+        Err(e) => return (status, error_template!(e, "Error querying loan")),
+    };
+    let write_off = match query_write_off(&mut db, &id).await {
+        Ok(Some(write_off)) => write_off,
+        Ok(None) => {
+            return (
+                status,
+                error_template!("Only written-off loans accept recoveries"),
+            )
+        }
+        Err(e) => {
+            return (
+                status,
+                error_template!(e, "Error querying write-off of the loan"),
+            )
+        }
+    };
+    let recovered: BigDecimal = match query_recoveries(&mut db, &id).await {
+        Ok(recoveries) => recoveries.iter().map(|recovery| &recovery.amount).sum(),
+        Err(e) => {
+            return (
+                status,
+                error_template!(e, "Error querying recoveries of the loan"),
+            )
+        }
+    };
+
+    // Rule 1: the recovery should be a positive amount
+    let mut errors = value.validate();
+    let new_recovery = value.amount.parse::<BigDecimal>().ok();
+    if errors.is_empty()
+        && new_recovery
+            .as_ref()
+            .map_or(true, |amount| *amount <= BigDecimal::zero())
+    {
+        errors.insert(
+            "amount".to_string(),
+            "The recovery should be positive".to_string(),
+        );
+    }
+    let new_recovery = match new_recovery {
+        Some(new_recovery) if errors.is_empty() => new_recovery,
+        _ => {
+            return (
+                Status::UnprocessableEntity,
+                Template::render(
+                    "recover-loan",
+                    RecoveryContext {
+                        id,
+                        written_off: write_off.amount.to_string(),
+                        recovered: recovered.to_string(),
+                        restriction: get_restriction(),
+                        value,
+                        errors,
+                    },
+                ),
+            )
+        }
+    };
+
+    // Rule 2: the recoveries should not outnumber the written-off amount
+    if &new_recovery + &recovered > write_off.amount {
+        return (status, error_template!(format!("The recovery outnumbers the written-off amount. New recovery is {new_recovery}, previous recovery is {recovered}, the written-off amount is {written_off}", written_off=write_off.amount)));
+    }
+
+    let subbranch = match query_subbranch(&mut db, &loan.subbranchName).await {
+        Ok(subbranch) => subbranch,
+        Err(e) => {
+            return (
+                status,
+                error_template!(e, "Fail to fetch information of the subbranch"),
+            )
+        }
+    };
+
+    // Updating the database
+    start_transaction!(db);
+    let new_asset = &subbranch.subbranchAsset + &new_recovery;
+    if let Err(e) = set_subbranch_asset(&mut db, &subbranch.subbranchName, &new_asset).await {
+        rollback!(db);
+        return (
+            status,
+            error_template!(e, "Error updating the subbranch asset"),
+        );
+    }
+    if let Err(e) =
+        sqlx::query("INSERT INTO loanrecovery (recoveryID, loanID, date, amount)VALUES(?, ?, ?, ?)")
+            .bind(uuid::Uuid::new_v4().to_string())
+            .bind(&id)
+            .bind(Local::now().format("%Y-%m-%d").to_string())
+            .bind(&new_recovery)
+            .execute(&mut *db)
+            .await
+    {
+        rollback!(db);
+        return (status, error_template!(e, "Error recording the recovery"));
+    }
+    commit!(db);
+    (
+        status,
+        Template::render("recover-loan-success", &HashMap::from([("id", id)])),
+    )
+}
+
+pub async fn query_write_off(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Option<LoanWriteOff>, GenericError> {
+    Ok(sqlx::query_as!(
+        LoanWriteOff,
+        "SELECT * FROM loanwriteoff WHERE loanID=?",
+        id
+    )
+    .fetch_optional(&mut **db)
+    .await?)
+}
+
+pub async fn query_recoveries(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Vec<LoanRecovery>, GenericError> {
+    Ok(sqlx::query_as!(
+        LoanRecovery,
+        "SELECT * FROM loanrecovery WHERE loanID=? ORDER BY date",
+        id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
@@ -10,19 +10,42 @@ pub mod models;
 mod new_client;
 mod preludes;
 mod search;
+mod search_filter;
+mod search_index;
+mod search_query;
 use preludes::rocket_prelude::*;
 mod account_manage;
 mod account_profile;
+mod chart;
+mod client_contact;
+mod client_dependency;
+mod client_note;
+mod client_portfolio;
 mod client_profile;
+mod collateral;
+mod credit_limit;
+mod dashboard;
 mod delete_payment;
 mod edit_account;
 mod edit_client;
+mod export;
+mod kyc;
+mod loan_agreement;
 mod loan_profile;
+mod loan_write_off;
+mod merge_client;
 mod new_account;
 mod new_loan;
 mod new_payment;
+mod relationship_manager;
+mod reporting_period;
+mod restructure_loan;
+mod reverse_payment;
+mod saved_search;
+mod service_type;
 mod subbranch_manage;
 mod utility;
+mod validation;
 
 #[launch]
 fn rocket() -> rocket::Rocket<rocket::Build> {
@@ -32,12 +55,40 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
             routes![
                 index,
                 search::search,
+                search::export_search,
+                saved_search::get_new_saved_search,
+                saved_search::submit_saved_search,
+                saved_search::mark_viewed,
+                saved_search::delete_saved_search,
                 new_client::submit,
                 new_client::new_client,
                 client_profile::client_profile,
                 edit_client::get_edit_client,
                 edit_client::act_edit_client,
                 edit_client::delete_client,
+                client_contact::get_new_contact,
+                client_contact::submit_new_contact,
+                client_contact::get_edit_contact,
+                client_contact::submit_edit_contact,
+                client_contact::delete_contact,
+                client_note::get_new_note,
+                client_note::submit_new_note,
+                client_note::get_edit_note,
+                client_note::submit_edit_note,
+                client_note::delete_note,
+                client_dependency::close_idle_accounts,
+                client_dependency::archive_client,
+                client_dependency::restore_client,
+                kyc::get_new_document,
+                kyc::submit_document,
+                kyc::download_document,
+                kyc::delete_document,
+                merge_client::duplicates,
+                merge_client::get_merge_client,
+                merge_client::submit,
+                relationship_manager::get_reassign_clients,
+                relationship_manager::submit_reassign_clients,
+                relationship_manager::export_clients,
                 new_account::new_account,
                 new_account::submit,
                 account_profile::account_profile,
@@ -48,26 +99,49 @@ fn rocket() -> rocket::Rocket<rocket::Build> {
                 new_loan::get_new_loan,
                 new_loan::submit,
                 loan_profile::loan_profile,
+                loan_profile::export_schedule,
                 new_payment::get_new_loan,
                 new_payment::submit,
                 delete_payment::delete_payment,
+                reverse_payment::get_reverse_payment,
+                reverse_payment::submit,
+                restructure_loan::get_restructure_loan,
+                restructure_loan::submit,
+                loan_write_off::get_write_off,
+                loan_write_off::submit_write_off,
+                loan_write_off::get_recovery,
+                loan_write_off::submit_recovery,
+                credit_limit::credit_limits,
+                credit_limit::submit,
+                credit_limit::delete_limit,
+                collateral::get_new_collateral,
+                collateral::submit_collateral,
+                collateral::release_collateral,
+                collateral::get_new_guarantor,
+                collateral::submit_guarantor,
+                collateral::delete_guarantor,
                 subbranch_manage::subbranch_profile,
+                subbranch_manage::export_statistics,
+                dashboard::dashboard,
             ],
         )
         .attach(Template::fairing())
         .attach(BankManage::init())
+        .manage(search_index::SearchIndex::default())
+        .attach(search_index::IndexInvalidation)
         .mount("/", FileServer::from(relative!("/static")))
 }
 
 #[derive(Serialize)]
 struct IndexContext {
     options: Vec<String>,
+    saved_searches: Vec<saved_search::SavedSearchView>,
 }
 
 #[macro_export]
 macro_rules! get_attr_list_of {
     (Client) => {
-        get_attr_list!(Client; clientID,clientName,clientAddr,contactName)
+        get_attr_list!(Client; clientID,clientName,clientAddr)
     };
     (Account)=>{
         get_attr_list!(Account; accountID)
@@ -76,16 +150,51 @@ macro_rules! get_attr_list_of {
         get_attr_list!(Loan; loanID)
     };
     {Subbranch}=>{
-        get_attr_list!(Subbranch; subbranchName)
+        get_attr_list!(Subbranch; subbranchName,city)
+    };
+    {ClientContact}=>{
+        get_attr_list!(ClientContact; contactName,contactTel,contactEmail)
+    };
+    {ClientNote}=>{
+        get_attr_list!(ClientNote; content)
+    };
+    {Employee}=>{
+        get_attr_list!(Employee; employeeID,employeeTel,employeeAddr)
+    };
+    {Department}=>{
+        get_attr_list!(Department; departmentID,departmentName,departmentType)
+    };
+    {Payment}=>{
+        get_attr_list!(Payment; paymentID,loanID,date,amount)
     };
 }
 
 #[get("/")]
-fn index() -> Template {
+async fn index(mut db: Connection<BankManage>) -> Template {
     let mut options = vec![];
     options.extend(get_attr_list_of!(Client).into_iter());
+    options.extend(get_attr_list_of!(ClientContact).into_iter());
+    options.extend(get_attr_list_of!(ClientNote).into_iter());
     options.extend(get_attr_list_of!(Account).into_iter());
     options.extend(get_attr_list_of!(Loan).into_iter());
     options.extend(get_attr_list_of!(Subbranch).into_iter());
-    Template::render("index", &IndexContext { options })
+    options.extend(get_attr_list_of!(Employee).into_iter());
+    options.extend(get_attr_list_of!(Department).into_iter());
+    options.extend(get_attr_list_of!(Payment).into_iter());
+    let saved_searches = saved_search::query_saved_searches(&mut db)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Error querying saved searches: {e}");
+            vec![]
+        })
+        .into_iter()
+        .map(saved_search::SavedSearchView::from)
+        .collect();
+    Template::render(
+        "index",
+        &IndexContext {
+            options,
+            saved_searches,
+        },
+    )
 }
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use super::preludes::rocket_prelude::*;
+use crate::client_contact::query_contacts;
+use crate::client_profile::query_client_by_id;
+use crate::utility::GenericError;
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use chrono::Local;
+use sqlx::Executor;
+
+/// Lowercase letters and digits only, so that spacing, case and punctuation do not tell records apart
+fn normalize_text(value: Option<&str>) -> Option<String> {
+    let normalized: String = value
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if normalized.is_empty() {
+        None
+    } else {
+        Some(normalized)
+    }
+}
+
+/// Digits only, without the country code
+fn normalize_tel(value: Option<&str>) -> Option<String> {
+    let digits: String = value
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect();
+    let digits = match digits.strip_prefix("86") {
+        Some(local) if local.len() == 11 => local.to_string(),
+        _ => digits,
+    };
+    if digits.is_empty() {
+        None
+    } else {
+        Some(digits)
+    }
+}
+
+fn normalize_email(value: Option<&str>) -> Option<String> {
+    let email = value.unwrap_or_default().trim().to_lowercase();
+    if email.is_empty() {
+        None
+    } else {
+        Some(email)
+    }
+}
+
+fn same<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+    a.is_some() && a == b
+}
+
+/// Returns the criteria on which two clients agree, if there are enough of them to suspect a duplicate.
+/// At least two of name, telephone, address and contact should match.
+fn match_criteria(
+    a: &Client,
+    a_contacts: &[ClientContact],
+    b: &Client,
+    b_contacts: &[ClientContact],
+) -> Option<Vec<&'static str>> {
+    let mut criteria = vec![];
+    if same(
+        normalize_text(a.clientName.as_deref()),
+        normalize_text(b.clientName.as_deref()),
+    ) {
+        criteria.push("name");
+    }
+    if same(
+        normalize_tel(a.clientTel.as_deref()),
+        normalize_tel(b.clientTel.as_deref()),
+    ) {
+        criteria.push("tel");
+    }
+    if same(
+        normalize_text(a.clientAddr.as_deref()),
+        normalize_text(b.clientAddr.as_deref()),
+    ) {
+        criteria.push("address");
+    }
+    if a_contacts.iter().any(|a_contact| {
+        b_contacts.iter().any(|b_contact| {
+            same(
+                normalize_text(Some(&a_contact.contactName)),
+                normalize_text(Some(&b_contact.contactName)),
+            ) || same(
+                normalize_tel(a_contact.contactTel.as_deref()),
+                normalize_tel(b_contact.contactTel.as_deref()),
+            ) || same(
+                normalize_email(a_contact.contactEmail.as_deref()),
+                normalize_email(b_contact.contactEmail.as_deref()),
+            )
+        })
+    }) {
+        criteria.push("contact");
+    }
+    if criteria.len() >= 2 {
+        Some(criteria)
+    } else {
+        None
+    }
+}
+
+#[derive(Serialize)]
+struct DuplicateView {
+    first: Client,
+    second: Client,
+    criteria: String,
+}
+
+#[derive(Serialize)]
+struct DuplicatesContext {
+    duplicates: Vec<DuplicateView>,
+}
+
+#[get("/duplicates")]
+pub async fn duplicates(mut db: Connection<BankManage>) -> Template {
+    let clients = unwrap_or_return!(
+        sqlx::query_as!(Client, "SELECT * FROM client ORDER BY clientID")
+            .fetch_all(&mut *db)
+            .await,
+        "Error querying clients"
+    );
+    let mut contacts: HashMap<String, Vec<ClientContact>> = HashMap::new();
+    for contact in unwrap_or_return!(
+        sqlx::query_as!(ClientContact, "SELECT * FROM clientcontact")
+            .fetch_all(&mut *db)
+            .await,
+        "Error querying contacts"
+    ) {
+        contacts
+            .entry(contact.clientID.clone())
+            .or_default()
+            .push(contact);
+    }
+    let contacts_of = |client: &Client| {
+        contacts
+            .get(&client.clientID)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    };
+    let mut duplicates = vec![];
+    for (i, first) in clients.iter().enumerate() {
+        for second in &clients[i + 1..] {
+            if let Some(criteria) =
+                match_criteria(first, contacts_of(first), second, contacts_of(second))
+            {
+                duplicates.push(DuplicateView {
+                    first: first.clone(),
+                    second: second.clone(),
+                    criteria: criteria.join(", "),
+                });
+            }
+        }
+    }
+    Template::render("duplicate-clients", DuplicatesContext { duplicates })
+}
+
+#[derive(Serialize)]
+struct MergeClientContext {
+    survivor: Client,
+    survivor_contacts: Vec<ClientContact>,
+    duplicate: Client,
+    duplicate_contacts: Vec<ClientContact>,
+}
+
+#[get("/merge/client?<survivor>&<duplicate>")]
+pub async fn get_merge_client(
+    mut db: Connection<BankManage>,
+    survivor: String,
+    duplicate: String,
+) -> Template {
+    let survivor = unwrap_or_return!(
+        query_client_by_id(&mut db, survivor).await,
+        "Error querying the surviving client"
+    );
+    let duplicate = unwrap_or_return!(
+        query_client_by_id(&mut db, duplicate).await,
+        "Error querying the duplicate client"
+    );
+    let survivor_contacts = unwrap_or_return!(
+        query_contacts(&mut db, &survivor.clientID).await,
+        "Error querying contacts of the surviving client"
+    );
+    let duplicate_contacts = unwrap_or_return!(
+        query_contacts(&mut db, &duplicate.clientID).await,
+        "Error querying contacts of the duplicate client"
+    );
+    Template::render(
+        "merge-client",
+        MergeClientContext {
+            survivor,
+            survivor_contacts,
+            duplicate,
+            duplicate_contacts,
+        },
+    )
+}
+
+#[post("/merge/client?<survivor>&<duplicate>")]
+pub async fn submit(
+    mut db: Connection<BankManage>,
+    survivor: String,
+    duplicate: String,
+) -> Template {
+    // Rule 1: a client cannot be merged into itself
+    if survivor == duplicate {
+        return error_template!("A client cannot be merged into itself");
+    }
+    unwrap_or_return!(
+        query_client_by_id(&mut db, survivor.clone()).await,
+        "Error querying the surviving client"
+    );
+    unwrap_or_return!(
+        query_client_by_id(&mut db, duplicate.clone()).await,
+        "Error querying the duplicate client"
+    );
+
+    // Updating the database
+    start_transaction!(db);
+    unwrap_or!(merge_clients(&mut db, &survivor, &duplicate).await, e, {
+        rollback!(db);
+        return error_template!(e, "Error merging clients");
+    });
+    commit!(db);
+    Template::render("update-client-success", &HashMap::from([("id", survivor)]))
+}
+
+#[derive(Debug)]
+pub struct MergeConflictError {
+    pub info: String,
+}
+
+impl std::fmt::Display for MergeConflictError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for MergeConflictError {}
+
+/// Moves the relations and documents of `duplicate` to `survivor`, deletes `duplicate` and keeps its ID as an alias of `survivor`
+async fn merge_clients(
+    db: &mut Connection<BankManage>,
+    survivor: &str,
+    duplicate: &str,
+) -> Result<(), GenericError> {
+    merge_account_management(db, survivor, duplicate).await?;
+
+    // Relations both clients already have are kept once
+    for table in ["own", "receiveloan"] {
+        let key = if table == "own" {
+            "accountID"
+        } else {
+            "loanID"
+        };
+        sqlx::query(&format!(
+            "DELETE FROM {table} WHERE clientID=? and {key} IN
+                (SELECT {key} FROM (SELECT {key} FROM {table} WHERE clientID=?) AS survivor_relation)"
+        ))
+        .bind(duplicate)
+        .bind(survivor)
+        .execute(&mut **db)
+        .await?;
+        sqlx::query(&format!("UPDATE {table} SET clientID=? WHERE clientID=?"))
+            .bind(survivor)
+            .bind(duplicate)
+            .execute(&mut **db)
+            .await?;
+    }
+
+    // A borrower cannot guarantee its own loan
+    if let Some(row) = sqlx::query(
+        "SELECT guarantee.loanID FROM guarantee JOIN receiveloan ON guarantee.loanID=receiveloan.loanID
+        WHERE receiveloan.clientID=? and guarantee.clientID IN (?, ?)",
+    )
+    .bind(survivor)
+    .bind(survivor)
+    .bind(duplicate)
+    .fetch_optional(&mut **db)
+    .await?
+    {
+        return Err(Box::new(MergeConflictError {
+            info: format!(
+                "The merged client would both borrow and guarantee loan {}",
+                row.try_get::<'_, String, _>(0)?
+            ),
+        }));
+    }
+    sqlx::query(
+        "DELETE FROM guarantee WHERE clientID=? and loanID IN
+            (SELECT loanID FROM (SELECT loanID FROM guarantee WHERE clientID=?) AS survivor_guarantee)",
+    )
+    .bind(duplicate)
+    .bind(survivor)
+    .execute(&mut **db)
+    .await?;
+    sqlx::query("UPDATE guarantee SET clientID=? WHERE clientID=?")
+        .bind(survivor)
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+
+    // The limit of the surviving client prevails
+    sqlx::query(
+        "DELETE FROM creditlimit WHERE scope=? and target=? and EXISTS
+            (SELECT * FROM (SELECT target FROM creditlimit WHERE scope=? and target=?) AS survivor_limit)",
+    )
+    .bind(crate::credit_limit::SCOPE_CLIENT)
+    .bind(duplicate)
+    .bind(crate::credit_limit::SCOPE_CLIENT)
+    .bind(survivor)
+    .execute(&mut **db)
+    .await?;
+    sqlx::query("UPDATE creditlimit SET target=? WHERE scope=? and target=?")
+        .bind(survivor)
+        .bind(crate::credit_limit::SCOPE_CLIENT)
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+
+    // Details missing on the surviving client are taken from the duplicate
+    let duplicate_client = query_client_by_id(db, duplicate.to_string()).await?;
+    sqlx::query(
+        "UPDATE client SET
+            employeeID=COALESCE(employeeID, ?),
+            clientTel=COALESCE(clientTel, ?),
+            clientAddr=COALESCE(clientAddr, ?)
+        WHERE clientID=?",
+    )
+    .bind(duplicate_client.employeeID)
+    .bind(duplicate_client.clientTel)
+    .bind(duplicate_client.clientAddr)
+    .bind(survivor)
+    .execute(&mut **db)
+    .await?;
+
+    // The contacts of the duplicate join those of the surviving client, whose primary contact is kept
+    sqlx::query("UPDATE clientcontact SET clientID=?, isPrimary=false WHERE clientID=?")
+        .bind(survivor)
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+    for table in ["clientdocument", "clientnote"] {
+        sqlx::query(&format!("UPDATE {table} SET clientID=? WHERE clientID=?"))
+            .bind(survivor)
+            .bind(duplicate)
+            .execute(&mut **db)
+            .await?;
+    }
+    crate::service_type::refresh_service_type(db, survivor).await?;
+    sqlx::query("UPDATE clientalias SET clientID=? WHERE clientID=?")
+        .bind(survivor)
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("DELETE FROM clientarchive WHERE clientID=?")
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("DELETE FROM client WHERE clientID=?")
+        .bind(duplicate)
+        .execute(&mut **db)
+        .await?;
+    sqlx::query("INSERT INTO clientalias (aliasID, clientID, mergeDate) VALUES (?, ?, ?)")
+        .bind(duplicate)
+        .bind(survivor)
+        .bind(Local::now().format("%Y-%m-%d").to_string())
+        .execute(&mut **db)
+        .await?;
+    Ok(())
+}
+
+/// A client holds at most one saving account and one checking account in each subbranch,
+/// so the merge is refused if both clients hold one of the same type in the same subbranch
+async fn merge_account_management(
+    db: &mut Connection<BankManage>,
+    survivor: &str,
+    duplicate: &str,
+) -> Result<(), GenericError> {
+    let duplicate_entries = sqlx::query_as!(
+        AccountManagement,
+        "SELECT * FROM accountmanagement WHERE clientID=?",
+        duplicate
+    )
+    .fetch_all(&mut **db)
+    .await?;
+    for duplicate_entry in duplicate_entries {
+        let survivor_entry = sqlx::query_as!(
+            AccountManagement,
+            "SELECT * FROM accountmanagement WHERE subbranchName=? and clientID=?",
+            duplicate_entry.subbranchName,
+            survivor
+        )
+        .fetch_optional(&mut **db)
+        .await?;
+        let survivor_entry = match survivor_entry {
+            Some(survivor_entry) => survivor_entry,
+            None => {
+                sqlx::query(
+                    "UPDATE accountmanagement SET clientID=? WHERE subbranchName=? and clientID=?",
+                )
+                .bind(survivor)
+                .bind(&duplicate_entry.subbranchName)
+                .bind(duplicate)
+                .execute(&mut **db)
+                .await?;
+                continue;
+            }
+        };
+
+        macro_rules! merge_account_id {
+            ($attr_name: ident, $account_type: literal) => {
+                match (&survivor_entry.$attr_name, &duplicate_entry.$attr_name) {
+                    (Some(a), Some(b)) if a != b => {
+                        return Err(Box::new(MergeConflictError {
+                            info: format!(
+                                "Both clients hold a {} account in subbranch {}",
+                                $account_type, duplicate_entry.subbranchName
+                            ),
+                        }))
+                    }
+                    (Some(a), _) => Some(a.clone()),
+                    (None, b) => b.clone(),
+                }
+            };
+        }
+        let savingAccountID = merge_account_id!(savingAccountID, "saving");
+        let checkingAccountID = merge_account_id!(checkingAccountID, "checking");
+
+        sqlx::query(
+            "UPDATE accountmanagement SET savingAccountID=?, checkingAccountID=? WHERE subbranchName=? and clientID=?",
+        )
+        .bind(savingAccountID)
+        .bind(checkingAccountID)
+        .bind(&duplicate_entry.subbranchName)
+        .bind(survivor)
+        .execute(&mut **db)
+        .await?;
+        sqlx::query("DELETE FROM accountmanagement WHERE subbranchName=? and clientID=?")
+            .bind(&duplicate_entry.subbranchName)
+            .bind(duplicate)
+            .execute(&mut **db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The ID of the client `id` was merged into, or `id` itself if it is not an alias
+pub async fn resolve_client_alias(
+    db: &mut Connection<BankManage>,
+    id: String,
+) -> Result<String, GenericError> {
+    Ok(
+        sqlx::query_as!(ClientAlias, "SELECT * FROM clientalias WHERE aliasID=?", id)
+            .fetch_optional(&mut **db)
+            .await?
+            .map(|alias| alias.clientID)
+            .unwrap_or(id),
+    )
+}
+
+pub async fn query_aliases(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Vec<ClientAlias>, GenericError> {
+    Ok(sqlx::query_as!(
+        ClientAlias,
+        "SELECT * FROM clientalias WHERE clientID=? ORDER BY mergeDate",
+        id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
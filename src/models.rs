@@ -1,17 +1,13 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::Serialize;
 
-#[derive(Default, Serialize, PartialEq, Eq, Hash, sqlx::FromRow)]
+#[derive(Default, Serialize, PartialEq, Eq, Hash, Clone, sqlx::FromRow)]
 pub struct Client {
     pub clientID: String,
     pub employeeID: Option<String>,
     pub clientName: Option<String>,
     pub clientTel: Option<String>,
     pub clientAddr: Option<String>,
-    pub contactName: Option<String>,
-    pub contactTel: Option<String>,
-    pub contactEmail: Option<String>,
-    pub contactRelationship: Option<String>,
     pub serviceType: Option<String>,
 }
 
@@ -56,9 +52,12 @@ pub struct Loan {
 
 #[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone, Hash)]
 pub struct Payment {
+    pub paymentID: String,
     pub loanID: String,
     pub date: NaiveDate,
     pub amount: sqlx::types::BigDecimal,
+    pub reversalOf: Option<String>,
+    pub reason: Option<String>,
 }
 
 #[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
@@ -73,3 +72,165 @@ pub struct Subbranch {
     pub city: String,
     pub subbranchAsset: sqlx::types::BigDecimal,
 }
+
+#[derive(PartialEq, sqlx::FromRow, Debug, Clone)]
+pub struct LoanAgreement {
+    pub loanID: String,
+    pub version: i32,
+    pub effectiveDate: NaiveDate,
+    pub amount: sqlx::types::BigDecimal,
+    pub termMonths: i32,
+    pub rate: f32,
+    pub borrowers: String,
+    pub reason: Option<String>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct LoanInstallment {
+    pub loanID: String,
+    pub version: i32,
+    pub installment: i32,
+    pub dueDate: NaiveDate,
+    pub principal: sqlx::types::BigDecimal,
+    pub interest: sqlx::types::BigDecimal,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct LoanWriteOff {
+    pub loanID: String,
+    pub date: NaiveDate,
+    pub amount: sqlx::types::BigDecimal,
+    pub reason: String,
+    pub approverID: String,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone, Hash)]
+pub struct LoanRecovery {
+    pub recoveryID: String,
+    pub loanID: String,
+    pub date: NaiveDate,
+    pub amount: sqlx::types::BigDecimal,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct CreditLimit {
+    pub scope: String,
+    pub target: String,
+    pub amount: sqlx::types::BigDecimal,
+    pub sharedAmongBorrowers: bool,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct Collateral {
+    pub collateralID: String,
+    pub loanID: String,
+    pub collateralType: String,
+    pub description: Option<String>,
+    pub appraisedValue: sqlx::types::BigDecimal,
+    pub appraisalDate: NaiveDate,
+    pub releaseDate: Option<NaiveDate>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct Guarantee {
+    pub loanID: String,
+    pub clientID: String,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct ClientAlias {
+    pub aliasID: String,
+    pub clientID: String,
+    pub mergeDate: NaiveDate,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct ClientArchive {
+    pub clientID: String,
+    pub archiveDate: NaiveDate,
+    pub reason: Option<String>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct ClientDocument {
+    pub documentID: String,
+    pub clientID: String,
+    pub documentType: String,
+    pub fileName: String,
+    pub contentType: String,
+    pub size: i64,
+    pub sha256: String,
+    pub uploadDate: NaiveDate,
+    pub expiryDate: Option<NaiveDate>,
+}
+
+#[derive(Default, Serialize, PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct ClientContact {
+    pub contactID: String,
+    pub clientID: String,
+    pub contactName: String,
+    pub contactTel: Option<String>,
+    pub contactEmail: Option<String>,
+    pub contactRelationship: Option<String>,
+    pub isPrimary: bool,
+}
+
+#[derive(Default, PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct Employee {
+    pub employeeID: String,
+    pub subbranchName: Option<String>,
+    pub departmentID: Option<String>,
+    pub employeeTel: Option<String>,
+    pub employeeAddr: Option<String>,
+    pub employmentCommenceDate: Option<NaiveDate>,
+}
+
+#[derive(Default, Serialize, PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct Department {
+    pub departmentID: String,
+    pub departmentName: Option<String>,
+    pub departmentType: Option<String>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct ClientNote {
+    pub noteID: String,
+    pub clientID: String,
+    pub authorID: String,
+    pub noteType: String,
+    pub content: String,
+    pub createdAt: NaiveDateTime,
+    pub updatedAt: Option<NaiveDateTime>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct SavedSearch {
+    pub searchID: String,
+    pub searchName: String,
+    pub employeeID: String,
+    pub query: String,
+    pub watch: bool,
+    pub createdAt: NaiveDateTime,
+    pub lastViewedAt: Option<NaiveDateTime>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct SavedSearchResult {
+    pub searchID: String,
+    pub resultKey: String,
+    pub fingerprint: String,
+    pub addedAt: NaiveDateTime,
+    pub changedAt: NaiveDateTime,
+    pub removedAt: Option<NaiveDateTime>,
+}
+
+#[derive(PartialEq, Eq, sqlx::FromRow, Debug, Clone)]
+pub struct AccountMovement {
+    pub movementID: String,
+    pub accountID: String,
+    pub accountType: String,
+    pub subbranchName: String,
+    pub date: NaiveDate,
+    pub kind: String,
+    pub amount: sqlx::types::BigDecimal,
+}
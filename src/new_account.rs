@@ -2,7 +2,8 @@ use std::collections::HashMap;
 
 use super::preludes::rocket_prelude::*;
 use crate::{account_manage::insert::*, start_transaction};
-use crate::{commit, error_template, rollback};
+use crate::{commit, error_template, rollback, validation::FormContext};
+use crate::{kyc::check_kyc, utility::get_client_id_list_from_input};
 use sqlx::Executor;
 
 #[get("/new/account")]
@@ -21,6 +22,24 @@ pub async fn submit(
     let template;
     match form.value {
         Some(ref submission) => {
+            let context = FormContext::new(submission);
+            if !context.errors.is_empty() {
+                return (
+                    Status::UnprocessableEntity,
+                    Template::render("new-account", &context),
+                );
+            }
+            let client_ids: Vec<String> = match get_client_id_list_from_input(&submission.clientIDs)
+            {
+                Ok(client_ids) => client_ids,
+                Err(e) => return (form.context.status(), error_template!(e, "Invalid input")),
+            };
+            if let Err(e) = check_kyc(&mut db, &client_ids).await {
+                return (
+                    form.context.status(),
+                    error_template!(e, "Account rejected"),
+                );
+            }
             start_transaction!(db);
             match add_new_account_and_own(&mut db, submission).await {
                 Ok(id) => {
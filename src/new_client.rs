@@ -1,9 +1,12 @@
 use crate::{
-    error_template,
-    utility::{validate_string_value, GenericError, Restriction},
+    client_contact::add_contact,
+    commit, error_template, rollback, start_transaction,
+    utility::{validate_client_id, validate_string_value, GenericError, Restriction},
+    validation::{FieldErrors, FormContext, Validate, Validator},
 };
 
 use super::preludes::rocket_prelude::*;
+use sqlx::Executor;
 
 #[derive(Debug, FromForm, Default, Serialize)]
 pub struct Submit {
@@ -16,7 +19,22 @@ pub struct Submit {
     contactrelation: String,
     contacttel: String,
     employeeID: String,
-    servicetype: String,
+}
+
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("name", &self.name)
+            .pattern("name", &self.name, "name")
+            .required("clientID", &self.clientID)
+            .check("clientID", validate_client_id(&self.clientID))
+            .pattern("tel", &self.tel, "tel")
+            .required("contactname", &self.contactname)
+            .pattern("contactname", &self.contactname, "name")
+            .pattern("contactemail", &self.contactemail, "email")
+            .pattern("contacttel", &self.contacttel, "tel")
+            .finish()
+    }
 }
 
 #[derive(Serialize)]
@@ -34,37 +52,29 @@ pub fn new_client() -> Template {
     )
 }
 
-// NOTE the attributes of new_client should not be None
+// NOTE the attributes of new_client except serviceType should not be None
 async fn add_client(
     db: &mut Connection<BankManage>,
     new_client: Client,
+    primary_contact: ClientContact,
 ) -> Result<(), GenericError> {
     sqlx::query(&format!(
         "INSERT INTO client (clientID,
             clientName,
             clientTel,
             clientAddr,
-            contactName,
-            contactTel,
-            contactEmail,
-            contactRelationship,
-            employeeID,
-            serviceType
+            employeeID
             ) VALUES
-            ({}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+            ({}, {}, {}, {}, {})",
         validate_string_value(&new_client.clientID),
         validate_string_value(&new_client.clientName.unwrap()),
         validate_string_value(&new_client.clientTel.unwrap()),
         validate_string_value(&new_client.clientAddr.unwrap()),
-        validate_string_value(&new_client.contactName.unwrap()),
-        validate_string_value(&new_client.contactTel.unwrap()),
-        validate_string_value(&new_client.contactEmail.unwrap()),
-        validate_string_value(&new_client.contactRelationship.unwrap()),
         validate_string_value(&new_client.employeeID.unwrap()),
-        validate_string_value(&new_client.serviceType.unwrap()),
     ))
     .execute(&mut **db)
     .await?;
+    add_contact(db, &primary_contact).await?;
     Ok(())
 }
 
@@ -75,21 +85,66 @@ pub async fn submit(
 ) -> (Status, Template) {
     let template = match form.value {
         Some(ref submission) => {
-            let new_client = Client {
-                clientID: submission.clientID.clone(),
-                clientName: Some(submission.name.clone()),
-                clientTel: Some(submission.tel.clone()),
-                clientAddr: Some(submission.address.clone()),
-                contactName: Some(submission.contactname.clone()),
-                employeeID: Some(submission.employeeID.clone()),
-                contactTel: Some(submission.contacttel.clone()),
-                contactEmail: Some(submission.contactemail.clone()),
-                contactRelationship: Some(submission.contactrelation.clone()),
-                serviceType: Some(submission.servicetype.clone()),
-            };
-            match add_client(&mut db, new_client).await {
-                Ok(_) => Template::render("new-client-success", &form.context),
+            let mut context = FormContext::new(submission);
+            if let Err(e) =
+                crate::relationship_manager::check_employee(&mut db, &submission.employeeID).await
+            {
+                context
+                    .errors
+                    .insert("employeeID".to_string(), e.to_string());
+            }
+            if !context.errors.is_empty() {
+                return (
+                    Status::UnprocessableEntity,
+                    Template::render("new-client", &context),
+                );
+            }
+            match validate_client_id(&submission.clientID) {
                 Err(e) => error_template!(e, "Error adding client"),
+                Ok(clientID) => {
+                    // The ID of a merged client stays reserved for the client it was merged into
+                    match crate::merge_client::resolve_client_alias(&mut db, clientID.clone()).await
+                    {
+                        Ok(survivor) if survivor != clientID => error_template!(format!(
+                            "Client {clientID} was merged into client {survivor}"
+                        )),
+                        Err(e) => error_template!(e, "Error adding client"),
+                        Ok(_) => {
+                            let primary_contact = ClientContact {
+                                contactID: uuid::Uuid::new_v4().to_string(),
+                                clientID: clientID.clone(),
+                                contactName: submission.contactname.clone(),
+                                contactTel: Some(submission.contacttel.clone())
+                                    .filter(|tel| !tel.is_empty()),
+                                contactEmail: Some(submission.contactemail.clone())
+                                    .filter(|email| !email.is_empty()),
+                                contactRelationship: Some(submission.contactrelation.clone())
+                                    .filter(|relationship| !relationship.is_empty()),
+                                isPrimary: true,
+                            };
+                            let new_client = Client {
+                                clientID,
+                                clientName: Some(submission.name.clone()),
+                                clientTel: Some(submission.tel.clone()),
+                                clientAddr: Some(submission.address.clone()),
+                                employeeID: Some(submission.employeeID.clone()),
+                                // Derived once the client holds an account or a loan
+                                serviceType: None,
+                            };
+                            start_transaction!(db);
+                            match add_client(&mut db, new_client, primary_contact).await {
+                                Ok(_) => {
+                                    commit!(db);
+                                    Template::render("new-client-success", &form.context)
+                                }
+                                Err(e) => {
+                                    rollback!(db);
+                                    error_template!(e, "Error adding client")
+                                }
+                            }
+                        }
+                    }
+                }
             }
         }
         None => error_template!("Error adding client: failed to receive form"),
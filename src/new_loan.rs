@@ -2,9 +2,17 @@ use std::collections::HashMap;
 
 use super::preludes::rocket_prelude::*;
 use crate::{
-    commit, error_template, rollback, start_transaction,
-    utility::{get_list_from_input, GenericError},
+    commit,
+    credit_limit::check_exposure,
+    error_template,
+    kyc::check_kyc,
+    loan_agreement::add_agreement,
+    rollback, start_transaction,
+    utility::{get_client_id_list_from_input, GenericError},
+    validation::{FieldErrors, FormContext, Validate, Validator},
 };
+use chrono::Local;
+use sqlx::types::BigDecimal;
 use sqlx::Executor;
 
 #[get("/new/loan")]
@@ -20,6 +28,27 @@ pub struct Submit {
     subbranch: String,
     clientIDs: String,
     amount: String,
+    termMonths: String,
+    rate: String,
+}
+
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("subbranch", &self.subbranch)
+            .required("clientIDs", &self.clientIDs)
+            .check(
+                "clientIDs",
+                get_client_id_list_from_input::<Vec<_>>(&self.clientIDs),
+            )
+            .required("amount", &self.amount)
+            .pattern("amount", &self.amount, "amount")
+            .required("termMonths", &self.termMonths)
+            .pattern("termMonths", &self.termMonths, "term")
+            .required("rate", &self.rate)
+            .pattern("rate", &self.rate, "float")
+            .finish()
+    }
 }
 
 #[post("/new/loan", data = "<form>")]
@@ -37,6 +66,35 @@ pub async fn submit(
             )
         }
     };
+    let context = FormContext::new(value);
+    if !context.errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render("new-loan", &context),
+        );
+    }
+    let clientIDs: Vec<_> = match get_client_id_list_from_input(&value.clientIDs) {
+        Ok(ids) => ids,
+        Err(e) => return (status, error_template!(e, "Error adding loan")),
+    };
+    let amount: BigDecimal = match value.amount.parse() {
+        Ok(amount) => amount,
+        Err(e) => {
+            return (
+                status,
+                error_template!(e, "Invalid input: cannot parse input amount into decimal"),
+            )
+        }
+    };
+    match check_kyc(&mut db, &clientIDs).await {
+        Ok(_) => (),
+        Err(e) => return (status, error_template!(e, "Loan rejected")),
+    }
+    match check_exposure(&mut db, &clientIDs, &amount, None).await {
+        Ok(_) => (),
+        Err(e) => return (status, error_template!(e, "Loan rejected")),
+    }
+
     start_transaction!(db);
     let loanID = uuid::Uuid::new_v4().to_string();
     match add_loan_attr(
@@ -53,8 +111,8 @@ pub async fn submit(
             return (status, error_template!(e, "Error adding loan attributes"));
         }
     }
-    for client_id in get_list_from_input::<Vec<_>>(&value.clientIDs) {
-        match add_receiveloan_relation(&mut db, loanID.clone(), client_id).await {
+    for client_id in clientIDs.iter() {
+        match add_receiveloan_relation(&mut db, loanID.clone(), client_id.clone()).await {
             Ok(_) => (),
             Err(e) => {
                 rollback!(db);
@@ -65,6 +123,13 @@ pub async fn submit(
             }
         }
     }
+    match add_initial_agreement(&mut db, loanID.clone(), clientIDs, value).await {
+        Ok(_) => (),
+        Err(e) => {
+            rollback!(db);
+            return (status, error_template!(e, "Error adding loan agreement"));
+        }
+    }
 
     commit!(db);
     (
@@ -93,7 +158,29 @@ async fn add_loan_attr(
     Ok(())
 }
 
-async fn add_receiveloan_relation(
+/// Modify table `loanagreement` and `loanschedule`
+async fn add_initial_agreement(
+    db: &mut Connection<BankManage>,
+    loanID: String,
+    clientIDs: Vec<String>,
+    value: &Submit,
+) -> Result<(), GenericError> {
+    let amount: BigDecimal = value.amount.parse()?;
+    let agreement = LoanAgreement {
+        loanID,
+        version: 1,
+        effectiveDate: Local::today().naive_local(),
+        amount: amount.clone(),
+        termMonths: value.termMonths.parse()?,
+        rate: value.rate.parse()?,
+        borrowers: clientIDs.join(" "),
+        reason: None,
+    };
+    add_agreement(db, &agreement, &amount).await?;
+    Ok(())
+}
+
+pub async fn add_receiveloan_relation(
     db: &mut Connection<BankManage>,
     loanID: String,
     clientID: String,
@@ -107,5 +194,6 @@ async fn add_receiveloan_relation(
     .bind(&clientID)
     .execute(&mut **db)
     .await?;
+    crate::service_type::refresh_service_type(db, &clientID).await?;
     Ok(())
 }
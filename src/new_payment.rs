@@ -3,15 +3,18 @@ use std::collections::HashMap;
 use super::preludes::rocket_prelude::*;
 use crate::subbranch_manage::*;
 use crate::utility::Restriction;
+use crate::validation::{FieldErrors, Validate, Validator};
 use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
 use bigdecimal::Zero;
 use chrono::Local;
 use sqlx::Executor;
 
 #[derive(Serialize)]
-struct NewPaymentContext {
+struct NewPaymentContext<'a> {
     id: String,
     restriction: Restriction,
+    value: &'a Submit,
+    errors: FieldErrors,
 }
 
 #[get("/new/payment?<id>")]
@@ -21,6 +24,8 @@ pub async fn get_new_loan(id: String) -> Template {
         NewPaymentContext {
             id,
             restriction: crate::utility::get_restriction(),
+            value: &<Submit as Default>::default(),
+            errors: FieldErrors::new(),
         },
     )
 }
@@ -30,28 +35,73 @@ pub struct Submit {
     amount: String,
 }
 
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("amount", &self.amount)
+            .pattern("amount", &self.amount, "amount")
+            .finish()
+    }
+}
+
 #[post("/new/payment?<id>", data = "<form>")]
 pub async fn submit(
     mut db: Connection<BankManage>,
     id: String,
     form: Form<Contextual<'_, Submit>>,
-) -> Template {
+) -> (Status, Template) {
+    let status = form.context.status();
     let value = match form.value {
         Some(ref value) => value,
-        None => return error_template!("Error adding payment: failed to receive form"),
+        None => {
+            return (
+                status,
+                error_template!("Error adding payment: failed to receive form"),
+            )
+        }
     };
+    let errors = value.validate();
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "new-payment",
+                NewPaymentContext {
+                    id,
+                    restriction: crate::utility::get_restriction(),
+                    value,
+                    errors,
+                },
+            ),
+        );
+    }
+    (status, add_payment(&mut db, id, value).await)
+}
+
+async fn add_payment(db: &mut Connection<BankManage>, id: String, value: &Submit) -> Template {
     let (loan, _, associated_payments) = unwrap_or_return!(
-        super::loan_profile::query_loan(&mut db, &id).await,
+        super::loan_profile::query_loan(db, &id).await,
         "Error querying loan"
     );
     let pay_amount: sqlx::types::BigDecimal =
         associated_payments.iter().map(|pay| &pay.amount).sum();
+    let write_off = unwrap_or_return!(
+        super::loan_write_off::query_write_off(db, &id).await,
+        "Error querying write-off of the loan"
+    );
     let status = unwrap_or_return!(
         super::loan_profile::LoanStatus::new(&pay_amount, &loan.amount),
         "Unexpected: Aggregated payment is more than the loaded amount"
-    );
-    if matches!(status, super::loan_profile::LoanStatus::Paid) {
-        return error_template!("The loan is already paid, no more payment is received");
+    )
+    .with_write_off(&write_off);
+    match status {
+        super::loan_profile::LoanStatus::Paid => {
+            return error_template!("The loan is already paid, no more payment is received")
+        }
+        super::loan_profile::LoanStatus::WrittenOff => {
+            return error_template!("The loan is written off, book a recovery instead of a payment")
+        }
+        _ => (),
     }
     let new_payment: sqlx::types::BigDecimal = unwrap_or_return!(
         value.amount.parse(),
@@ -65,7 +115,7 @@ pub async fn submit(
 
     // Rule 2: the payment should not outnumber the assets of the subbranch
     let subbranch = unwrap_or_return!(
-        query_subbranch(&mut db, &loan.subbranchName).await,
+        query_subbranch(db, &loan.subbranchName).await,
         "Fail to fetch information of the subbranch"
     );
     if new_payment > subbranch.subbranchAsset {
@@ -81,7 +131,7 @@ pub async fn submit(
     start_transaction!(db);
     let new_asset = &subbranch.subbranchAsset - &new_payment;
     unwrap_or!(
-        set_subbranch_asset(&mut db, &subbranch.subbranchName, &new_asset).await,
+        set_subbranch_asset(db, &subbranch.subbranchName, &new_asset).await,
         e,
         {
             rollback!(db);
@@ -89,11 +139,12 @@ pub async fn submit(
         }
     );
     unwrap_or!(
-        sqlx::query("INSERT INTO payment (loanID, date, amount)VALUES(?, ?, ?)")
+        sqlx::query("INSERT INTO payment (paymentID, loanID, date, amount)VALUES(?, ?, ?, ?)")
+            .bind(uuid::Uuid::new_v4().to_string())
             .bind(&loan.loanID)
             .bind(Local::now().format("%Y-%m-%d").to_string())
             .bind(&new_payment)
-            .execute(&mut *db)
+            .execute(&mut **db)
             .await,
         e,
         {
@@ -0,0 +1,218 @@
+use super::preludes::rocket_prelude::*;
+use crate::export::{Export, ExportFormat, Table};
+use crate::utility::GenericError;
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use sqlx::Executor;
+
+#[derive(Debug)]
+pub struct AssignmentError {
+    pub info: String,
+}
+
+impl std::fmt::Display for AssignmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for AssignmentError {}
+
+async fn query_employee(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Option<Employee>, GenericError> {
+    Ok(
+        sqlx::query_as!(Employee, "SELECT * FROM employee WHERE employeeID=?", id)
+            .fetch_optional(&mut **db)
+            .await?,
+    )
+}
+
+/// Checks that the relationship manager of a client is a known employee. An empty ID means no manager is assigned.
+pub async fn check_employee(db: &mut Connection<BankManage>, id: &str) -> Result<(), GenericError> {
+    if id.is_empty() || query_employee(db, id).await?.is_some() {
+        Ok(())
+    } else {
+        Err(Box::new(AssignmentError {
+            info: format!("No employee with ID {id}"),
+        }))
+    }
+}
+
+async fn is_manager(db: &mut Connection<BankManage>, id: &str) -> Result<bool, GenericError> {
+    Ok(
+        sqlx::query("SELECT employeeID FROM manager WHERE employeeID=?")
+            .bind(id)
+            .fetch_optional(&mut **db)
+            .await?
+            .is_some(),
+    )
+}
+
+async fn query_managed_clients(
+    db: &mut Connection<BankManage>,
+    employee_id: &str,
+) -> Result<Vec<Client>, GenericError> {
+    Ok(sqlx::query_as!(
+        Client,
+        "SELECT * FROM client WHERE employeeID=? ORDER BY clientName",
+        employee_id
+    )
+    .fetch_all(&mut **db)
+    .await?)
+}
+
+#[derive(Serialize)]
+struct ReassignContext {
+    from: String,
+    clients: Vec<Client>,
+}
+
+#[get("/reassign/clients?<from>")]
+pub async fn get_reassign_clients(mut db: Connection<BankManage>, from: String) -> Template {
+    let clients = unwrap_or_return!(
+        query_managed_clients(&mut db, &from).await,
+        "Error querying clients of the employee"
+    );
+    Template::render("reassign-clients", ReassignContext { from, clients })
+}
+
+/// Clients of the relationship manager, or of the whole bank, leaving out the archived ones
+#[get("/export/clients?<manager>&<format>")]
+pub async fn export_clients(
+    mut db: Connection<BankManage>,
+    manager: Option<String>,
+    format: ExportFormat,
+) -> Result<Export, Template> {
+    let manager = manager.filter(|manager| !manager.is_empty());
+    let clients = match &manager {
+        Some(manager) => query_managed_clients(&mut db, manager).await,
+        None => sqlx::query_as!(Client, "SELECT * FROM client ORDER BY clientName")
+            .fetch_all(&mut *db)
+            .await
+            .map_err(GenericError::from),
+    };
+    let clients = unwrap_or!(clients, e, {
+        return Err(error_template!(e, "Error querying clients"));
+    });
+    let archived = unwrap_or!(
+        crate::client_dependency::query_archived_client_ids(&mut db).await,
+        e,
+        { return Err(error_template!(e, "Error querying archived clients")) }
+    );
+    let name = match &manager {
+        Some(manager) => format!("Clients of {manager}"),
+        None => "Clients".to_string(),
+    };
+    let mut table = Table::new(
+        name,
+        vec![
+            "Client ID",
+            "Name",
+            "Telephone",
+            "Address",
+            "Service type",
+            "Relationship manager",
+        ],
+    );
+    for client in clients
+        .into_iter()
+        .filter(|client| !archived.contains(&client.clientID))
+    {
+        table.push(vec![
+            client.clientID.into(),
+            client.clientName.into(),
+            client.clientTel.into(),
+            client.clientAddr.into(),
+            client.serviceType.into(),
+            client.employeeID.into(),
+        ]);
+    }
+    table
+        .export(format)
+        .map_err(|e| error_template!(e, "Error exporting clients"))
+}
+
+#[derive(Debug, FromForm, Default)]
+pub struct ReassignSubmit {
+    to: String,
+    managerID: String,
+    clientIDs: Vec<String>,
+}
+
+#[post("/reassign/clients?<from>", data = "<form>")]
+pub async fn submit_reassign_clients(
+    mut db: Connection<BankManage>,
+    from: String,
+    form: Form<Contextual<'_, ReassignSubmit>>,
+) -> Template {
+    let value = match form.value {
+        Some(ref value) => value,
+        None => return error_template!("Error reassigning clients: failed to receive form"),
+    };
+    let to = value.to.trim();
+
+    // Rule 1: clients are handed over to another existing employee
+    if to.is_empty() || to == from {
+        return error_template!("Clients should be reassigned to another employee");
+    }
+    if unwrap_or_return!(query_employee(&mut db, to).await, "Error querying employee").is_none() {
+        return error_template!(format!("No employee with ID {to}"));
+    }
+
+    // Rule 2: a reassignment is approved by a manager
+    if !unwrap_or_return!(
+        is_manager(&mut db, value.managerID.trim()).await,
+        "Error querying manager"
+    ) {
+        return error_template!("A reassignment should be approved by a manager");
+    }
+
+    // Rule 3: only clients currently managed by the employee are reassigned
+    if value.clientIDs.is_empty() {
+        return error_template!("No client selected");
+    }
+    let managed = unwrap_or_return!(
+        query_managed_clients(&mut db, &from).await,
+        "Error querying clients of the employee"
+    );
+    if let Some(client_id) = value
+        .clientIDs
+        .iter()
+        .find(|id| !managed.iter().any(|client| &client.clientID == *id))
+    {
+        return error_template!(format!("Client {client_id} is not managed by {from}"));
+    }
+
+    start_transaction!(db);
+    for client_id in &value.clientIDs {
+        unwrap_or!(
+            sqlx::query("UPDATE client SET employeeID=? WHERE clientID=?")
+                .bind(to)
+                .bind(client_id)
+                .execute(&mut *db)
+                .await,
+            e,
+            {
+                rollback!(db);
+                return error_template!(e, "Error reassigning client");
+            }
+        );
+    }
+    commit!(db);
+    Template::render(
+        "reassign-clients-success",
+        ReassignSuccessContext {
+            from,
+            to: to.to_string(),
+            count: value.clientIDs.len(),
+        },
+    )
+}
+
+#[derive(Serialize)]
+struct ReassignSuccessContext {
+    from: String,
+    to: String,
+    count: usize,
+}
@@ -0,0 +1,262 @@
+use super::preludes::rocket_prelude::*;
+use chrono::{Datelike, Duration, Local, NaiveDate};
+
+/// Years covered when no range is given
+const DEFAULT_DURATION_YEAR: i32 = 5;
+/// Most periods a report is split into
+const MAX_PERIODS: usize = 1000;
+/// Years a date of a report may fall in, so that the periods around it are representable
+const YEARS: std::ops::RangeInclusive<i32> = 1900..=9999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum Granularity {
+    #[field(value = "day")]
+    Day,
+    #[field(value = "week")]
+    Week,
+    #[field(value = "month")]
+    Month,
+    #[field(value = "quarter")]
+    Quarter,
+    #[field(value = "year")]
+    Year,
+}
+
+impl Granularity {
+    pub const ALL: [Granularity; 5] = [
+        Self::Day,
+        Self::Week,
+        Self::Month,
+        Self::Quarter,
+        Self::Year,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+            Self::Year => "year",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Day => "Day",
+            Self::Week => "Week",
+            Self::Month => "Month",
+            Self::Quarter => "Quarter",
+            Self::Year => "Year",
+        }
+    }
+
+    /// Start of the period containing the date. Weeks start on Monday.
+    fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Self::Month => NaiveDate::from_ymd(date.year(), date.month(), 1),
+            Self::Quarter => NaiveDate::from_ymd(date.year(), date.month0() / 3 * 3 + 1, 1),
+            Self::Year => NaiveDate::from_ymd(date.year(), 1, 1),
+        }
+    }
+
+    /// Start of the period following the one starting on `start`
+    fn next_start(&self, start: NaiveDate) -> NaiveDate {
+        let add_months = |months: u32| {
+            let month0 = start.month0() + months;
+            NaiveDate::from_ymd(start.year() + (month0 / 12) as i32, month0 % 12 + 1, 1)
+        };
+        match self {
+            Self::Day => start + Duration::days(1),
+            Self::Week => start + Duration::days(7),
+            Self::Month => add_months(1),
+            Self::Quarter => add_months(3),
+            Self::Year => add_months(12),
+        }
+    }
+
+    fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Self::Day => start.to_string(),
+            Self::Week => {
+                let week = start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Self::Month => start.format("%b %Y").to_string(),
+            Self::Quarter => format!("{} Q{}", start.year(), start.month0() / 3 + 1),
+            Self::Year => start.year().to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct GranularityOption {
+    name: &'static str,
+    description: &'static str,
+    selected: bool,
+}
+
+#[derive(Debug)]
+pub struct PeriodError {
+    pub info: String,
+}
+
+impl std::fmt::Display for PeriodError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for PeriodError {}
+
+#[derive(Debug)]
+pub struct Period {
+    /// The first day of the period, or of the range if the period starts before it
+    pub start: NaiveDate,
+    /// The day after the period, or after the range if the period ends after it
+    pub end: NaiveDate,
+    pub label: String,
+}
+
+/// A date range split into periods of the granularity
+#[derive(Debug)]
+pub struct ReportingPeriod {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub granularity: Granularity,
+    /// The earliest first
+    pub periods: Vec<Period>,
+}
+
+fn parse_date(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>, PeriodError> {
+    match value.map(str::trim).filter(|value| !value.is_empty()) {
+        Some(value) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) if YEARS.contains(&date.year()) => Ok(Some(date)),
+            Ok(_) => Err(PeriodError {
+                info: format!(
+                    "The {name} date {value} should be between the years {} and {}",
+                    YEARS.start(),
+                    YEARS.end()
+                ),
+            }),
+            Err(_) => Err(PeriodError {
+                info: format!("Invalid {name} date: {value}"),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+impl ReportingPeriod {
+    /// From the query parameters of a report. By default, the last five years up to today by month.
+    pub fn new(
+        from: Option<&str>,
+        to: Option<&str>,
+        granularity: Option<Granularity>,
+    ) -> Result<Self, PeriodError> {
+        let to = parse_date("end", to)?.unwrap_or_else(|| Local::today().naive_local());
+        let from = parse_date("start", from)?
+            .unwrap_or_else(|| NaiveDate::from_ymd(to.year() - DEFAULT_DURATION_YEAR + 1, 1, 1));
+        if from > to {
+            return Err(PeriodError {
+                info: format!("The start date {from} is after the end date {to}"),
+            });
+        }
+        let granularity = granularity.unwrap_or(Granularity::Month);
+
+        let mut periods = vec![];
+        let mut start = granularity.period_start(from);
+        while start <= to {
+            if periods.len() == MAX_PERIODS {
+                return Err(PeriodError {
+                    info: format!(
+                        "More than {MAX_PERIODS} periods from {from} to {to}, choose a shorter range or a coarser granularity"
+                    ),
+                });
+            }
+            let end = granularity.next_start(start);
+            periods.push(Period {
+                start: start.max(from),
+                end: end.min(to.succ()),
+                label: granularity.label(start),
+            });
+            start = end;
+        }
+        Ok(ReportingPeriod {
+            from,
+            to,
+            granularity,
+            periods,
+        })
+    }
+
+    /// Index of the period containing the date, or None if it is out of the range
+    pub fn index_of(&self, date: NaiveDate) -> Option<usize> {
+        if date < self.from || date > self.to {
+            None
+        } else {
+            Some(self.periods.partition_point(|period| period.end <= date))
+        }
+    }
+
+    /// Options of a select input choosing the granularity
+    pub fn granularity_options(&self) -> Vec<GranularityOption> {
+        Granularity::ALL
+            .iter()
+            .map(|granularity| GranularityOption {
+                name: granularity.as_str(),
+                description: granularity.description(),
+                selected: *granularity == self.granularity,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_the_range_into_periods() {
+        let report = ReportingPeriod::new(
+            Some("2021-11-15"),
+            Some("2022-02-10"),
+            Some(Granularity::Month),
+        )
+        .unwrap();
+        let labels: Vec<_> = report
+            .periods
+            .iter()
+            .map(|period| &period.label[..])
+            .collect();
+        assert_eq!(labels, ["Nov 2021", "Dec 2021", "Jan 2022", "Feb 2022"]);
+        assert_eq!(report.periods[0].start, NaiveDate::from_ymd(2021, 11, 15));
+        assert_eq!(report.periods[3].end, NaiveDate::from_ymd(2022, 2, 11));
+        assert_eq!(report.index_of(NaiveDate::from_ymd(2022, 1, 31)), Some(2));
+        assert_eq!(report.index_of(NaiveDate::from_ymd(2022, 2, 11)), None);
+    }
+
+    #[test]
+    fn rejects_dates_out_of_the_years() {
+        let year = Some(Granularity::Year);
+        for date in ["+262143-12-31", "10000-01-01", "1899-12-31", "0001-01-01"] {
+            assert!(ReportingPeriod::new(Some(date), Some("2000-01-01"), year).is_err());
+            assert!(ReportingPeriod::new(Some("2000-01-01"), Some(date), year).is_err());
+        }
+        assert!(ReportingPeriod::new(None, Some("+262143-12-31"), None).is_err());
+        assert!(ReportingPeriod::new(Some("1900-01-01"), Some("1999-12-31"), year).is_ok());
+        assert!(ReportingPeriod::new(Some("9900-01-01"), Some("9999-12-31"), year).is_ok());
+    }
+
+    #[test]
+    fn rejects_too_many_periods() {
+        let report = ReportingPeriod::new(
+            Some("1900-01-01"),
+            Some("9999-12-31"),
+            Some(Granularity::Day),
+        );
+        assert!(report.is_err());
+    }
+}
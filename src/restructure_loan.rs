@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+
+use super::preludes::rocket_prelude::*;
+use crate::kyc::check_kyc;
+use crate::loan_agreement::*;
+use crate::utility::{get_client_id_list_from_input, get_restriction, GenericError, Restriction};
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use chrono::Local;
+use sqlx::types::BigDecimal;
+use sqlx::Executor;
+
+#[derive(Serialize)]
+struct RestructureLoanContext {
+    id: String,
+    clientIDs: String,
+    amount: String,
+    paid: String,
+    termMonths: String,
+    rate: String,
+    capitalizeArrears: bool,
+    reason: String,
+    arrears: String,
+    restriction: Restriction,
+    errors: FieldErrors,
+}
+
+#[get("/restructure/loan?<id>")]
+pub async fn get_restructure_loan(mut db: Connection<BankManage>, id: String) -> Template {
+    render_restructure_loan(&mut db, id, None, FieldErrors::new()).await
+}
+
+/// Renders the form with the input of the user if any, otherwise with the current terms of the loan
+async fn render_restructure_loan(
+    db: &mut Connection<BankManage>,
+    id: String,
+    value: Option<&Submit>,
+    errors: FieldErrors,
+) -> Template {
+    let (loan, associated_clients, associated_payments) = unwrap_or_return!(
+        super::loan_profile::query_loan(db, &id).await,
+        "Error querying loan"
+    );
+    let agreements = unwrap_or_return!(
+        query_agreements(db, &id).await,
+        "Error querying loan agreements"
+    );
+    let arrears = match agreements.first() {
+        Some(agreement) => overdue_interest(
+            agreement,
+            &unwrap_or_return!(
+                query_schedule(db, &id, agreement.version).await,
+                "Error querying loan schedule"
+            ),
+            &associated_payments,
+            &Local::today().naive_local(),
+        ),
+        None => BigDecimal::default(),
+    };
+    let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+    let current = Submit {
+        clientIDs: associated_clients
+            .into_iter()
+            .map(|receive_loan| receive_loan.clientID)
+            .collect::<Vec<_>>()
+            .join(" "),
+        amount: loan.amount.to_string(),
+        termMonths: agreements
+            .first()
+            .map(|agreement| agreement.termMonths.to_string())
+            .unwrap_or_default(),
+        rate: agreements
+            .first()
+            .map(|agreement| agreement.rate.to_string())
+            .unwrap_or_default(),
+        capitalizeArrears: false,
+        reason: String::new(),
+    };
+    let value = value.unwrap_or(&current);
+    Template::render(
+        "restructure-loan",
+        RestructureLoanContext {
+            id,
+            clientIDs: value.clientIDs.clone(),
+            amount: value.amount.clone(),
+            paid: pay_amount.to_string(),
+            termMonths: value.termMonths.clone(),
+            rate: value.rate.clone(),
+            capitalizeArrears: value.capitalizeArrears,
+            reason: value.reason.clone(),
+            arrears: arrears.to_string(),
+            restriction: get_restriction(),
+            errors,
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct Submit {
+    clientIDs: String,
+    amount: String,
+    termMonths: String,
+    rate: String,
+    capitalizeArrears: bool,
+    reason: String,
+}
+
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("clientIDs", &self.clientIDs)
+            .check(
+                "clientIDs",
+                get_client_id_list_from_input::<Vec<_>>(&self.clientIDs),
+            )
+            .required("amount", &self.amount)
+            .pattern("amount", &self.amount, "amount")
+            .required("termMonths", &self.termMonths)
+            .pattern("termMonths", &self.termMonths, "term")
+            .required("rate", &self.rate)
+            .pattern("rate", &self.rate, "float")
+            .required("reason", &self.reason)
+            .finish()
+    }
+}
+
+#[post("/restructure/loan?<id>", data = "<form>")]
+pub async fn submit(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, Submit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error restructuring loan: failed to receive form"),
+            )
+        }
+    };
+    let errors = value.validate();
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            render_restructure_loan(&mut db, id, Some(value), errors).await,
+        );
+    }
+    (status, submit_restructuring(&mut db, id, value).await)
+}
+
+async fn submit_restructuring(
+    db: &mut Connection<BankManage>,
+    id: String,
+    value: &Submit,
+) -> Template {
+    let (loan, associated_clients, associated_payments) = unwrap_or_return!(
+        super::loan_profile::query_loan(db, &id).await,
+        "Error querying loan"
+    );
+    let pay_amount: BigDecimal = associated_payments.iter().map(|pay| &pay.amount).sum();
+    let mut new_amount: BigDecimal = unwrap_or_return!(
+        value.amount.parse(),
+        "Invalid input: cannot parse input amount into decimal"
+    );
+    let term_months: i32 = unwrap_or_return!(
+        value.termMonths.parse(),
+        "Invalid input: cannot parse the term into an integer"
+    );
+    let rate: f32 = unwrap_or_return!(
+        value.rate.parse(),
+        "Invalid input: cannot parse the rate into a number"
+    );
+    let new_client_IDs: HashSet<String> = unwrap_or_return!(
+        get_client_id_list_from_input(&value.clientIDs),
+        "Invalid input"
+    );
+
+    // Rule 1: a written-off loan cannot be restructured
+    if unwrap_or_return!(
+        super::loan_write_off::query_write_off(db, &id).await,
+        "Error querying write-off of the loan"
+    )
+    .is_some()
+    {
+        return error_template!("The loan is written off and cannot be restructured");
+    }
+
+    // Rule 2: the loan should keep at least one borrower
+    if new_client_IDs.is_empty() {
+        return error_template!("A loan should have at least one borrower");
+    }
+
+    // Rule 3: a guarantor of the loan cannot become one of its borrowers
+    let guarantors = unwrap_or_return!(
+        crate::collateral::query_guarantors(db, &id).await,
+        "Error querying guarantors"
+    );
+    if let Some(guarantor) = guarantors
+        .iter()
+        .find(|guarantee| new_client_IDs.contains(&guarantee.clientID))
+    {
+        return error_template!(format!(
+            "Client {} guarantees the loan and cannot be its borrower",
+            guarantor.clientID
+        ));
+    }
+
+    // Rule 4: clients joining the loan as borrowers should hold valid identity documents
+    let added_client_IDs: Vec<String> = new_client_IDs
+        .iter()
+        .filter(|client_id| {
+            !associated_clients
+                .iter()
+                .any(|receive_loan| &receive_loan.clientID == *client_id)
+        })
+        .cloned()
+        .collect();
+    unwrap_or!(check_kyc(db, &added_client_IDs).await, e, {
+        return error_template!(e, "Restructuring rejected");
+    });
+
+    let today = Local::today().naive_local();
+    if value.capitalizeArrears {
+        let agreements = unwrap_or_return!(
+            query_agreements(db, &id).await,
+            "Error querying loan agreements"
+        );
+        if let Some(agreement) = agreements.first() {
+            let schedule = unwrap_or_return!(
+                query_schedule(db, &id, agreement.version).await,
+                "Error querying loan schedule"
+            );
+            new_amount += overdue_interest(agreement, &schedule, &associated_payments, &today);
+        }
+    }
+
+    // Rule 5: past payments are preserved, so the loan cannot shrink below them
+    if new_amount < pay_amount {
+        return error_template!(format!(
+            "The new amount {new_amount} is less than the paid amount {pay_amount}"
+        ));
+    }
+
+    // Rule 6: the borrowers should stay within their credit limits
+    unwrap_or!(
+        crate::credit_limit::check_exposure(
+            db,
+            &new_client_IDs.iter().cloned().collect::<Vec<_>>(),
+            &(&new_amount - &pay_amount),
+            Some(&id),
+        )
+        .await,
+        e,
+        { return error_template!(e, "Restructuring rejected") }
+    );
+
+    // Updating the database
+    start_transaction!(db);
+    unwrap_or!(
+        restructure_loan(
+            db,
+            &loan,
+            associated_clients
+                .into_iter()
+                .map(|receive_loan| receive_loan.clientID)
+                .collect(),
+            new_client_IDs,
+            LoanAgreement {
+                loanID: id.clone(),
+                version: 0,
+                effectiveDate: today,
+                amount: new_amount.clone(),
+                termMonths: term_months,
+                rate,
+                borrowers: String::new(),
+                reason: Some(value.reason.trim().to_string()),
+            },
+            &(&new_amount - &pay_amount),
+        )
+        .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error restructuring loan");
+        }
+    );
+    commit!(db);
+    Template::render("restructure-loan-success", &HashMap::from([("id", id)]))
+}
+
+/// Modify table `loan`, `receiveloan`, and record the new agreement in `loanagreement` and `loanschedule`
+async fn restructure_loan(
+    db: &mut Connection<BankManage>,
+    loan: &Loan,
+    current_client_IDs: HashSet<String>,
+    new_client_IDs: HashSet<String>,
+    mut agreement: LoanAgreement,
+    outstanding: &BigDecimal,
+) -> Result<(), GenericError> {
+    sqlx::query("UPDATE loan SET amount=? WHERE loanID=?")
+        .bind(&agreement.amount)
+        .bind(&loan.loanID)
+        .execute(&mut **db)
+        .await?;
+
+    for to_add_client in new_client_IDs.difference(&current_client_IDs) {
+        crate::new_loan::add_receiveloan_relation(db, loan.loanID.clone(), to_add_client.clone())
+            .await?;
+    }
+    for to_remove_client in current_client_IDs.difference(&new_client_IDs) {
+        sqlx::query("DELETE FROM receiveloan WHERE loanID=? and clientID=?")
+            .bind(&loan.loanID)
+            .bind(to_remove_client)
+            .execute(&mut **db)
+            .await?;
+        crate::service_type::refresh_service_type(db, to_remove_client).await?;
+    }
+
+    let mut borrowers: Vec<_> = new_client_IDs.into_iter().collect();
+    borrowers.sort();
+    agreement.borrowers = borrowers.join(" ");
+    add_agreement(db, &agreement, outstanding).await?;
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use super::preludes::rocket_prelude::*;
+use crate::subbranch_manage::*;
+use crate::utility::GenericError;
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use chrono::Local;
+use sqlx::Executor;
+
+#[derive(Serialize)]
+struct ReversePaymentContext<'a> {
+    id: String,
+    loanID: String,
+    date: String,
+    amount: String,
+    value: &'a Submit,
+    errors: FieldErrors,
+}
+
+#[get("/reverse/payment?<id>")]
+pub async fn get_reverse_payment(mut db: Connection<BankManage>, id: String) -> Template {
+    let payment = unwrap_or_return!(query_payment(&mut db, &id).await, "Error querying payment");
+    render_reverse_payment(payment, &<Submit as Default>::default(), FieldErrors::new())
+}
+
+fn render_reverse_payment(payment: Payment, value: &Submit, errors: FieldErrors) -> Template {
+    Template::render(
+        "reverse-payment",
+        ReversePaymentContext {
+            id: payment.paymentID,
+            loanID: payment.loanID,
+            date: payment.date.to_string(),
+            amount: payment.amount.to_string(),
+            value,
+            errors,
+        },
+    )
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct Submit {
+    reason: String,
+}
+
+impl Validate for Submit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("reason", &self.reason)
+            .finish()
+    }
+}
+
+#[post("/reverse/payment?<id>", data = "<form>")]
+pub async fn submit(
+    mut db: Connection<BankManage>,
+    id: String,
+    form: Form<Contextual<'_, Submit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let value = match form.value {
+        Some(ref value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error reversing payment: failed to receive form"),
+            )
+        }
+    };
+    let payment = match query_payment(&mut db, &id).await {
+        Ok(payment) => payment,
+        Err(e) => return (status, error_template!(e, "Error querying payment")),
+    };
+    let errors = value.validate();
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            render_reverse_payment(payment, value, errors),
+        );
+    }
+    (status, reverse_payment(&mut db, id, value, payment).await)
+}
+
+async fn reverse_payment(
+    db: &mut Connection<BankManage>,
+    id: String,
+    value: &Submit,
+    payment: Payment,
+) -> Template {
+    // Rule 1: a compensating entry cannot be reversed again
+    if payment.reversalOf.is_some() {
+        return error_template!("The payment is itself a reversal and cannot be reversed");
+    }
+
+    // Rule 2: a payment can be reversed only once
+    let reversed = unwrap_or_return!(
+        query_reversal_of(db, &id).await,
+        "Error querying reversals of the payment"
+    );
+    if let Some(reversal) = reversed {
+        return error_template!(format!(
+            "The payment has already been reversed by {}",
+            reversal.paymentID
+        ));
+    }
+
+    let (loan, _, _) = unwrap_or_return!(
+        super::loan_profile::query_loan(db, &payment.loanID).await,
+        "Error querying loan"
+    );
+
+    // Rule 3: the payments of a written-off loan are frozen
+    if unwrap_or_return!(
+        super::loan_write_off::query_write_off(db, &loan.loanID).await,
+        "Error querying write-off of the loan"
+    )
+    .is_some()
+    {
+        return error_template!("The loan is written off, its payments cannot be reversed");
+    }
+    let subbranch = unwrap_or_return!(
+        query_subbranch(db, &loan.subbranchName).await,
+        "Fail to fetch information of the subbranch"
+    );
+
+    // Updating the database
+    start_transaction!(db);
+    let new_asset = &subbranch.subbranchAsset + &payment.amount;
+    unwrap_or!(
+        set_subbranch_asset(db, &subbranch.subbranchName, &new_asset).await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error updating the subbranch asset");
+        }
+    );
+    unwrap_or!(
+        sqlx::query(
+            "INSERT INTO payment (paymentID, loanID, date, amount, reversalOf, reason)VALUES(?, ?, ?, ?, ?, ?)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&payment.loanID)
+        .bind(Local::now().format("%Y-%m-%d").to_string())
+        .bind(-payment.amount.clone())
+        .bind(&payment.paymentID)
+        .bind(value.reason.trim())
+        .execute(&mut **db)
+        .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error recording the compensating payment");
+        }
+    );
+    commit!(db);
+    Template::render(
+        "reverse-payment-success",
+        &HashMap::from([("id", payment.loanID)]),
+    )
+}
+
+pub async fn query_payment(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Payment, GenericError> {
+    Ok(
+        sqlx::query_as!(Payment, "SELECT * FROM payment WHERE paymentID=?", id)
+            .fetch_one(&mut **db)
+            .await?,
+    )
+}
+
+/// Returns the compensating entry of the payment, if it has been reversed
+pub async fn query_reversal_of(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<Option<Payment>, GenericError> {
+    Ok(
+        sqlx::query_as!(Payment, "SELECT * FROM payment WHERE reversalOf=?", id)
+            .fetch_optional(&mut **db)
+            .await?,
+    )
+}
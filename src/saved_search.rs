@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use super::preludes::rocket_prelude::*;
+use crate::relationship_manager::check_employee;
+use crate::utility::GenericError;
+use crate::validation::{FieldErrors, Validate, Validator};
+use crate::{commit, error_template, rollback, start_transaction, unwrap_or, unwrap_or_return};
+use chrono::{Local, NaiveDateTime};
+use sqlx::Executor;
+
+/// Saved searches, listed on the index page
+pub async fn query_saved_searches(
+    db: &mut Connection<BankManage>,
+) -> Result<Vec<SavedSearch>, GenericError> {
+    Ok(
+        sqlx::query_as!(SavedSearch, "SELECT * FROM savedsearch ORDER BY searchName")
+            .fetch_all(&mut **db)
+            .await?,
+    )
+}
+
+pub async fn query_saved_search(
+    db: &mut Connection<BankManage>,
+    id: &str,
+) -> Result<SavedSearch, GenericError> {
+    Ok(sqlx::query_as!(
+        SavedSearch,
+        "SELECT * FROM savedsearch WHERE searchID=?",
+        id
+    )
+    .fetch_one(&mut **db)
+    .await?)
+}
+
+#[derive(Serialize, Debug)]
+pub struct SavedSearchView {
+    searchID: String,
+    searchName: String,
+    employeeID: String,
+    query: String,
+    watch: bool,
+    lastViewedAt: Option<String>,
+}
+
+impl From<SavedSearch> for SavedSearchView {
+    fn from(saved: SavedSearch) -> Self {
+        SavedSearchView {
+            searchID: saved.searchID,
+            searchName: saved.searchName,
+            employeeID: saved.employeeID,
+            query: saved.query,
+            watch: saved.watch,
+            lastViewedAt: saved
+                .lastViewedAt
+                .map(|date| date.format("%Y-%m-%d %H:%M").to_string()),
+        }
+    }
+}
+
+/// Changes of the results of a watched search since it was last marked as viewed
+#[derive(Debug, Default)]
+pub struct WatchReport {
+    /// Result key -> "New" or "Changed"
+    pub changes: HashMap<String, &'static str>,
+    pub removed: usize,
+}
+
+async fn apply_snapshot(
+    db: &mut Connection<BankManage>,
+    saved: &SavedSearch,
+    snapshot: &HashMap<String, SavedSearchResult>,
+    results: &[(String, String)],
+    now: NaiveDateTime,
+) -> Result<(), GenericError> {
+    for (key, fingerprint) in results {
+        match snapshot.get(key) {
+            None => {
+                sqlx::query(
+                    "INSERT INTO savedsearchresult (searchID, resultKey, fingerprint, addedAt, changedAt) VALUES (?, ?, ?, ?, ?)",
+                )
+                .bind(&saved.searchID)
+                .bind(key)
+                .bind(fingerprint)
+                .bind(now)
+                .bind(now)
+                .execute(&mut **db)
+                .await?;
+            }
+            Some(row) if row.removedAt.is_some() => {
+                // Listed again, as if it were new
+                sqlx::query(
+                    "UPDATE savedsearchresult SET fingerprint=?, addedAt=?, changedAt=?, removedAt=NULL WHERE searchID=? AND resultKey=?",
+                )
+                .bind(fingerprint)
+                .bind(now)
+                .bind(now)
+                .bind(&saved.searchID)
+                .bind(key)
+                .execute(&mut **db)
+                .await?;
+            }
+            Some(row) if &row.fingerprint != fingerprint => {
+                sqlx::query(
+                    "UPDATE savedsearchresult SET fingerprint=?, changedAt=? WHERE searchID=? AND resultKey=?",
+                )
+                .bind(fingerprint)
+                .bind(now)
+                .bind(&saved.searchID)
+                .bind(key)
+                .execute(&mut **db)
+                .await?;
+            }
+            Some(_) => (),
+        }
+    }
+    for row in snapshot.values() {
+        if row.removedAt.is_none() && !results.iter().any(|(key, _)| key == &row.resultKey) {
+            sqlx::query(
+                "UPDATE savedsearchresult SET removedAt=? WHERE searchID=? AND resultKey=?",
+            )
+            .bind(now)
+            .bind(&saved.searchID)
+            .bind(&row.resultKey)
+            .execute(&mut **db)
+            .await?;
+        }
+    }
+    // The first view is the baseline the later changes are compared to
+    if saved.lastViewedAt.is_none() {
+        sqlx::query("UPDATE savedsearch SET lastViewedAt=? WHERE searchID=?")
+            .bind(now)
+            .bind(&saved.searchID)
+            .execute(&mut **db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Records the results of a watched search, given as result key -> digest of what the result shows,
+/// and reports what has been added, changed or removed since the search was last marked as viewed
+pub async fn watch_results(
+    db: &mut Connection<BankManage>,
+    saved: &SavedSearch,
+    results: &[(String, String)],
+) -> Result<WatchReport, GenericError> {
+    let now = Local::now().naive_local();
+    let snapshot: HashMap<String, SavedSearchResult> = sqlx::query_as!(
+        SavedSearchResult,
+        "SELECT * FROM savedsearchresult WHERE searchID=?",
+        saved.searchID
+    )
+    .fetch_all(&mut **db)
+    .await?
+    .into_iter()
+    .map(|row| (row.resultKey.clone(), row))
+    .collect();
+
+    start_transaction!(db);
+    if let Err(e) = apply_snapshot(db, saved, &snapshot, results, now).await {
+        rollback!(db);
+        return Err(e);
+    }
+    commit!(db);
+
+    let last_viewed = match saved.lastViewedAt {
+        Some(last_viewed) => last_viewed,
+        None => return Ok(WatchReport::default()),
+    };
+    let mut report = WatchReport::default();
+    for (key, fingerprint) in results {
+        let (added, changed) = match snapshot.get(key) {
+            Some(row) if row.removedAt.is_none() => (
+                row.addedAt,
+                if &row.fingerprint == fingerprint {
+                    row.changedAt
+                } else {
+                    now
+                },
+            ),
+            _ => (now, now),
+        };
+        if added > last_viewed {
+            report.changes.insert(key.clone(), "New");
+        } else if changed > last_viewed {
+            report.changes.insert(key.clone(), "Changed");
+        }
+    }
+    report.removed = snapshot
+        .values()
+        .filter(|row| match row.removedAt {
+            Some(removed) => removed > last_viewed,
+            None => !results.iter().any(|(key, _)| key == &row.resultKey),
+        })
+        .count();
+    Ok(report)
+}
+
+#[derive(Debug, FromForm, Default, Serialize)]
+pub struct SavedSearchSubmit {
+    searchName: String,
+    employeeID: String,
+    /// Query string of the search, as in `search::ResultContext`
+    query: String,
+    watch: bool,
+}
+
+impl Validate for SavedSearchSubmit {
+    fn validate(&self) -> FieldErrors {
+        Validator::default()
+            .required("searchName", &self.searchName)
+            .required("employeeID", &self.employeeID)
+            .required("query", &self.query)
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct SaveSearchContext<'a> {
+    value: &'a SavedSearchSubmit,
+    errors: FieldErrors,
+}
+
+#[get("/new/saved-search?<query>")]
+pub async fn get_new_saved_search(query: String) -> Template {
+    Template::render(
+        "new-saved-search",
+        SaveSearchContext {
+            value: &SavedSearchSubmit {
+                query,
+                ..<SavedSearchSubmit as Default>::default()
+            },
+            errors: FieldErrors::new(),
+        },
+    )
+}
+
+#[post("/new/saved-search", data = "<form>")]
+pub async fn submit_saved_search(
+    mut db: Connection<BankManage>,
+    form: Form<Contextual<'_, SavedSearchSubmit>>,
+) -> (Status, Template) {
+    let status = form.context.status();
+    let submission = match form.into_inner().value {
+        Some(value) => value,
+        None => {
+            return (
+                status,
+                error_template!("Error saving search: failed to receive form"),
+            )
+        }
+    };
+    let mut errors = submission.validate();
+    if let Err(e) = check_employee(&mut db, submission.employeeID.trim()).await {
+        errors.insert("employeeID".to_string(), e.to_string());
+    }
+    if !errors.is_empty() {
+        return (
+            Status::UnprocessableEntity,
+            Template::render(
+                "new-saved-search",
+                SaveSearchContext {
+                    value: &submission,
+                    errors,
+                },
+            ),
+        );
+    }
+
+    let saved = SavedSearch {
+        searchID: uuid::Uuid::new_v4().to_string(),
+        searchName: submission.searchName.trim().to_string(),
+        employeeID: submission.employeeID.trim().to_string(),
+        query: submission.query,
+        watch: submission.watch,
+        createdAt: Local::now().naive_local(),
+        lastViewedAt: None,
+    };
+    match sqlx::query(
+        "INSERT INTO savedsearch (searchID, searchName, employeeID, query, watch, createdAt) VALUES
+    (?, ?, ?, ?, ?, ?)
+    ",
+    )
+    .bind(&saved.searchID)
+    .bind(&saved.searchName)
+    .bind(&saved.employeeID)
+    .bind(&saved.query)
+    .bind(saved.watch)
+    .bind(saved.createdAt)
+    .execute(&mut *db)
+    .await
+    {
+        Ok(_) => (
+            status,
+            Template::render(
+                "saved-search-success",
+                SavedSearchSuccessContext {
+                    info: "saved",
+                    search: SavedSearchView::from(saved),
+                },
+            ),
+        ),
+        Err(e) => (status, error_template!(e, "Error saving search")),
+    }
+}
+
+#[derive(Serialize)]
+struct SavedSearchSuccessContext {
+    info: &'static str,
+    search: SavedSearchView,
+}
+
+/// Marks the changes of a watched search as seen, so that only later ones are highlighted
+#[post("/viewed/saved-search?<id>")]
+pub async fn mark_viewed(mut db: Connection<BankManage>, id: String) -> Template {
+    let saved = unwrap_or_return!(
+        query_saved_search(&mut db, &id).await,
+        "Error querying saved search"
+    );
+    start_transaction!(db);
+    unwrap_or!(
+        sqlx::query("DELETE FROM savedsearchresult WHERE searchID=? AND removedAt IS NOT NULL")
+            .bind(&id)
+            .execute(&mut *db)
+            .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error marking saved search as viewed");
+        }
+    );
+    unwrap_or!(
+        sqlx::query("UPDATE savedsearch SET lastViewedAt=? WHERE searchID=?")
+            .bind(Local::now().naive_local())
+            .bind(&id)
+            .execute(&mut *db)
+            .await,
+        e,
+        {
+            rollback!(db);
+            return error_template!(e, "Error marking saved search as viewed");
+        }
+    );
+    commit!(db);
+    Template::render(
+        "saved-search-success",
+        SavedSearchSuccessContext {
+            info: "marked as viewed",
+            search: SavedSearchView::from(saved),
+        },
+    )
+}
+
+#[get("/delete/saved-search?<id>")]
+pub async fn delete_saved_search(mut db: Connection<BankManage>, id: String) -> Template {
+    let saved = unwrap_or_return!(
+        query_saved_search(&mut db, &id).await,
+        "Error querying saved search"
+    );
+    start_transaction!(db);
+    for statement in [
+        "DELETE FROM savedsearchresult WHERE searchID=?",
+        "DELETE FROM savedsearch WHERE searchID=?",
+    ] {
+        if let Err(e) = sqlx::query(statement).bind(&id).execute(&mut *db).await {
+            rollback!(db);
+            return error_template!(e, "Error deleting saved search");
+        }
+    }
+    commit!(db);
+    Template::render(
+        "saved-search-success",
+        SavedSearchSuccessContext {
+            info: "deleted",
+            search: SavedSearchView::from(saved),
+        },
+    )
+}
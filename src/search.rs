@@ -1,21 +1,201 @@
 use super::preludes::rocket_prelude::*;
+use crate::export::{Export, ExportFormat, Table};
+use crate::saved_search::{query_saved_search, watch_results, SavedSearchView};
+use crate::search_filter::{Conditions, FilterParam, SearchFilter};
+use crate::search_index::{KeywordMatch, SearchIndex};
+use crate::search_query::{Condition, Entity, SearchQuery};
+use crate::utility::GenericError;
+use crate::{error_template, unwrap_or, unwrap_or_return};
+use chrono::NaiveDate;
 use regex::{Regex, RegexBuilder};
+use rocket::http::RawStr;
+use rocket::State;
+use sha2::{Digest, Sha256};
+use sqlx::types::BigDecimal;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
-#[derive(Debug, FromForm, Serialize)]
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+pub const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Serialize)]
 pub struct SearchResultView {
     href: String,
     result_name: String,
     result_subtitle: String,
     result_desc: HashMap<String, String>,
+    /// "New" or "Changed" since a watched search was last viewed
+    change: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityCount {
+    entity: &'static str,
+    count: usize,
 }
 
-#[derive(Debug, FromForm, Serialize, Default)]
+#[derive(Debug, Serialize, Default)]
 pub struct ResultContext {
     search: String,
+    options: Vec<String>,
     results: Vec<SearchResultView>,
+    total: usize,
+    counts: Vec<EntityCount>,
+    /// Position of the first result of the page, counting from 1
+    first: usize,
+    last: usize,
+    /// Query string of the search without the cursor, used to build links to other pages
+    query: String,
+    filters: Vec<FilterParam>,
+    sort: String,
+    order: String,
+    page_size: usize,
+    previous_cursor: Option<usize>,
+    next_cursor: Option<usize>,
+    /// Why the search could not be parsed as a query
+    query_error: Option<String>,
+    /// The saved search being viewed
+    saved: Option<SavedSearchView>,
+    /// Results of a watched search which are new or changed, or no longer found, since it was last viewed
+    changed: usize,
+    removed: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum SortBy {
+    #[field(value = "name")]
+    Name,
+    #[field(value = "balance")]
+    Balance,
+    #[field(value = "open_date")]
+    OpenDate,
+    #[field(value = "amount")]
+    Amount,
+    #[field(value = "relevance")]
+    Relevance,
+}
+
+impl SortBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Balance => "balance",
+            Self::OpenDate => "open_date",
+            Self::Amount => "amount",
+            Self::Relevance => "relevance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum SortOrder {
+    #[field(value = "asc")]
+    Asc,
+    #[field(value = "desc")]
+    Desc,
+}
+
+impl SortOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Asc => "asc",
+            Self::Desc => "desc",
+        }
+    }
+}
+
+/// Values a result can be sorted by. Results lacking the value sorted by are placed last.
+#[derive(Debug, Default)]
+struct SortKey {
+    name: String,
+    balance: Option<BigDecimal>,
+    open_date: Option<NaiveDate>,
+    amount: Option<BigDecimal>,
+    /// How well a keyword search matches the result
+    relevance: f64,
+}
+
+struct SearchEntry {
+    /// Kind of entity, in plural as shown in the counts
+    entity: &'static str,
+    /// Key of the record, unique among the entity
+    record: String,
+    key: SortKey,
+    view: SearchResultView,
+}
+
+/// Orders by the chosen value, then by name and link, so that a page always holds the same results
+fn compare_entries(a: &SearchEntry, b: &SearchEntry, sort: SortBy, order: SortOrder) -> Ordering {
+    fn compare_optional<T: Ord>(a: &Option<T>, b: &Option<T>, order: SortOrder) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => match order {
+                SortOrder::Asc => a.cmp(b),
+                SortOrder::Desc => b.cmp(a),
+            },
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    let by_name = || a.key.name.to_lowercase().cmp(&b.key.name.to_lowercase());
+    match sort {
+        SortBy::Name => match order {
+            SortOrder::Asc => by_name(),
+            SortOrder::Desc => by_name().reverse(),
+        },
+        SortBy::Balance => {
+            compare_optional(&a.key.balance, &b.key.balance, order).then_with(by_name)
+        }
+        SortBy::OpenDate => {
+            compare_optional(&a.key.open_date, &b.key.open_date, order).then_with(by_name)
+        }
+        SortBy::Amount => compare_optional(&a.key.amount, &b.key.amount, order).then_with(by_name),
+        SortBy::Relevance => {
+            let by_relevance = a
+                .key
+                .relevance
+                .partial_cmp(&b.key.relevance)
+                .unwrap_or(Ordering::Equal);
+            match order {
+                SortOrder::Asc => by_relevance,
+                SortOrder::Desc => by_relevance.reverse(),
+            }
+            .then_with(by_name)
+        }
+    }
+    .then_with(|| a.view.href.cmp(&b.view.href))
+}
+
+/// Digest of what a result shows, without the highlighting, to tell whether it has changed
+fn fingerprint(view: &SearchResultView) -> String {
+    let mut desc: Vec<(&String, String)> = view
+        .result_desc
+        .iter()
+        .map(|(name, value)| (name, plain_text(value)))
+        .collect();
+    desc.sort();
+    let shown = format!(
+        "{}\n{}\n{:?}",
+        plain_text(&view.result_name),
+        plain_text(&view.result_subtitle),
+        desc
+    );
+    hex::encode(Sha256::digest(shown.as_bytes()))
 }
 
+/// Entity kinds in the order their counts are listed
+const ENTITIES: [&str; 9] = [
+    "Clients",
+    "Contacts",
+    "Notes",
+    "Accounts",
+    "Loans",
+    "Subbranches",
+    "Employees",
+    "Departments",
+    "Payments",
+];
+
 #[macro_export]
 macro_rules! get_attr_list {
     ($struct_name: ident; $($attr: ident),+) => {
@@ -23,212 +203,1121 @@ macro_rules! get_attr_list {
     };
 }
 
+/// Escapes the text to be put in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The text of a shown value, without the marks and the escaping
+fn plain_text(html: &str) -> String {
+    html.replace("<mark>", "")
+        .replace("</mark>", "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Escapes the text as HTML and marks what the regex matches, so that `<mark>` is the only markup of a result
+fn mark_matches(text: &str, re: Option<&Regex>) -> String {
+    let mut result = String::new();
+    let mut last = 0;
+    for found in re.into_iter().flat_map(|re| re.find_iter(text)) {
+        if found.as_str().is_empty() {
+            continue;
+        }
+        result += &escape_html(&text[last..found.start()]);
+        result += &format!("<mark>{}</mark>", escape_html(found.as_str()));
+        last = found.end();
+    }
+    result + &escape_html(&text[last..])
+}
+
 trait Hightlight {
-    fn highlight(&self, re: Regex, replace_with: &str) -> String;
+    fn highlight(&self, re: Option<&Regex>) -> String;
     fn to_string(&self) -> String {
-        self.highlight(Regex::new("").unwrap(), "")
+        self.highlight(None)
     }
 }
 
 impl Hightlight for String {
-    fn highlight(&self, re: Regex, replace_with: &str) -> String {
-        if self.is_empty() {
-            self.clone()
-        } else {
-            re.replace_all(self, replace_with).to_string()
-        }
+    fn highlight(&self, re: Option<&Regex>) -> String {
+        mark_matches(self, re)
     }
 }
 
 impl Hightlight for Option<String> {
-    fn highlight(&self, re: Regex, replace_with: &str) -> String {
-        if self.is_none() || self.as_ref().unwrap().is_empty() {
-            <String as Default>::default()
-        } else {
-            let str = self.as_ref().unwrap();
-            re.replace_all(str, replace_with).to_string()
-        }
+    fn highlight(&self, re: Option<&Regex>) -> String {
+        self.as_deref()
+            .map(|text| mark_matches(text, re))
+            .unwrap_or_default()
     }
 }
 
 fn hightlight_string<Src: Hightlight>(search_ref: &'_ str, src: &Src) -> String {
-    src.highlight(
-        RegexBuilder::new(&format!("(?P<s>{0})", search_ref))
+    src.highlight(Some(
+        &RegexBuilder::new(search_ref)
             .case_insensitive(true)
             .build()
             .expect("Regex pattern error during parsing search keys"),
-        "<mark>$s</mark>",
-    )
+    ))
 }
 
-/// Returns `HashMap<PK, (struct of entity, HashMap<attr name, higlighted attr value>)>`
+/// Highlights the text matched by the pattern, or leaves the text as it is without a pattern
+fn highlight<Src: Hightlight>(pattern: Option<&str>, src: &Src) -> String {
+    match pattern {
+        Some(pattern) => hightlight_string(pattern, src),
+        None => Hightlight::to_string(src),
+    }
+}
+
+/// Rows of the entity meeting both the condition compiled from the search and the structured filters.
+/// With a page of the entity, only the rows of the page are read.
+async fn query_entity<T>(
+    db: &mut Connection<BankManage>,
+    entity: Entity,
+    condition: Condition,
+    filter_conditions: &Conditions,
+    page: Option<&EntityPage>,
+) -> Vec<T>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::mysql::MySqlRow> + Send + Unpin,
+{
+    let (clause, values) = match condition {
+        Condition::True => ("TRUE".to_string(), vec![]),
+        Condition::False => return vec![],
+        Condition::Sql { clause, values } => (clause, values),
+    };
+    let mut statement = format!(
+        "SELECT * FROM {} WHERE ({clause}){}",
+        entity.table(),
+        filter_conditions.to_sql()
+    );
+    if let Some(page) = page.filter(|page| page.entity == entity) {
+        statement += &format!(
+            "{}{} LIMIT {} OFFSET {}",
+            listed_condition(entity),
+            page.order_by,
+            page.page_size,
+            page.cursor
+        );
+    }
+    let mut query = sqlx::query_as::<_, T>(&statement);
+    for value in values.iter().chain(&filter_conditions.values) {
+        query = query.bind(value);
+    }
+    query.fetch_all(&mut **db).await.unwrap_or_else(|e| {
+        eprintln!("Error querying {}: {e}", entity.as_str());
+        vec![]
+    })
+}
+
+/// Number of the listed rows of the entity meeting both the condition and the structured filters
+async fn count_entity(
+    db: &mut Connection<BankManage>,
+    entity: Entity,
+    condition: Condition,
+    filter_conditions: &Conditions,
+) -> Result<usize, GenericError> {
+    let (clause, values) = match condition {
+        Condition::True => ("TRUE".to_string(), vec![]),
+        Condition::False => return Ok(0),
+        Condition::Sql { clause, values } => (clause, values),
+    };
+    let statement = format!(
+        "SELECT COUNT(*) FROM {} WHERE ({clause}){}{}",
+        entity.table(),
+        filter_conditions.to_sql(),
+        listed_condition(entity)
+    );
+    let mut query = sqlx::query(&statement);
+    for value in values.iter().chain(&filter_conditions.values) {
+        query = query.bind(value);
+    }
+    Ok(query.fetch_one(&mut **db).await?.try_get::<'_, i64, _>(0)? as usize)
+}
+
+/// Condition leaving out the rows kept for audit but no longer listed, namely those of archived clients
+fn listed_condition(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Client | Entity::Contact | Entity::Note => {
+            " and clientID NOT IN (SELECT clientID FROM clientarchive)"
+        }
+        _ => "",
+    }
+}
+
+/// `ORDER BY` clause listing the rows of the entity as `compare_entries` sorts their results.
+/// Not for relevance, which is not a column.
+fn order_by(entity: Entity, sort: SortBy, order: SortOrder) -> String {
+    let value = match (entity, sort) {
+        (Entity::Account, SortBy::Balance) => Some("balance"),
+        (Entity::Subbranch, SortBy::Balance) => Some("subbranchAsset"),
+        (Entity::Note, SortBy::OpenDate) => Some("DATE(createdAt)"),
+        (Entity::Account, SortBy::OpenDate) => Some("openDate"),
+        (Entity::Employee, SortBy::OpenDate) => Some("employmentCommenceDate"),
+        (Entity::Payment, SortBy::OpenDate) => Some("date"),
+        (Entity::Loan | Entity::Payment, SortBy::Amount) => Some("amount"),
+        _ => None,
+    };
+    let name = match entity {
+        Entity::Client => "clientName",
+        Entity::Contact => "contactName",
+        Entity::Note => "noteType",
+        Entity::Account => "accountID",
+        Entity::Loan => "loanID",
+        Entity::Subbranch => "subbranchName",
+        Entity::Employee => "employeeID",
+        Entity::Department => "departmentName",
+        Entity::Payment => "paymentID",
+    };
+    let direction = match order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    let mut columns = vec![];
+    if let Some(value) = value {
+        // Rows lacking the value are placed last
+        columns.push(format!("{value} IS NULL"));
+        columns.push(format!("{value} {direction}"));
+    }
+    columns.push(match sort {
+        SortBy::Name => format!("COALESCE({name}, '') {direction}"),
+        _ => format!("COALESCE({name}, '')"),
+    });
+    columns.push(entity.key_column().to_string());
+    format!(" ORDER BY {}", columns.join(", "))
+}
+
+/// Kind of entity, in plural as shown in the counts
+fn entity_name(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Client => "Clients",
+        Entity::Contact => "Contacts",
+        Entity::Note => "Notes",
+        Entity::Account => "Accounts",
+        Entity::Loan => "Loans",
+        Entity::Subbranch => "Subbranches",
+        Entity::Employee => "Employees",
+        Entity::Department => "Departments",
+        Entity::Payment => "Payments",
+    }
+}
+
+/// Search option prefix of the attributes of the entity
+fn option_prefix(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Client => "Client.",
+        Entity::Contact => "ClientContact.",
+        Entity::Note => "ClientNote.",
+        Entity::Account => "Account.",
+        Entity::Loan => "Loan.",
+        Entity::Subbranch => "Subbranch.",
+        Entity::Employee => "Employee.",
+        Entity::Department => "Department.",
+        Entity::Payment => "Payment.",
+    }
+}
+
+/// How the results of a search are found
+enum Matching {
+    /// Nothing is searched, so everything with a chosen attribute is listed
+    All,
+    /// Plain keywords, looked up in the index
+    Keywords(HashMap<(Entity, String), KeywordMatch>),
+    Query {
+        search_query: SearchQuery,
+        pattern: Option<String>,
+    },
+}
+
+impl Matching {
+    /// Condition on the table of the entity. Queries do not use the search options.
+    fn condition(&self, entity: Entity, options: &[String]) -> Condition {
+        match self {
+            Self::All => {
+                if options
+                    .iter()
+                    .any(|option| option.starts_with(option_prefix(entity)))
+                {
+                    Condition::True
+                } else {
+                    Condition::False
+                }
+            }
+            Self::Keywords(matches) => {
+                let keys: Vec<String> = matches
+                    .keys()
+                    .filter(|(matched, _)| *matched == entity)
+                    .map(|(_, key)| key.clone())
+                    .collect();
+                if keys.is_empty() {
+                    Condition::False
+                } else {
+                    Condition::sql(
+                        format!(
+                            "{} IN ({})",
+                            entity.key_column(),
+                            vec!["?"; keys.len()].join(", ")
+                        ),
+                        keys,
+                    )
+                }
+            }
+            Self::Query { search_query, .. } => search_query.compile(entity),
+        }
+    }
+
+    /// Regex matching the text to be highlighted in the result
+    fn pattern(&self, entity: Entity, key: &str) -> Option<&str> {
+        match self {
+            Self::All => None,
+            Self::Keywords(matches) => matches
+                .get(&(entity, key.to_string()))
+                .map(|keyword_match| keyword_match.pattern.as_str()),
+            Self::Query { pattern, .. } => pattern.as_deref(),
+        }
+    }
+
+    fn relevance(&self, entity: Entity, key: &str) -> f64 {
+        match self {
+            Self::Keywords(matches) => matches
+                .get(&(entity, key.to_string()))
+                .map(|keyword_match| keyword_match.relevance)
+                .unwrap_or_default(),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Returns `HashMap<PK, (struct of entity, HashMap<attr name, higlighted attr value>)>`,
+/// keeping only the attributes in which the search is found
 macro_rules! get_search_result {
-    ($searchOption: expr; $struct_name: ident; $table_name: ident;$search: expr; $db: expr; $pk:ident, $($attr: ident),+) => {
-        match (&$searchOption, &mut $db, &$search){
-            (searchOption, db, search_ref)=>{
-                let mut filter_results: HashMap<String, ($struct_name, HashMap<String, String>)> = HashMap::new();
-                $(
-                    if(searchOption.contains(&concat!(stringify!($struct_name), ".", stringify!($attr)).to_string())){
-                        let query_statement = format!(concat!("SELECT * FROM ", stringify!($table_name), " WHERE ", stringify!($attr), " LIKE '%{0}%'"), search_ref);
-                        let search_results=sqlx::query_as::<_, $struct_name>(&query_statement).fetch_all(&mut **db).await.unwrap_or(vec![]);
-                        for search_result in search_results {
-                            let new_value = (
-                                stringify!($attr).to_string(),
-                                hightlight_string(search_ref, &search_result.$attr)
-                            );
-                            match filter_results.entry(search_result.$pk.clone()){
-                                std::collections::hash_map::Entry::Occupied(mut entry)=>{
-                                    entry.get_mut().1.insert(new_value.0, new_value.1);}
-                                std::collections::hash_map::Entry::Vacant(entry)=>{
-                                    entry.insert((search_result, HashMap::from([new_value])));}
-                            }
-                        }
-                    }
-                )*
-                filter_results
+    ($matching: expr; $entity: expr; $condition: expr; $struct_name: ident; $db: expr; $page: expr; $pk:ident, $($attr: ident),+) => {{
+        let mut filter_results: HashMap<String, ($struct_name, HashMap<String, String>)> = HashMap::new();
+        for search_result in query_entity::<$struct_name>($db, $entity, $condition, &Conditions::default(), $page).await {
+            let pattern = $matching.pattern($entity, &search_result.$pk);
+            let mut highlighted = HashMap::new();
+            $(
+                let value = highlight(pattern, &search_result.$attr);
+                if value.contains("<mark>") {
+                    highlighted.insert(stringify!($attr).to_string(), value);
+                }
+            )*
+            filter_results.insert(search_result.$pk.clone(), (search_result, highlighted));
+        }
+        filter_results
+    }};
+}
+
+/// Which of the sorted results are shown
+#[derive(Debug, Clone, Copy)]
+struct PageRequest {
+    cursor: usize,
+    page_size: usize,
+}
+
+/// A page of the results of a single entity sorted by a column, read from the database as it is
+struct EntityPage {
+    entity: Entity,
+    order_by: String,
+    cursor: usize,
+    page_size: usize,
+}
+
+/// Results of a search, sorted as chosen
+struct FoundEntries {
+    entries: Vec<SearchEntry>,
+    sort: SortBy,
+    order: SortOrder,
+    /// Set when only the requested page of a single entity is read, with the number of all its results
+    paged: Option<EntityCount>,
+}
+
+/// Finds the results of the search, or returns the page telling why it cannot be searched.
+/// When a page is requested, the results are all of a single entity and they are sorted by a column,
+/// only the rows of the page are read and the results are counted by the database.
+/// Otherwise every matching row of each entity is read, since the results of all entities are ranked
+/// and sorted together (relevance is only known once the rows are read).
+#[allow(clippy::too_many_arguments)]
+async fn find_entries(
+    db: &mut Connection<BankManage>,
+    search_index: &SearchIndex,
+    search: &str,
+    searchOption: &[String],
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    filter: &SearchFilter,
+    page: Option<PageRequest>,
+) -> Result<FoundEntries, Template> {
+    let mut entries: Vec<SearchEntry> = vec![];
+    let search_query = match SearchQuery::parse(search) {
+        Ok(search_query) => search_query,
+        Err(e) => {
+            return Err(Template::render(
+                "results",
+                &ResultContext {
+                    search: search.to_string(),
+                    options: searchOption.to_vec(),
+                    query_error: Some(e.to_string()),
+                    ..ResultContext::default()
+                },
+            ))
+        }
+    };
+    let account_conditions = match filter.account_conditions() {
+        Ok(conditions) => conditions,
+        Err(e) => return Err(error_template!(e, "Invalid search filter")),
+    };
+    let loan_conditions = match filter.loan_conditions() {
+        Ok(conditions) => conditions,
+        Err(e) => return Err(error_template!(e, "Invalid search filter")),
+    };
+    let subbranch_conditions = filter.subbranch_conditions();
+    // Structured filters leave out the entities without the filtered attributes
+    let active_options: Vec<String> = searchOption
+        .iter()
+        .cloned()
+        .filter(|option| {
+            if option.starts_with("Account.") {
+                account_conditions.is_some()
+            } else if option.starts_with("Loan.") {
+                loan_conditions.is_some()
+            } else if option.starts_with("Subbranch.") {
+                subbranch_conditions.is_some()
+            } else {
+                filter.applies_to_clients()
             }
+        })
+        .collect();
+
+    // Plain keywords are looked up in the index among the chosen attributes, and anything else is a query
+    let matching = if !search_query.is_plain() {
+        Matching::Query {
+            pattern: search_query.highlight_pattern(),
+            search_query,
+        }
+    } else if search.trim().is_empty() {
+        Matching::All
+    } else {
+        Matching::Keywords(unwrap_or!(
+            search_index.search(db, search, &active_options).await,
+            e,
+            { return Err(error_template!(e, "Error searching")) }
+        ))
+    };
+    // Structured filters leave out the entities without the filtered attributes
+    let unfiltered_condition = |entity: Entity| {
+        if filter.applies_to_clients() {
+            matching.condition(entity, &active_options)
+        } else {
+            Condition::False
         }
     };
-}
 
-#[get("/search?<search>&<searchOption>")]
-pub async fn search(
-    mut db: Connection<BankManage>,
-    search: String,
-    searchOption: Vec<String>,
-) -> Template {
-    // Search among clients
-    let client_filter_results = get_search_result!(searchOption;Client;client; search; db;clientID, clientID,clientName,clientAddr,contactName);
+    // Keyword searches list the most relevant results first
+    let sort = sort.unwrap_or(match matching {
+        Matching::Keywords(_) => SortBy::Relevance,
+        _ => SortBy::Name,
+    });
+    let order = order.unwrap_or(match sort {
+        SortBy::Relevance => SortOrder::Desc,
+        _ => SortOrder::Asc,
+    });
 
-    let mut result_view = ResultContext {
-        search: search.clone(),
-        ..<ResultContext as Default>::default()
+    // A page of a single entity sorted by a column is read as it is
+    let no_conditions = Conditions::default();
+    let searched: Vec<(Entity, Condition, &Conditions)> = Entity::ALL
+        .into_iter()
+        .filter_map(|entity| {
+            let (condition, conditions) = match entity {
+                Entity::Account => (
+                    matching.condition(entity, &active_options),
+                    account_conditions.as_ref()?,
+                ),
+                Entity::Loan => (
+                    matching.condition(entity, &active_options),
+                    loan_conditions.as_ref()?,
+                ),
+                Entity::Subbranch => (
+                    matching.condition(entity, &active_options),
+                    subbranch_conditions.as_ref()?,
+                ),
+                _ => (unfiltered_condition(entity), &no_conditions),
+            };
+            (!matches!(condition, Condition::False)).then_some((entity, condition, conditions))
+        })
+        .collect();
+    let mut paged = None;
+    let page = match (page, &searched[..]) {
+        (Some(page), [(entity, condition, conditions)]) if sort != SortBy::Relevance => {
+            let count = unwrap_or!(
+                count_entity(db, *entity, condition.clone(), conditions).await,
+                e,
+                { return Err(error_template!(e, "Error counting search results")) }
+            );
+            paged = Some(EntityCount {
+                entity: entity_name(*entity),
+                count,
+            });
+            Some(EntityPage {
+                entity: *entity,
+                order_by: order_by(*entity, sort, order),
+                cursor: page.cursor,
+                page_size: page.page_size,
+            })
+        }
+        _ => None,
     };
 
-    for mut client in client_filter_results.into_values() {
-        result_view.results.push(SearchResultView {
-            href: "/profile/client?id=".to_string() + &client.0.clientID,
-            result_subtitle: ToString::to_string(
-                client.1.get("clientID").unwrap_or(&client.0.clientID),
-            ),
-            result_name: ToString::to_string(
-                client
+    // Search among clients
+    let client_filter_results = get_search_result!(matching; Entity::Client; unfiltered_condition(Entity::Client); Client; db; page.as_ref(); clientID, clientID,clientName,clientAddr);
+
+    // Archived clients are kept for audit but no longer listed
+    let archived_clients = crate::client_dependency::query_archived_client_ids(db)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Error querying archived clients: {e}");
+            Default::default()
+        });
+    for mut client in client_filter_results
+        .into_values()
+        .filter(|client| !archived_clients.contains(&client.0.clientID))
+    {
+        entries.push(SearchEntry {
+            entity: "Clients",
+            record: client.0.clientID.clone(),
+            key: SortKey {
+                name: client.0.clientName.clone().unwrap_or_default(),
+                relevance: matching.relevance(Entity::Client, &client.0.clientID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: "/profile/client?id=".to_string() + &client.0.clientID,
+                result_subtitle: client
+                    .1
+                    .get("clientID")
+                    .cloned()
+                    .unwrap_or_else(|| escape_html(&client.0.clientID)),
+                result_name: client
                     .1
                     .get("clientName")
-                    .unwrap_or(&Hightlight::to_string(&client.0.clientName)),
-            ),
-            result_desc: if !search.is_empty() {
-                client.1.remove("clientID");
-                client.1.remove("clientName");
-                client.1
-            } else {
-                <HashMap<String, String> as Default>::default()
+                    .cloned()
+                    .unwrap_or_else(|| Hightlight::to_string(&client.0.clientName)),
+                result_desc: if !search.is_empty() {
+                    client.1.remove("clientID");
+                    client.1.remove("clientName");
+                    client.1
+                } else {
+                    <HashMap<String, String> as Default>::default()
+                },
             },
         });
     }
 
-    // Search among accounts
-    if searchOption.contains(&"Account.accountID".to_string()) {
-        let account_results: Vec<Account> = sqlx::query_as(&format!(
-            "SELECT * FROM account WHERE accountID LIKE '%{}%'",
-            search
-        ))
-        .fetch_all(&mut *db)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("Error querying account: {e_info}", e_info = e);
-            vec![]
+    // Search among contacts of clients
+    let contact_filter_results = get_search_result!(matching; Entity::Contact; unfiltered_condition(Entity::Contact); ClientContact; db; page.as_ref(); contactID, contactName,contactTel,contactEmail);
+    for mut contact in contact_filter_results
+        .into_values()
+        .filter(|contact| !archived_clients.contains(&contact.0.clientID))
+    {
+        let result_name = "Contact: ".to_string()
+            + &contact
+                .1
+                .remove("contactName")
+                .unwrap_or_else(|| escape_html(&contact.0.contactName));
+        let mut result_desc = if !search.is_empty() {
+            contact.1
+        } else {
+            <HashMap<String, String> as Default>::default()
+        };
+        if let Some(ref relationship) = contact.0.contactRelationship {
+            result_desc.insert("Relationship".to_string(), escape_html(relationship));
+        }
+        entries.push(SearchEntry {
+            entity: "Contacts",
+            record: contact.0.contactID.clone(),
+            key: SortKey {
+                name: contact.0.contactName.clone(),
+                relevance: matching.relevance(Entity::Contact, &contact.0.contactID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: "/profile/client?id=".to_string() + &contact.0.clientID,
+                result_name,
+                result_subtitle: escape_html(&contact.0.clientID),
+                result_desc,
+            },
+        });
+    }
+
+    // Search among notes on clients
+    let note_filter_results = get_search_result!(matching; Entity::Note; unfiltered_condition(Entity::Note); ClientNote; db; page.as_ref(); noteID, content);
+    for mut note in note_filter_results
+        .into_values()
+        .filter(|note| !archived_clients.contains(&note.0.clientID))
+    {
+        let mut result_desc = if !search.is_empty() {
+            note.1
+                .remove("content")
+                .map(|content| HashMap::from([("Content".to_string(), content)]))
+                .unwrap_or_default()
+        } else {
+            <HashMap<String, String> as Default>::default()
+        };
+        result_desc.insert("Author".to_string(), escape_html(&note.0.authorID));
+        result_desc.insert("Date".to_string(), note.0.createdAt.to_string());
+        entries.push(SearchEntry {
+            entity: "Notes",
+            record: note.0.noteID.clone(),
+            key: SortKey {
+                name: note.0.noteType.clone(),
+                open_date: Some(note.0.createdAt.date()),
+                relevance: matching.relevance(Entity::Note, &note.0.noteID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: "/profile/client?id=".to_string() + &note.0.clientID,
+                result_name: "Note: ".to_string() + &escape_html(&note.0.noteType),
+                result_subtitle: escape_html(&note.0.clientID),
+                result_desc,
+            },
         });
+    }
+
+    // Search among accounts
+    if let Some(conditions) = &account_conditions {
+        let account_results: Vec<Account> = query_entity(
+            db,
+            Entity::Account,
+            matching.condition(Entity::Account, &active_options),
+            conditions,
+            page.as_ref(),
+        )
+        .await;
 
         eprintln!("results of account: {account_results:?} for {search}",);
         for account_result in account_results {
-            result_view.results.push(SearchResultView {
-                href: "/profile/account?id=".to_string() + &account_result.accountID,
-                result_name: "Account: ".to_string() + &account_result.accountID,
-                result_subtitle: "".to_string(),
-                result_desc: [
-                    (
-                        "Account ID".to_string(),
-                        hightlight_string(&search, &account_result.accountID),
-                    ),
-                    ("Open Date".to_string(), account_result.openDate.to_string()),
-                    ("Balance".to_string(), account_result.balance.to_string()),
-                ]
-                .into_iter()
-                .collect(),
+            entries.push(SearchEntry {
+                entity: "Accounts",
+                record: account_result.accountID.clone(),
+                key: SortKey {
+                    name: account_result.accountID.clone(),
+                    balance: Some(account_result.balance.clone()),
+                    open_date: Some(account_result.openDate),
+                    relevance: matching.relevance(Entity::Account, &account_result.accountID),
+                    ..SortKey::default()
+                },
+                view: SearchResultView {
+                    change: None,
+                    href: "/profile/account?id=".to_string() + &account_result.accountID,
+                    result_name: "Account: ".to_string() + &escape_html(&account_result.accountID),
+                    result_subtitle: "".to_string(),
+                    result_desc: [
+                        (
+                            "Account ID".to_string(),
+                            highlight(
+                                matching.pattern(Entity::Account, &account_result.accountID),
+                                &account_result.accountID,
+                            ),
+                        ),
+                        ("Open Date".to_string(), account_result.openDate.to_string()),
+                        ("Balance".to_string(), account_result.balance.to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
             });
         }
     }
 
     // search among loans
-    if searchOption.contains(&"Loan.loanID".to_string()) {
-        let loan_results: Vec<Loan> = sqlx::query_as(&format!(
-            "SELECT * FROM loan WHERE loanID LIKE '%{}%'",
-            search
-        ))
-        .fetch_all(&mut *db)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("Error querying loan: {e}");
-            vec![]
-        });
+    if let Some(conditions) = &loan_conditions {
+        let loan_results: Vec<Loan> = query_entity(
+            db,
+            Entity::Loan,
+            matching.condition(Entity::Loan, &active_options),
+            conditions,
+            page.as_ref(),
+        )
+        .await;
 
         for loan_result in loan_results {
-            result_view.results.push(SearchResultView {
-                href: "/profile/loan?id=".to_string() + &loan_result.loanID,
-                result_name: "Loan: ".to_string() + &loan_result.loanID,
-                result_subtitle: "".to_string(),
-                result_desc: [
-                    (
-                        "Loan ID".to_string(),
-                        hightlight_string(&search, &loan_result.loanID),
-                    ),
-                    ("Subbranch".to_string(), loan_result.subbranchName),
-                    ("Amount".to_string(), loan_result.amount.to_string()),
-                ]
-                .into_iter()
-                .collect(),
+            entries.push(SearchEntry {
+                entity: "Loans",
+                record: loan_result.loanID.clone(),
+                key: SortKey {
+                    name: loan_result.loanID.clone(),
+                    amount: Some(loan_result.amount.clone()),
+                    relevance: matching.relevance(Entity::Loan, &loan_result.loanID),
+                    ..SortKey::default()
+                },
+                view: SearchResultView {
+                    change: None,
+                    href: "/profile/loan?id=".to_string() + &loan_result.loanID,
+                    result_name: "Loan: ".to_string() + &escape_html(&loan_result.loanID),
+                    result_subtitle: "".to_string(),
+                    result_desc: [
+                        (
+                            "Loan ID".to_string(),
+                            highlight(
+                                matching.pattern(Entity::Loan, &loan_result.loanID),
+                                &loan_result.loanID,
+                            ),
+                        ),
+                        (
+                            "Subbranch".to_string(),
+                            escape_html(&loan_result.subbranchName),
+                        ),
+                        ("Amount".to_string(), loan_result.amount.to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
             });
         }
     }
 
     //Search among subbranches
-    if searchOption.contains(&"Subbranch.subbranchName".to_string()) {
-        let subbranch_results: Vec<Subbranch> = sqlx::query_as(&format!(
-            "SELECT * FROM subbranch WHERE subbranchName LIKE '%{}%'",
-            search
-        ))
-        .fetch_all(&mut *db)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("Error querying subbranch: {e}");
-            vec![]
-        });
+    if let Some(conditions) = &subbranch_conditions {
+        let subbranch_results: Vec<Subbranch> = query_entity(
+            db,
+            Entity::Subbranch,
+            matching.condition(Entity::Subbranch, &active_options),
+            conditions,
+            page.as_ref(),
+        )
+        .await;
 
         for subbranch_result in subbranch_results {
-            result_view.results.push(SearchResultView {
-                href: "/profile/subbranch?name=".to_string() + &subbranch_result.subbranchName,
-                result_name: "Subbranch: ".to_string() + &subbranch_result.subbranchName,
+            entries.push(SearchEntry {
+                entity: "Subbranches",
+                record: subbranch_result.subbranchName.clone(),
+                key: SortKey {
+                    name: subbranch_result.subbranchName.clone(),
+                    balance: Some(subbranch_result.subbranchAsset.clone()),
+                    relevance: matching
+                        .relevance(Entity::Subbranch, &subbranch_result.subbranchName),
+                    ..SortKey::default()
+                },
+                view: SearchResultView {
+                    change: None,
+                    href: "/profile/subbranch?name=".to_string() + &subbranch_result.subbranchName,
+                    result_name: "Subbranch: ".to_string()
+                        + &escape_html(&subbranch_result.subbranchName),
+                    result_subtitle: "".to_string(),
+                    result_desc: [
+                        (
+                            "Name".to_string(),
+                            highlight(
+                                matching
+                                    .pattern(Entity::Subbranch, &subbranch_result.subbranchName),
+                                &subbranch_result.subbranchName,
+                            ),
+                        ),
+                        (
+                            "City".to_string(),
+                            highlight(
+                                matching
+                                    .pattern(Entity::Subbranch, &subbranch_result.subbranchName),
+                                &subbranch_result.city,
+                            ),
+                        ),
+                        (
+                            "Assets".to_string(),
+                            subbranch_result.subbranchAsset.to_string(),
+                        ),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+            });
+        }
+    }
+
+    // Search among employees, linking to the clients they serve
+    let employee_filter_results = get_search_result!(matching; Entity::Employee; unfiltered_condition(Entity::Employee); Employee; db; page.as_ref(); employeeID, employeeID,employeeTel,employeeAddr);
+    for mut employee in employee_filter_results.into_values() {
+        let mut result_desc: HashMap<String, String> = HashMap::new();
+        for (attr, description) in [("employeeTel", "Tel"), ("employeeAddr", "Address")] {
+            if let Some(value) = employee.1.remove(attr) {
+                result_desc.insert(description.to_string(), value);
+            }
+        }
+        if let Some(ref subbranch) = employee.0.subbranchName {
+            result_desc.insert("Subbranch".to_string(), escape_html(subbranch));
+        }
+        if let Some(ref department) = employee.0.departmentID {
+            result_desc.insert("Department".to_string(), escape_html(department));
+        }
+        entries.push(SearchEntry {
+            entity: "Employees",
+            record: employee.0.employeeID.clone(),
+            key: SortKey {
+                name: employee.0.employeeID.clone(),
+                open_date: employee.0.employmentCommenceDate,
+                relevance: matching.relevance(Entity::Employee, &employee.0.employeeID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: format!(
+                    "/search?search={}",
+                    RawStr::new(&format!("manager=\"{}\"", employee.0.employeeID)).percent_encode()
+                ),
+                result_name: "Employee: ".to_string()
+                    + &employee
+                        .1
+                        .remove("employeeID")
+                        .unwrap_or_else(|| escape_html(&employee.0.employeeID)),
+                result_subtitle: "".to_string(),
+                result_desc,
+            },
+        });
+    }
+
+    // Search among departments, linking to their employees
+    let department_filter_results = get_search_result!(matching; Entity::Department; unfiltered_condition(Entity::Department); Department; db; page.as_ref(); departmentID, departmentID,departmentName,departmentType);
+    for mut department in department_filter_results.into_values() {
+        let name = department
+            .1
+            .remove("departmentName")
+            .unwrap_or_else(|| Hightlight::to_string(&department.0.departmentName));
+        entries.push(SearchEntry {
+            entity: "Departments",
+            record: department.0.departmentID.clone(),
+            key: SortKey {
+                name: department.0.departmentName.clone().unwrap_or_default(),
+                relevance: matching.relevance(Entity::Department, &department.0.departmentID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: format!(
+                    "/search?search={}",
+                    RawStr::new(&format!(
+                        "type:employee department=\"{}\"",
+                        department.0.departmentID
+                    ))
+                    .percent_encode()
+                ),
+                result_name: "Department: ".to_string() + &name,
+                result_subtitle: department
+                    .1
+                    .remove("departmentID")
+                    .unwrap_or_else(|| escape_html(&department.0.departmentID)),
+                result_desc: [(
+                    "Type".to_string(),
+                    department
+                        .1
+                        .remove("departmentType")
+                        .unwrap_or_else(|| Hightlight::to_string(&department.0.departmentType)),
+                )]
+                .into_iter()
+                .collect(),
+            },
+        });
+    }
+
+    // Search among payments on loans
+    let payment_filter_results = get_search_result!(matching; Entity::Payment; unfiltered_condition(Entity::Payment); Payment; db; page.as_ref(); paymentID, paymentID,loanID);
+    for mut payment in payment_filter_results.into_values() {
+        entries.push(SearchEntry {
+            entity: "Payments",
+            record: payment.0.paymentID.clone(),
+            key: SortKey {
+                name: payment.0.paymentID.clone(),
+                open_date: Some(payment.0.date),
+                amount: Some(payment.0.amount.clone()),
+                relevance: matching.relevance(Entity::Payment, &payment.0.paymentID),
+                ..SortKey::default()
+            },
+            view: SearchResultView {
+                change: None,
+                href: "/profile/loan?id=".to_string() + &payment.0.loanID,
+                result_name: "Payment: ".to_string()
+                    + &payment
+                        .1
+                        .remove("paymentID")
+                        .unwrap_or_else(|| escape_html(&payment.0.paymentID)),
                 result_subtitle: "".to_string(),
                 result_desc: [
                     (
-                        "Name".to_string(),
-                        hightlight_string(&search, &subbranch_result.subbranchName),
-                    ),
-                    (
-                        "City".to_string(),
-                        hightlight_string(&search, &subbranch_result.city),
-                    ),
-                    (
-                        "Assets".to_string(),
-                        subbranch_result.subbranchAsset.to_string(),
+                        "Loan ID".to_string(),
+                        payment
+                            .1
+                            .remove("loanID")
+                            .unwrap_or_else(|| escape_html(&payment.0.loanID)),
                     ),
+                    ("Date".to_string(), payment.0.date.to_string()),
+                    ("Amount".to_string(), payment.0.amount.to_string()),
                 ]
                 .into_iter()
                 .collect(),
-            });
+            },
+        });
+    }
+
+    entries.sort_by(|a, b| compare_entries(a, b, sort, order));
+    Ok(FoundEntries {
+        entries,
+        sort,
+        order,
+        paged,
+    })
+}
+
+/// Results of a single entity sorted by a column are paged by the database. Otherwise every result is found
+/// and sorted before the page is taken, as is every result of a watched search, to be compared with its last view.
+#[get("/search?<search>&<searchOption>&<sort>&<order>&<page_size>&<cursor>&<saved>&<filter..>")]
+pub async fn search(
+    mut db: Connection<BankManage>,
+    search_index: &State<SearchIndex>,
+    search: String,
+    searchOption: Vec<String>,
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    page_size: Option<usize>,
+    cursor: Option<usize>,
+    saved: Option<String>,
+    filter: SearchFilter,
+) -> Template {
+    let page_size = page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let saved = match saved {
+        Some(id) => Some(unwrap_or_return!(
+            query_saved_search(&mut db, &id).await,
+            "Error querying saved search"
+        )),
+        None => None,
+    };
+    let watched = saved.as_ref().is_some_and(|saved| saved.watch);
+    let FoundEntries {
+        mut entries,
+        sort,
+        order,
+        paged,
+    } = match find_entries(
+        &mut db,
+        search_index,
+        &search,
+        &searchOption,
+        sort,
+        order,
+        &filter,
+        (!watched).then_some(PageRequest {
+            cursor: cursor.unwrap_or_default(),
+            page_size,
+        }),
+    )
+    .await
+    {
+        Ok(found) => found,
+        Err(template) => return template,
+    };
+
+    // A watched search highlights what has changed since it was last viewed
+    let mut saved_search = None;
+    let (mut changed, mut removed) = (0, 0);
+    if let Some(saved) = saved {
+        if saved.watch {
+            let results: Vec<(String, String)> = entries
+                .iter()
+                .map(|entry| {
+                    (
+                        format!("{}:{}", entry.entity, entry.record),
+                        fingerprint(&entry.view),
+                    )
+                })
+                .collect();
+            let mut report = unwrap_or_return!(
+                watch_results(&mut db, &saved, &results).await,
+                "Error comparing with the last view"
+            );
+            changed = report.changes.len();
+            removed = report.removed;
+            for (entry, (key, _)) in entries.iter_mut().zip(&results) {
+                entry.view.change = report.changes.remove(key);
+            }
         }
+        saved_search = Some(SavedSearchView::from(saved));
     }
 
-    Template::render("results", &result_view)
+    let (total, cursor, counts, results): (usize, usize, Vec<EntityCount>, Vec<SearchResultView>) =
+        match paged {
+            // The entries are already the page
+            Some(count) => (
+                count.count,
+                cursor.unwrap_or_default().min(count.count),
+                if count.count > 0 { vec![count] } else { vec![] },
+                entries.into_iter().map(|entry| entry.view).collect(),
+            ),
+            None => {
+                let total = entries.len();
+                let cursor = cursor.unwrap_or_default().min(total);
+                let counts = ENTITIES
+                    .iter()
+                    .map(|entity| EntityCount {
+                        entity,
+                        count: entries
+                            .iter()
+                            .filter(|entry| entry.entity == *entity)
+                            .count(),
+                    })
+                    .filter(|count| count.count > 0)
+                    .collect();
+                let results = entries
+                    .into_iter()
+                    .skip(cursor)
+                    .take(page_size)
+                    .map(|entry| entry.view)
+                    .collect();
+                (total, cursor, counts, results)
+            }
+        };
+
+    let mut query = format!("search={}", RawStr::new(&search).percent_encode());
+    for option in &searchOption {
+        query += &format!("&searchOption={}", RawStr::new(option).percent_encode());
+    }
+    let filters = filter.params();
+    for param in &filters {
+        query += &format!(
+            "&{}={}",
+            param.name,
+            RawStr::new(&param.value).percent_encode()
+        );
+    }
+    query += &format!(
+        "&sort={}&order={}&page_size={page_size}",
+        sort.as_str(),
+        order.as_str()
+    );
+
+    Template::render(
+        "results",
+        &ResultContext {
+            search,
+            total,
+            counts,
+            first: if results.is_empty() { 0 } else { cursor + 1 },
+            last: cursor + results.len(),
+            previous_cursor: if cursor > 0 {
+                Some(cursor.saturating_sub(page_size))
+            } else {
+                None
+            },
+            next_cursor: if cursor + results.len() < total {
+                Some(cursor + results.len())
+            } else {
+                None
+            },
+            results,
+            options: searchOption,
+            query,
+            filters,
+            sort: sort.as_str().to_string(),
+            order: order.as_str().to_string(),
+            page_size,
+            query_error: None,
+            saved: saved_search,
+            changed,
+            removed,
+        },
+    )
+}
+
+/// Every result of the search, not only a page of them, with the highlighting removed
+#[get("/export/search?<search>&<searchOption>&<sort>&<order>&<format>&<filter..>")]
+pub async fn export_search(
+    mut db: Connection<BankManage>,
+    search_index: &State<SearchIndex>,
+    search: String,
+    searchOption: Vec<String>,
+    sort: Option<SortBy>,
+    order: Option<SortOrder>,
+    format: ExportFormat,
+    filter: SearchFilter,
+) -> Result<Export, Template> {
+    let found = find_entries(
+        &mut db,
+        search_index,
+        &search,
+        &searchOption,
+        sort,
+        order,
+        &filter,
+        None,
+    )
+    .await?;
+    let mut table = Table::new(
+        "Search results",
+        vec!["Type", "Name", "Subtitle", "Details", "Link"],
+    );
+    for entry in found.entries {
+        let mut details: Vec<String> = entry
+            .view
+            .result_desc
+            .iter()
+            .map(|(name, value)| format!("{name}: {}", plain_text(value)))
+            .collect();
+        details.sort();
+        table.push(vec![
+            entry.entity.into(),
+            plain_text(&entry.view.result_name).into(),
+            plain_text(&entry.view.result_subtitle).into(),
+            details.join("; ").into(),
+            entry.view.href.into(),
+        ]);
+    }
+    table
+        .export(format)
+        .map_err(|e| error_template!(e, "Error exporting search results"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_rows_as_the_results_are_sorted() {
+        assert_eq!(
+            order_by(Entity::Client, SortBy::Name, SortOrder::Desc),
+            " ORDER BY COALESCE(clientName, '') DESC, clientID"
+        );
+        assert_eq!(
+            order_by(Entity::Account, SortBy::Balance, SortOrder::Desc),
+            " ORDER BY balance IS NULL, balance DESC, COALESCE(accountID, ''), account.accountID"
+        );
+        // Clients have no amount, so they are sorted by name
+        assert_eq!(
+            order_by(Entity::Client, SortBy::Amount, SortOrder::Desc),
+            " ORDER BY COALESCE(clientName, ''), clientID"
+        );
+    }
+
+    #[test]
+    fn escapes_results_before_marking_the_matches() {
+        let re = RegexBuilder::new("b")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        let text = "<a href='x'>B&b</a>".to_string();
+        let shown = text.highlight(Some(&re));
+        assert_eq!(
+            shown,
+            "&lt;a href=&#39;x&#39;&gt;<mark>B</mark>&amp;<mark>b</mark>&lt;/a&gt;"
+        );
+        assert_eq!(plain_text(&shown), text);
+        assert_eq!(Hightlight::to_string(&None::<String>), "");
+    }
 }
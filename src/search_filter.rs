@@ -0,0 +1,294 @@
+use super::preludes::rocket_prelude::*;
+use crate::loan_profile::LoanStatus;
+use chrono::NaiveDate;
+use sqlx::types::BigDecimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromFormField)]
+pub enum AccountType {
+    #[field(value = "saving")]
+    Saving,
+    #[field(value = "checking")]
+    Checking,
+}
+
+impl AccountType {
+    pub const ALL: [AccountType; 2] = [Self::Saving, Self::Checking];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Saving => "saving",
+            Self::Checking => "checking",
+        }
+    }
+}
+
+/// Condition on `account` that the account is of the type
+pub fn account_type_condition(account_type: AccountType) -> &'static str {
+    match account_type {
+        AccountType::Saving => "account.accountID IN (SELECT accountID FROM savingaccount)",
+        AccountType::Checking => "account.accountID IN (SELECT accountID FROM checkingaccount)",
+    }
+}
+
+/// Condition on `account` that one of the subbranches managing the account meets `condition`
+pub fn account_subbranch_condition(condition: &str) -> String {
+    format!("EXISTS (SELECT * FROM accountmanagement WHERE (accountmanagement.savingAccountID=account.accountID OR accountmanagement.checkingAccountID=account.accountID) and {condition})")
+}
+
+/// Condition on `loan` that the loan is in the status, following `LoanStatus::new`
+pub fn loan_status_condition(status: LoanStatus) -> String {
+    let paid =
+        "(SELECT COALESCE(SUM(payment.amount), 0) FROM payment WHERE payment.loanID=loan.loanID)";
+    let written_off = "loan.loanID IN (SELECT loanID FROM loanwriteoff)";
+    match status {
+        LoanStatus::WrittenOff => written_off.to_string(),
+        LoanStatus::Unpaid => format!("NOT {written_off} and {paid} = 0 and loan.amount <> 0"),
+        LoanStatus::BeingPaid => {
+            format!("NOT {written_off} and {paid} > 0 and {paid} < loan.amount")
+        }
+        LoanStatus::Paid => format!("NOT {written_off} and {paid} = loan.amount"),
+    }
+}
+
+/// Structured filters of a search, passed in the query string so that searches can be bookmarked.
+/// A filter only applies to the entities having the filtered attribute, and the other entities are left out once it is set.
+#[derive(Debug, Default, FromForm)]
+pub struct SearchFilter {
+    min_balance: Option<String>,
+    max_balance: Option<String>,
+    opened_after: Option<String>,
+    opened_before: Option<String>,
+    account_type: Option<AccountType>,
+    currency: Option<String>,
+    min_amount: Option<String>,
+    max_amount: Option<String>,
+    loan_status: Option<LoanStatus>,
+    subbranch: Option<String>,
+    city: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilterParam {
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug)]
+pub struct FilterError {
+    pub info: String,
+}
+
+impl std::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// Conditions to be joined with `and`, along with the values bound to their placeholders
+#[derive(Debug, Default)]
+pub struct Conditions {
+    pub clauses: Vec<String>,
+    pub values: Vec<String>,
+}
+
+impl Conditions {
+    fn push(&mut self, clause: &str, value: Option<String>) {
+        self.clauses.push(clause.to_string());
+        self.values.extend(value);
+    }
+
+    /// The conditions to be appended to a `WHERE` clause, or nothing if there is none
+    pub fn to_sql(&self) -> String {
+        self.clauses
+            .iter()
+            .map(|clause| format!(" and {clause}"))
+            .collect()
+    }
+}
+
+fn empty_as_none(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_decimal(name: &str, value: &Option<String>) -> Result<Option<String>, FilterError> {
+    match empty_as_none(value) {
+        Some(value) => match value.parse::<BigDecimal>() {
+            Ok(decimal) => Ok(Some(decimal.to_string())),
+            Err(_) => Err(FilterError {
+                info: format!("Invalid {name}: {value} is not a number"),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+fn parse_date(name: &str, value: &Option<String>) -> Result<Option<String>, FilterError> {
+    match empty_as_none(value) {
+        Some(value) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => Ok(Some(date.to_string())),
+            Err(_) => Err(FilterError {
+                info: format!("Invalid {name}: {value} is not a date"),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+impl SearchFilter {
+    fn has_account_filter(&self) -> bool {
+        empty_as_none(&self.min_balance).is_some()
+            || empty_as_none(&self.max_balance).is_some()
+            || empty_as_none(&self.opened_after).is_some()
+            || empty_as_none(&self.opened_before).is_some()
+            || self.account_type.is_some()
+            || empty_as_none(&self.currency).is_some()
+    }
+
+    fn has_loan_filter(&self) -> bool {
+        empty_as_none(&self.min_amount).is_some()
+            || empty_as_none(&self.max_amount).is_some()
+            || self.loan_status.is_some()
+    }
+
+    fn has_location_filter(&self) -> bool {
+        empty_as_none(&self.subbranch).is_some() || empty_as_none(&self.city).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.has_account_filter() && !self.has_loan_filter() && !self.has_location_filter()
+    }
+
+    /// Clients, contacts, notes, employees, departments and payments have none of the filtered attributes
+    pub fn applies_to_clients(&self) -> bool {
+        self.is_empty()
+    }
+
+    /// Returns the conditions on `account`, or None if accounts are left out
+    pub fn account_conditions(&self) -> Result<Option<Conditions>, FilterError> {
+        let mut conditions = Conditions::default();
+        if let Some(value) = parse_decimal("minimum balance", &self.min_balance)? {
+            conditions.push("account.balance >= ?", Some(value));
+        }
+        if let Some(value) = parse_decimal("maximum balance", &self.max_balance)? {
+            conditions.push("account.balance <= ?", Some(value));
+        }
+        if let Some(value) = parse_date("open date", &self.opened_after)? {
+            conditions.push("account.openDate >= ?", Some(value));
+        }
+        if let Some(value) = parse_date("open date", &self.opened_before)? {
+            conditions.push("account.openDate <= ?", Some(value));
+        }
+        if let Some(account_type) = self.account_type {
+            conditions.push(account_type_condition(account_type), None);
+        }
+        if let Some(value) = empty_as_none(&self.currency) {
+            conditions.push(
+                "account.accountID IN (SELECT accountID FROM savingaccount WHERE currencyType=?)",
+                Some(value.to_string()),
+            );
+        }
+        if let Some(value) = empty_as_none(&self.subbranch) {
+            conditions.push(
+                &account_subbranch_condition("accountmanagement.subbranchName=?"),
+                Some(value.to_string()),
+            );
+        }
+        if let Some(value) = empty_as_none(&self.city) {
+            conditions.push(
+                &account_subbranch_condition(
+                    "accountmanagement.subbranchName IN (SELECT subbranchName FROM subbranch WHERE city=?)",
+                ),
+                Some(value.to_string()),
+            );
+        }
+        Ok(if self.has_loan_filter() {
+            None
+        } else {
+            Some(conditions)
+        })
+    }
+
+    /// Returns the conditions on `loan`, or None if loans are left out
+    pub fn loan_conditions(&self) -> Result<Option<Conditions>, FilterError> {
+        let mut conditions = Conditions::default();
+        if let Some(value) = parse_decimal("minimum amount", &self.min_amount)? {
+            conditions.push("loan.amount >= ?", Some(value));
+        }
+        if let Some(value) = parse_decimal("maximum amount", &self.max_amount)? {
+            conditions.push("loan.amount <= ?", Some(value));
+        }
+        if let Some(status) = self.loan_status {
+            conditions.push(&loan_status_condition(status), None);
+        }
+        if let Some(value) = empty_as_none(&self.subbranch) {
+            conditions.push("loan.subbranchName=?", Some(value.to_string()));
+        }
+        if let Some(value) = empty_as_none(&self.city) {
+            conditions.push(
+                "loan.subbranchName IN (SELECT subbranchName FROM subbranch WHERE city=?)",
+                Some(value.to_string()),
+            );
+        }
+        Ok(if self.has_account_filter() {
+            None
+        } else {
+            Some(conditions)
+        })
+    }
+
+    /// Returns the conditions on `subbranch`, or None if subbranches are left out
+    pub fn subbranch_conditions(&self) -> Option<Conditions> {
+        if self.has_account_filter() || self.has_loan_filter() {
+            return None;
+        }
+        let mut conditions = Conditions::default();
+        if let Some(value) = empty_as_none(&self.subbranch) {
+            conditions.push("subbranch.subbranchName=?", Some(value.to_string()));
+        }
+        if let Some(value) = empty_as_none(&self.city) {
+            conditions.push("subbranch.city=?", Some(value.to_string()));
+        }
+        Some(conditions)
+    }
+
+    /// The filters which are set, as they appear in the query string
+    pub fn params(&self) -> Vec<FilterParam> {
+        let mut params = vec![];
+        for (name, value) in [
+            ("min_balance", &self.min_balance),
+            ("max_balance", &self.max_balance),
+            ("opened_after", &self.opened_after),
+            ("opened_before", &self.opened_before),
+            ("currency", &self.currency),
+            ("min_amount", &self.min_amount),
+            ("max_amount", &self.max_amount),
+            ("subbranch", &self.subbranch),
+            ("city", &self.city),
+        ] {
+            if let Some(value) = empty_as_none(value) {
+                params.push(FilterParam {
+                    name,
+                    value: value.to_string(),
+                });
+            }
+        }
+        if let Some(account_type) = self.account_type {
+            params.push(FilterParam {
+                name: "account_type",
+                value: account_type.as_str().to_string(),
+            });
+        }
+        if let Some(loan_status) = self.loan_status {
+            params.push(FilterParam {
+                name: "loan_status",
+                value: loan_status.as_str().to_string(),
+            });
+        }
+        params
+    }
+}
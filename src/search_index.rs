@@ -0,0 +1,537 @@
+//! In-memory inverted index over the searchable attributes, used for keyword searches.
+//!
+//! Latin text is indexed by words, and Chinese text by characters along with their pinyin,
+//! so that `zhangsan`, `zs`, `张三` and `张san` all find 张三.
+//! A keyword matches a term exactly, as a prefix, inside it or with a typo,
+//! and the results are ranked by how rare the matched terms are and in which attribute they are found.
+//!
+//! The index is built at the first search and rebuilt after any request which may have changed the data.
+
+use super::preludes::rocket_prelude::*;
+use crate::search_query::Entity;
+use crate::utility::GenericError;
+use pinyin::ToPinyin;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::sync::RwLock;
+use rocket::{Request, Response};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{self, AtomicBool};
+
+/// Weight of the names and IDs, compared to the other attributes
+const NAME_BOOST: f64 = 2.0;
+/// Weight of a keyword found as the beginning of a term, inside a term, or with one or two typos
+const PREFIX_MATCH: f64 = 0.7;
+const INFIX_MATCH: f64 = 0.5;
+const TYPO_MATCH: [f64; 2] = [0.6, 0.4];
+
+/// Requests to these paths with GET only read data, so they leave the index as it is
+const READ_ONLY_PATHS: [&str; 7] = [
+    "/search",
+    "/profile/",
+    "/dashboard",
+    "/export/",
+    "/images/",
+    "/style.css",
+    "/form-style.css",
+];
+
+struct Field {
+    /// Search option of the attribute, e.g. `Client.clientName`
+    option: &'static str,
+    boost: f64,
+}
+
+struct Document {
+    entity: Entity,
+    key: String,
+    fields: Vec<Field>,
+}
+
+struct Posting {
+    document: usize,
+    field: usize,
+    /// Text of the attribute the term comes from, highlighted when the term is matched
+    surface: String,
+}
+
+#[derive(Default)]
+struct Index {
+    documents: Vec<Document>,
+    postings: HashMap<String, Vec<Posting>>,
+}
+
+/// A document matching every keyword
+#[derive(Debug)]
+pub struct KeywordMatch {
+    pub relevance: f64,
+    /// Regex matching the text found in the document
+    pub pattern: String,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+/// Splits text into runs of Chinese characters and runs of other letters and digits
+fn runs(text: &str) -> Vec<(String, bool)> {
+    let mut runs: Vec<(String, bool)> = vec![];
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            runs.push((String::new(), false));
+            continue;
+        }
+        let cjk = is_cjk(c);
+        match runs.last_mut() {
+            Some((run, run_cjk)) if *run_cjk == cjk => run.push(c),
+            _ => runs.push((c.to_string(), cjk)),
+        }
+    }
+    runs.retain(|(run, _)| !run.is_empty());
+    runs
+}
+
+/// Terms of the text, each with the text it comes from
+fn index_terms(text: &str) -> Vec<(String, String)> {
+    let mut terms = vec![];
+    for (run, cjk) in runs(text) {
+        if !cjk {
+            terms.push((run.to_lowercase(), run));
+            continue;
+        }
+        let mut full = String::new();
+        let mut initials = String::new();
+        for c in run.chars() {
+            terms.push((c.to_string(), c.to_string()));
+            if let Some(pinyin) = c.to_pinyin() {
+                terms.push((pinyin.plain().to_string(), c.to_string()));
+                full += pinyin.plain();
+                initials += pinyin.first_letter();
+            }
+        }
+        if run.chars().count() > 1 && !full.is_empty() {
+            terms.push((full, run.clone()));
+            terms.push((initials, run));
+        }
+    }
+    terms
+}
+
+/// Keywords of a search: words, and Chinese characters one by one
+fn keywords(search: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = vec![];
+    for (run, cjk) in runs(search) {
+        if cjk {
+            keywords.extend(run.chars().map(String::from));
+        } else {
+            keywords.push(run.to_lowercase());
+        }
+    }
+    let mut seen = HashSet::new();
+    keywords.retain(|keyword| seen.insert(keyword.clone()));
+    keywords
+}
+
+/// Edit distance between `a` and `b`, or None if it exceeds `max`
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current[j + 1] = (previous[j] + usize::from(ca != cb))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        if current.iter().min().copied().unwrap_or_default() > max {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
+
+/// How well the keyword matches the term, from 0 for no match to 1 for the same term
+fn match_quality(keyword: &str, term: &str) -> f64 {
+    if keyword == term {
+        return 1.0;
+    }
+    let length = keyword.chars().count();
+    // A single Chinese character or letter only matches itself
+    if length < 2 || keyword.chars().any(is_cjk) {
+        return 0.0;
+    }
+    if term.starts_with(keyword) {
+        return PREFIX_MATCH;
+    }
+    if length >= 3 && term.contains(keyword) {
+        return INFIX_MATCH;
+    }
+    if length >= 4 && !term.chars().any(is_cjk) {
+        let max = if length >= 8 { 2 } else { 1 };
+        if let Some(distance) = edit_distance(keyword, term, max) {
+            return TYPO_MATCH[distance - 1];
+        }
+    }
+    0.0
+}
+
+impl Index {
+    fn add(&mut self, entity: Entity, key: String, fields: Vec<(&'static str, f64, String)>) {
+        let document = self.documents.len();
+        let mut document_fields = vec![];
+        for (field, (option, boost, text)) in fields.into_iter().enumerate() {
+            for (term, surface) in index_terms(&text) {
+                self.postings.entry(term).or_default().push(Posting {
+                    document,
+                    field,
+                    surface,
+                });
+            }
+            document_fields.push(Field { option, boost });
+        }
+        self.documents.push(Document {
+            entity,
+            key,
+            fields: document_fields,
+        });
+    }
+
+    async fn build(db: &mut Connection<BankManage>) -> Result<Self, GenericError> {
+        let mut index = Index::default();
+        for client in sqlx::query_as::<_, Client>("SELECT * FROM client")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Client,
+                client.clientID.clone(),
+                vec![
+                    ("Client.clientID", NAME_BOOST, client.clientID),
+                    (
+                        "Client.clientName",
+                        NAME_BOOST,
+                        client.clientName.unwrap_or_default(),
+                    ),
+                    (
+                        "Client.clientAddr",
+                        1.0,
+                        client.clientAddr.unwrap_or_default(),
+                    ),
+                ],
+            );
+        }
+        for contact in sqlx::query_as::<_, ClientContact>("SELECT * FROM clientcontact")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Contact,
+                contact.contactID,
+                vec![
+                    ("ClientContact.contactName", NAME_BOOST, contact.contactName),
+                    (
+                        "ClientContact.contactTel",
+                        1.0,
+                        contact.contactTel.unwrap_or_default(),
+                    ),
+                    (
+                        "ClientContact.contactEmail",
+                        1.0,
+                        contact.contactEmail.unwrap_or_default(),
+                    ),
+                ],
+            );
+        }
+        for note in sqlx::query_as::<_, ClientNote>("SELECT * FROM clientnote")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Note,
+                note.noteID,
+                vec![("ClientNote.content", 1.0, note.content)],
+            );
+        }
+        for account in sqlx::query_as::<_, Account>("SELECT * FROM account")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Account,
+                account.accountID.clone(),
+                vec![("Account.accountID", NAME_BOOST, account.accountID)],
+            );
+        }
+        for loan in sqlx::query_as::<_, Loan>("SELECT * FROM loan")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Loan,
+                loan.loanID.clone(),
+                vec![("Loan.loanID", NAME_BOOST, loan.loanID)],
+            );
+        }
+        for subbranch in sqlx::query_as::<_, Subbranch>("SELECT * FROM subbranch")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Subbranch,
+                subbranch.subbranchName.clone(),
+                vec![
+                    (
+                        "Subbranch.subbranchName",
+                        NAME_BOOST,
+                        subbranch.subbranchName,
+                    ),
+                    ("Subbranch.city", 1.0, subbranch.city),
+                ],
+            );
+        }
+        for employee in sqlx::query_as::<_, Employee>("SELECT * FROM employee")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Employee,
+                employee.employeeID.clone(),
+                vec![
+                    ("Employee.employeeID", NAME_BOOST, employee.employeeID),
+                    (
+                        "Employee.employeeTel",
+                        1.0,
+                        employee.employeeTel.unwrap_or_default(),
+                    ),
+                    (
+                        "Employee.employeeAddr",
+                        1.0,
+                        employee.employeeAddr.unwrap_or_default(),
+                    ),
+                ],
+            );
+        }
+        for department in sqlx::query_as::<_, Department>("SELECT * FROM department")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Department,
+                department.departmentID.clone(),
+                vec![
+                    (
+                        "Department.departmentID",
+                        NAME_BOOST,
+                        department.departmentID,
+                    ),
+                    (
+                        "Department.departmentName",
+                        NAME_BOOST,
+                        department.departmentName.unwrap_or_default(),
+                    ),
+                    (
+                        "Department.departmentType",
+                        1.0,
+                        department.departmentType.unwrap_or_default(),
+                    ),
+                ],
+            );
+        }
+        for payment in sqlx::query_as::<_, Payment>("SELECT * FROM payment")
+            .fetch_all(&mut **db)
+            .await?
+        {
+            index.add(
+                Entity::Payment,
+                payment.paymentID.clone(),
+                vec![
+                    ("Payment.paymentID", NAME_BOOST, payment.paymentID),
+                    ("Payment.loanID", 1.0, payment.loanID),
+                    ("Payment.date", 1.0, payment.date.to_string()),
+                    ("Payment.amount", 1.0, payment.amount.to_string()),
+                ],
+            );
+        }
+        Ok(index)
+    }
+
+    /// Documents matching every keyword in the attributes of `options`, by entity and key
+    fn search(&self, search: &str, options: &[String]) -> HashMap<(Entity, String), KeywordMatch> {
+        let keywords = keywords(search);
+        if keywords.is_empty() {
+            return HashMap::new();
+        }
+        let total = self.documents.len() as f64;
+        // Document -> (keywords matched, relevance, text matched)
+        let mut found: HashMap<usize, (usize, f64, HashSet<&str>)> = HashMap::new();
+        for keyword in &keywords {
+            // Document -> best score of the keyword, text matched
+            let mut scores: HashMap<usize, (f64, Vec<&str>)> = HashMap::new();
+            for (term, postings) in &self.postings {
+                let quality = match_quality(keyword, term);
+                if quality == 0.0 {
+                    continue;
+                }
+                let frequency = postings
+                    .iter()
+                    .map(|posting| posting.document)
+                    .collect::<HashSet<_>>()
+                    .len() as f64;
+                let rarity = (1.0 + total / frequency).ln();
+                for posting in postings {
+                    let field = &self.documents[posting.document].fields[posting.field];
+                    if !options.iter().any(|option| option == field.option) {
+                        continue;
+                    }
+                    let score = quality * rarity * field.boost;
+                    let entry = scores.entry(posting.document).or_insert((0.0, vec![]));
+                    entry.0 = entry.0.max(score);
+                    entry.1.push(&posting.surface);
+                }
+            }
+            for (document, (score, surfaces)) in scores {
+                let entry = found.entry(document).or_insert((0, 0.0, HashSet::new()));
+                entry.0 += 1;
+                entry.1 += score;
+                entry.2.extend(surfaces);
+            }
+        }
+        found
+            .into_iter()
+            .filter(|(_, (matched, _, _))| *matched == keywords.len())
+            .map(|(document, (_, relevance, surfaces))| {
+                let document = &self.documents[document];
+                let mut surfaces: Vec<&str> = surfaces.into_iter().collect();
+                // The longest first, so that a whole word is highlighted rather than a part of it
+                surfaces.sort_by_key(|surface| std::cmp::Reverse(surface.chars().count()));
+                (
+                    (document.entity, document.key.clone()),
+                    KeywordMatch {
+                        relevance,
+                        pattern: surfaces
+                            .into_iter()
+                            .map(regex::escape)
+                            .collect::<Vec<_>>()
+                            .join("|"),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// The index shared by the searches, managed by Rocket
+pub struct SearchIndex {
+    index: RwLock<Index>,
+    stale: AtomicBool,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        SearchIndex {
+            index: RwLock::new(Index::default()),
+            stale: AtomicBool::new(true),
+        }
+    }
+}
+
+impl SearchIndex {
+    pub fn invalidate(&self) {
+        self.stale.store(true, atomic::Ordering::SeqCst);
+    }
+
+    /// Searches the keywords in the attributes of `options`, rebuilding the index first if it is stale
+    pub async fn search(
+        &self,
+        db: &mut Connection<BankManage>,
+        search: &str,
+        options: &[String],
+    ) -> Result<HashMap<(Entity, String), KeywordMatch>, GenericError> {
+        if self.stale.load(atomic::Ordering::SeqCst) {
+            let mut index = self.index.write().await;
+            // Another search may have rebuilt it while waiting for the lock
+            if self.stale.swap(false, atomic::Ordering::SeqCst) {
+                match Index::build(db).await {
+                    Ok(built) => *index = built,
+                    Err(e) => {
+                        self.invalidate();
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(self.index.read().await.search(search, options))
+    }
+}
+
+/// Marks the search index stale after every request which may have changed the data
+pub struct IndexInvalidation;
+
+#[rocket::async_trait]
+impl Fairing for IndexInvalidation {
+    fn info(&self) -> Info {
+        Info {
+            name: "Search index invalidation",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _: &mut Response<'r>) {
+        let path = request.uri().path().as_str();
+        let read_only = request.method() == rocket::http::Method::Get
+            && (path == "/"
+                || READ_ONLY_PATHS
+                    .iter()
+                    .any(|prefix| path.starts_with(prefix)));
+        if !read_only {
+            if let Some(index) = request.rocket().state::<SearchIndex>() {
+                index.invalidate();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_edit_distance_within_the_bound() {
+        assert_eq!(edit_distance("zhang", "zhang", 0), Some(0));
+        assert_eq!(edit_distance("zhang", "zhanq", 1), Some(1));
+        assert_eq!(edit_distance("zhang", "zhangs", 1), Some(1));
+        assert_eq!(edit_distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(edit_distance("kitten", "sitting", 2), None);
+        // Lengths too far apart are rejected without comparing
+        assert_eq!(edit_distance("li", "liang", 2), None);
+        // Characters, not bytes, are counted
+        assert_eq!(edit_distance("张三", "张四", 1), Some(1));
+    }
+
+    #[test]
+    fn ranks_exact_prefix_infix_and_typo_matches() {
+        assert_eq!(match_quality("zhang", "zhang"), 1.0);
+        assert_eq!(match_quality("zha", "zhang"), PREFIX_MATCH);
+        assert_eq!(match_quality("han", "zhang"), INFIX_MATCH);
+        assert_eq!(match_quality("zhanq", "zhang"), TYPO_MATCH[0]);
+        assert_eq!(match_quality("subrench", "subbranch"), TYPO_MATCH[1]);
+    }
+
+    #[test]
+    fn leaves_out_short_and_distant_keywords() {
+        // A single letter or Chinese character only matches itself
+        assert_eq!(match_quality("z", "zhang"), 0.0);
+        assert_eq!(match_quality("张", "张三"), 0.0);
+        assert_eq!(match_quality("张", "张"), 1.0);
+        // Chinese keywords are not matched by prefix or typos
+        assert_eq!(match_quality("张三", "张三丰"), 0.0);
+        // Infixes need three characters, typos four, and two typos eight
+        assert_eq!(match_quality("an", "zhang"), 0.0);
+        assert_eq!(match_quality("zanq", "zhang"), 0.0);
+        assert_eq!(match_quality("zhnag", "zhang"), 0.0);
+    }
+}
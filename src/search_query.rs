@@ -0,0 +1,1073 @@
+//! A small query language for the search box, e.g.
+//! `type:loan subbranch:"Hefei Subbranch" amount>50000 status:being-paid`.
+//!
+//! - `field:value` matches a field containing the value, `field=value` an exact value
+//! - `>`, `>=`, `<` and `<=` compare amounts, balances and dates
+//! - `"quoted phrases"` keep their spaces
+//! - `-term` or `NOT term` negates a term
+//! - terms are combined with `AND` unless separated by `OR`, and can be grouped with parentheses
+//! - `client:` and `manager:` follow ownership and service, e.g. `type:account client:Zhang`
+//!   finds the accounts owned by clients named Zhang, and `type:loan manager:E001` the loans of
+//!   the clients served by employee E001
+//!
+//! A query compiles into a parameterized condition on the table of each entity.
+
+use crate::loan_profile::LoanStatus;
+use crate::search_filter::{
+    account_subbranch_condition, account_type_condition, loan_status_condition, AccountType,
+};
+use chrono::NaiveDate;
+use sqlx::types::BigDecimal;
+
+/// Entities that can be searched, with their tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Entity {
+    Client,
+    Contact,
+    Note,
+    Account,
+    Loan,
+    Subbranch,
+    Employee,
+    Department,
+    Payment,
+}
+
+impl Entity {
+    pub const ALL: [Entity; 9] = [
+        Self::Client,
+        Self::Contact,
+        Self::Note,
+        Self::Account,
+        Self::Loan,
+        Self::Subbranch,
+        Self::Employee,
+        Self::Department,
+        Self::Payment,
+    ];
+
+    /// Name of the entity in `type:` terms
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Contact => "contact",
+            Self::Note => "note",
+            Self::Account => "account",
+            Self::Loan => "loan",
+            Self::Subbranch => "subbranch",
+            Self::Employee => "employee",
+            Self::Department => "department",
+            Self::Payment => "payment",
+        }
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Contact => "clientcontact",
+            Self::Note => "clientnote",
+            Self::Account => "account",
+            Self::Loan => "loan",
+            Self::Subbranch => "subbranch",
+            Self::Employee => "employee",
+            Self::Department => "department",
+            Self::Payment => "payment",
+        }
+    }
+
+    /// Column identifying a row of the entity
+    pub fn key_column(&self) -> &'static str {
+        match self {
+            Self::Client => "clientID",
+            Self::Contact => "contactID",
+            Self::Note => "noteID",
+            Self::Account => "account.accountID",
+            Self::Loan => "loan.loanID",
+            Self::Subbranch => "subbranch.subbranchName",
+            Self::Employee => "employee.employeeID",
+            Self::Department => "department.departmentID",
+            Self::Payment => "payment.paymentID",
+        }
+    }
+
+    /// Columns matched by a term without a field
+    fn text_columns(&self) -> &'static [&'static str] {
+        match self {
+            Self::Client => &["clientID", "clientName", "clientAddr"],
+            Self::Contact => &["contactName", "contactTel", "contactEmail"],
+            Self::Note => &["content"],
+            Self::Account => &["account.accountID"],
+            Self::Loan => &["loan.loanID"],
+            Self::Subbranch => &["subbranchName", "city"],
+            Self::Employee => &["employee.employeeID", "employeeTel", "employeeAddr"],
+            Self::Department => &["departmentID", "departmentName", "departmentType"],
+            Self::Payment => &["payment.paymentID", "payment.loanID"],
+        }
+    }
+
+    /// Condition on the field of the entity, where `{}` stands for the comparison.
+    /// None if the entity has no such field.
+    fn field_condition(&self, field: Field) -> Option<String> {
+        let column = match (self, field) {
+            (Self::Client, Field::Id) => "clientID",
+            (Self::Client, Field::Name) => "clientName",
+            (Self::Client, Field::Address) => "clientAddr",
+            (Self::Client, Field::Tel) => "clientTel",
+            (Self::Client, Field::Manager) => "employeeID",
+            (Self::Client, Field::Client) => return Some(CLIENT_MATCH.to_string()),
+            (Self::Client, Field::Department) => {
+                return Some(format!(
+                    "employeeID IN (SELECT employeeID FROM employee WHERE {})",
+                    department_condition("employee.departmentID")
+                ))
+            }
+            (Self::Contact, Field::Name) => "contactName",
+            (Self::Contact, Field::Tel) => "contactTel",
+            (Self::Contact, Field::Email) => "contactEmail",
+            (Self::Contact | Self::Note, Field::Client) => {
+                return Some(format!(
+                    "clientID IN (SELECT clientID FROM client WHERE {CLIENT_MATCH})"
+                ))
+            }
+            (Self::Note, Field::Content) => "content",
+            (Self::Note, Field::Author) => "authorID",
+            (Self::Note, Field::Date) => "DATE(createdAt)",
+            (Self::Account, Field::Id) => "account.accountID",
+            (Self::Account, Field::Balance) => "account.balance",
+            (Self::Account, Field::Date) => "account.openDate",
+            (Self::Account, Field::Currency) => {
+                return Some(
+                    "account.accountID IN (SELECT accountID FROM savingaccount WHERE currencyType{})"
+                        .to_string(),
+                )
+            }
+            (Self::Account, Field::Subbranch) => {
+                return Some(account_subbranch_condition(
+                    "accountmanagement.subbranchName{}",
+                ))
+            }
+            (Self::Account, Field::City) => {
+                return Some(account_subbranch_condition(
+                    "accountmanagement.subbranchName IN (SELECT subbranchName FROM subbranch WHERE city{})",
+                ))
+            }
+            (Self::Account, Field::Client) => {
+                return Some(account_client_condition("account.accountID", CLIENT_MATCH))
+            }
+            (Self::Account, Field::Manager) => {
+                return Some(account_client_condition("account.accountID", MANAGER_MATCH))
+            }
+            (Self::Loan, Field::Id | Field::Loan) => "loan.loanID",
+            (Self::Loan, Field::Amount) => "loan.amount",
+            (Self::Loan, Field::Subbranch) => "loan.subbranchName",
+            (Self::Loan, Field::City) => {
+                return Some(
+                    "loan.subbranchName IN (SELECT subbranchName FROM subbranch WHERE city{})"
+                        .to_string(),
+                )
+            }
+            (Self::Subbranch, Field::Name | Field::Subbranch) => "subbranchName",
+            (Self::Loan, Field::Client) => {
+                return Some(loan_client_condition("loan.loanID", CLIENT_MATCH))
+            }
+            (Self::Loan, Field::Manager) => {
+                return Some(loan_client_condition("loan.loanID", MANAGER_MATCH))
+            }
+            (Self::Subbranch, Field::City) => "city",
+            (Self::Employee, Field::Id | Field::Manager) => "employee.employeeID",
+            (Self::Employee, Field::Tel) => "employeeTel",
+            (Self::Employee, Field::Address) => "employeeAddr",
+            (Self::Employee, Field::Date) => "employmentCommenceDate",
+            (Self::Employee, Field::Subbranch) => "employee.subbranchName",
+            (Self::Employee, Field::City) => {
+                return Some(
+                    "employee.subbranchName IN (SELECT subbranchName FROM subbranch WHERE city{})"
+                        .to_string(),
+                )
+            }
+            (Self::Employee, Field::Department) => {
+                return Some(department_condition("employee.departmentID"))
+            }
+            (Self::Department, Field::Id) => "departmentID",
+            (Self::Department, Field::Name) => "departmentName",
+            (Self::Department, Field::Department) => {
+                return Some("(departmentID{} OR departmentName{})".to_string())
+            }
+            (Self::Payment, Field::Id) => "payment.paymentID",
+            (Self::Payment, Field::Loan) => "payment.loanID",
+            (Self::Payment, Field::Date) => "payment.date",
+            (Self::Payment, Field::Amount) => "payment.amount",
+            (Self::Payment, Field::Subbranch) => {
+                return Some(
+                    "payment.loanID IN (SELECT loanID FROM loan WHERE subbranchName{})".to_string(),
+                )
+            }
+            (Self::Payment, Field::Client) => {
+                return Some(loan_client_condition("payment.loanID", CLIENT_MATCH))
+            }
+            (Self::Payment, Field::Manager) => {
+                return Some(loan_client_condition("payment.loanID", MANAGER_MATCH))
+            }
+            _ => return None,
+        };
+        Some(format!("{column}{{}}"))
+    }
+}
+
+/// Condition on `client` that the client has the ID or name, where `{}` stands for the comparison
+const CLIENT_MATCH: &str = "(client.clientID{} OR client.clientName{})";
+/// Condition on `client` that the client is served by the employee
+const MANAGER_MATCH: &str = "client.employeeID{}";
+
+/// Condition that one of the clients owning the account in `column` meets `condition`
+fn account_client_condition(column: &str, condition: &str) -> String {
+    format!("{column} IN (SELECT own.accountID FROM own JOIN client ON own.clientID=client.clientID WHERE {condition})")
+}
+
+/// Condition that one of the clients receiving the loan in `column` meets `condition`
+fn loan_client_condition(column: &str, condition: &str) -> String {
+    format!("{column} IN (SELECT receiveloan.loanID FROM receiveloan JOIN client ON receiveloan.clientID=client.clientID WHERE {condition})")
+}
+
+/// Condition that the department in `column` has the ID or name
+fn department_condition(column: &str) -> String {
+    format!("{column} IN (SELECT departmentID FROM department WHERE departmentID{{}} OR departmentName{{}})")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Text,
+    Decimal,
+    Date,
+    Type,
+    AccountType,
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Type,
+    Id,
+    Name,
+    Address,
+    Tel,
+    Email,
+    Manager,
+    Content,
+    Author,
+    Date,
+    Balance,
+    Currency,
+    AccountType,
+    Amount,
+    Status,
+    Subbranch,
+    City,
+    Client,
+    Department,
+    Loan,
+}
+
+/// Field names, with their aliases, as typed in queries
+const FIELDS: [(&str, Field); 24] = [
+    ("type", Field::Type),
+    ("id", Field::Id),
+    ("name", Field::Name),
+    ("addr", Field::Address),
+    ("address", Field::Address),
+    ("tel", Field::Tel),
+    ("email", Field::Email),
+    ("manager", Field::Manager),
+    ("employee", Field::Manager),
+    ("content", Field::Content),
+    ("author", Field::Author),
+    ("date", Field::Date),
+    ("opened", Field::Date),
+    ("balance", Field::Balance),
+    ("currency", Field::Currency),
+    ("account_type", Field::AccountType),
+    ("amount", Field::Amount),
+    ("status", Field::Status),
+    ("subbranch", Field::Subbranch),
+    ("city", Field::City),
+    ("client", Field::Client),
+    ("owner", Field::Client),
+    ("department", Field::Department),
+    ("loan", Field::Loan),
+];
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        FIELDS
+            .iter()
+            .find(|(field_name, _)| field_name.eq_ignore_ascii_case(name))
+            .map(|(_, field)| *field)
+    }
+
+    fn kind(&self) -> FieldKind {
+        match self {
+            Self::Type => FieldKind::Type,
+            Self::AccountType => FieldKind::AccountType,
+            Self::Status => FieldKind::Status,
+            Self::Balance | Self::Amount => FieldKind::Decimal,
+            Self::Date => FieldKind::Date,
+            _ => FieldKind::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    /// `:`, contains for text and equals otherwise
+    Match,
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Operator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Match => ":",
+            Self::Equal => "=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+        }
+    }
+
+    fn to_sql(self) -> &'static str {
+        match self {
+            Self::Match | Self::Equal => " = ?",
+            Self::Greater => " > ?",
+            Self::GreaterOrEqual => " >= ?",
+            Self::Less => " < ?",
+            Self::LessOrEqual => " <= ?",
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        !matches!(self, Self::Match | Self::Equal)
+    }
+}
+
+#[derive(Debug)]
+pub struct QuerySyntaxError {
+    pub info: String,
+    /// Position in the query, counting characters from 1
+    pub position: usize,
+}
+
+impl std::fmt::Display for QuerySyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.info, self.position)
+    }
+}
+
+impl std::error::Error for QuerySyntaxError {}
+
+fn syntax_error(info: String, position: usize) -> QuerySyntaxError {
+    QuerySyntaxError {
+        info,
+        position: position + 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    And,
+    Or,
+    Not,
+    /// A term without field, quoted or not
+    Text {
+        value: String,
+        quoted: bool,
+    },
+    Field {
+        name: String,
+        operator: Operator,
+        value: String,
+    },
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '"'
+}
+
+/// Splits the query into tokens, each along with its position
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QuerySyntaxError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    let read_quoted = |i: &mut usize| -> Result<String, QuerySyntaxError> {
+        let start = *i;
+        *i += 1;
+        let mut value = String::new();
+        while *i < chars.len() && chars[*i] != '"' {
+            value.push(chars[*i]);
+            *i += 1;
+        }
+        if *i == chars.len() {
+            return Err(syntax_error("Unclosed quotation mark".to_string(), start));
+        }
+        *i += 1;
+        Ok(value)
+    };
+    let read_word = |i: &mut usize| -> String {
+        let mut value = String::new();
+        while *i < chars.len() && !is_delimiter(chars[*i]) {
+            value.push(chars[*i]);
+            *i += 1;
+        }
+        value
+    };
+
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                tokens.push((Token::LeftParen, start));
+            }
+            ')' => {
+                i += 1;
+                tokens.push((Token::RightParen, start));
+            }
+            '"' => {
+                let value = read_quoted(&mut i)?;
+                tokens.push((
+                    Token::Text {
+                        value,
+                        quoted: true,
+                    },
+                    start,
+                ));
+            }
+            '-' if i + 1 < chars.len() && !chars[i + 1].is_whitespace() => {
+                i += 1;
+                tokens.push((Token::Not, start));
+            }
+            _ => {
+                let mut name = String::new();
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    name.push(chars[i]);
+                    i += 1;
+                }
+                let operator = match (chars.get(i), chars.get(i + 1)) {
+                    _ if name.is_empty() => None,
+                    (Some('>'), Some('=')) => Some((Operator::GreaterOrEqual, 2)),
+                    (Some('<'), Some('=')) => Some((Operator::LessOrEqual, 2)),
+                    (Some('>'), _) => Some((Operator::Greater, 1)),
+                    (Some('<'), _) => Some((Operator::Less, 1)),
+                    (Some('='), _) => Some((Operator::Equal, 1)),
+                    (Some(':'), _) => Some((Operator::Match, 1)),
+                    _ => None,
+                };
+                match operator {
+                    Some((operator, length)) => {
+                        i += length;
+                        let value = match chars.get(i) {
+                            Some('"') => read_quoted(&mut i)?,
+                            _ => read_word(&mut i),
+                        };
+                        if value.is_empty() {
+                            return Err(syntax_error(
+                                format!("Missing value after {name}{}", operator.as_str()),
+                                start,
+                            ));
+                        }
+                        tokens.push((
+                            Token::Field {
+                                name,
+                                operator,
+                                value,
+                            },
+                            start,
+                        ));
+                    }
+                    None => {
+                        let value = name + &read_word(&mut i);
+                        tokens.push((
+                            match value.as_str() {
+                                "AND" => Token::And,
+                                "OR" | "|" => Token::Or,
+                                "NOT" => Token::Not,
+                                _ => Token::Text {
+                                    value,
+                                    quoted: false,
+                                },
+                            },
+                            start,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// A term whose field and value have been checked
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Text(String),
+    Type(Entity),
+    AccountType(AccountType),
+    Status(LoanStatus),
+    Field {
+        field: Field,
+        operator: Operator,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+fn parse_term(token: Token, position: usize) -> Result<Term, QuerySyntaxError> {
+    let (name, operator, value) = match token {
+        Token::Text { value, .. } => return Ok(Term::Text(value)),
+        Token::Field {
+            name,
+            operator,
+            value,
+        } => (name, operator, value),
+        _ => unreachable!(),
+    };
+    let field = Field::parse(&name).ok_or_else(|| {
+        syntax_error(
+            format!(
+                "Unknown field {name}, expecting one of {}",
+                FIELDS
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            position,
+        )
+    })?;
+    let kind = field.kind();
+    if operator.is_comparison() && !matches!(kind, FieldKind::Decimal | FieldKind::Date) {
+        return Err(syntax_error(
+            format!("{name} cannot be compared with {}", operator.as_str()),
+            position,
+        ));
+    }
+    let normalized = value.to_lowercase().replace('-', "_");
+    match kind {
+        FieldKind::Type => Entity::ALL
+            .into_iter()
+            .find(|entity| normalized.trim_end_matches('s') == entity.as_str())
+            .map(Term::Type)
+            .ok_or_else(|| {
+                syntax_error(
+                    format!(
+                        "Unknown type {value}, expecting one of {}",
+                        Entity::ALL.map(|entity| entity.as_str()).join(", ")
+                    ),
+                    position,
+                )
+            }),
+        FieldKind::AccountType => AccountType::ALL
+            .into_iter()
+            .find(|account_type| normalized == account_type.as_str())
+            .map(Term::AccountType)
+            .ok_or_else(|| {
+                syntax_error(
+                    format!("Unknown account type {value}, expecting saving or checking"),
+                    position,
+                )
+            }),
+        FieldKind::Status => LoanStatus::ALL
+            .into_iter()
+            .find(|status| normalized == status.as_str())
+            .map(Term::Status)
+            .ok_or_else(|| {
+                syntax_error(
+                    format!(
+                        "Unknown loan status {value}, expecting one of {}",
+                        LoanStatus::ALL
+                            .map(|status| status.as_str().replace('_', "-"))
+                            .join(", ")
+                    ),
+                    position,
+                )
+            }),
+        FieldKind::Decimal => match value.parse::<BigDecimal>() {
+            Ok(decimal) => Ok(Term::Field {
+                field,
+                operator,
+                value: decimal.to_string(),
+            }),
+            Err(_) => Err(syntax_error(
+                format!("{name} should be compared with a number, not {value}"),
+                position,
+            )),
+        },
+        FieldKind::Date => match NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+            Ok(date) => Ok(Term::Field {
+                field,
+                operator,
+                value: date.to_string(),
+            }),
+            Err(_) => Err(syntax_error(
+                format!("{name} should be compared with a date like 2022-06-30, not {value}"),
+                position,
+            )),
+        },
+        FieldKind::Text => Ok(Term::Field {
+            field,
+            operator,
+            value,
+        }),
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Length of the query, where an unexpected end is reported
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map(|(_, position)| *position)
+            .unwrap_or(self.end)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QuerySyntaxError> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expr::Or(operands)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QuerySyntaxError> {
+        let mut operands = vec![];
+        loop {
+            match self.peek() {
+                None | Some(Token::RightParen) | Some(Token::Or) => break,
+                Some(Token::And) => self.next += 1,
+                _ => operands.push(self.parse_unary()?),
+            }
+        }
+        match operands.len() {
+            0 => Err(syntax_error(
+                "Expecting a search term".to_string(),
+                self.position(),
+            )),
+            1 => Ok(operands.remove(0)),
+            _ => Ok(Expr::And(operands)),
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QuerySyntaxError> {
+        let position = self.position();
+        let token = match self.tokens.get(self.next) {
+            Some((token, _)) => token.clone(),
+            None => {
+                return Err(syntax_error(
+                    "Expecting a search term".to_string(),
+                    position,
+                ))
+            }
+        };
+        self.next += 1;
+        match token {
+            Token::Not => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Token::LeftParen => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::RightParen) {
+                    return Err(syntax_error("Unclosed parenthesis".to_string(), position));
+                }
+                self.next += 1;
+                Ok(expr)
+            }
+            Token::Text { .. } | Token::Field { .. } => {
+                Ok(Expr::Term(parse_term(token, position)?))
+            }
+            _ => Err(syntax_error(
+                "Expecting a search term".to_string(),
+                position,
+            )),
+        }
+    }
+}
+
+/// A condition compiled for one entity, folded when it is known to hold or fail
+#[derive(Debug, Clone)]
+pub enum Condition {
+    True,
+    False,
+    Sql { clause: String, values: Vec<String> },
+}
+
+impl Condition {
+    pub fn sql(clause: String, values: Vec<String>) -> Self {
+        Self::Sql { clause, values }
+    }
+
+    fn not(self) -> Self {
+        match self {
+            Self::True => Self::False,
+            Self::False => Self::True,
+            Self::Sql { clause, values } => Self::sql(format!("NOT ({clause})"), values),
+        }
+    }
+
+    /// Joins the operands with `and`, or with `OR` if `and` is false
+    fn combine(operands: Vec<Condition>, and: bool) -> Self {
+        let mut clauses = vec![];
+        let mut values = vec![];
+        for operand in operands {
+            match operand {
+                Self::Sql {
+                    clause,
+                    values: operand_values,
+                } => {
+                    clauses.push(format!("({clause})"));
+                    values.extend(operand_values);
+                }
+                Self::False if and => return Self::False,
+                Self::True if !and => return Self::True,
+                _ => (),
+            }
+        }
+        match (clauses.is_empty(), and) {
+            (true, true) => Self::True,
+            (true, false) => Self::False,
+            (false, _) => Self::sql(clauses.join(if and { " and " } else { " OR " }), values),
+        }
+    }
+}
+
+fn escape_like(value: &str) -> String {
+    format!(
+        "%{}%",
+        value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+fn compile_term(term: &Term, entity: Entity) -> Condition {
+    match term {
+        Term::Text(value) => Condition::combine(
+            entity
+                .text_columns()
+                .iter()
+                .map(|column| Condition::sql(format!("{column} LIKE ?"), vec![escape_like(value)]))
+                .collect(),
+            false,
+        ),
+        Term::Type(expected) => {
+            if *expected == entity {
+                Condition::True
+            } else {
+                Condition::False
+            }
+        }
+        Term::AccountType(account_type) => match entity {
+            Entity::Account => {
+                Condition::sql(account_type_condition(*account_type).to_string(), vec![])
+            }
+            _ => Condition::False,
+        },
+        Term::Status(status) => match entity {
+            Entity::Loan => Condition::sql(loan_status_condition(*status), vec![]),
+            _ => Condition::False,
+        },
+        Term::Field {
+            field,
+            operator,
+            value,
+        } => match entity.field_condition(*field) {
+            Some(condition) => {
+                let (comparison, value) = match (field.kind(), operator) {
+                    (FieldKind::Text, Operator::Match) => (" LIKE ?", escape_like(value)),
+                    _ => (operator.to_sql(), value.clone()),
+                };
+                // A relation may compare the value with several columns
+                let count = condition.matches("{}").count();
+                Condition::sql(condition.replace("{}", comparison), vec![value; count])
+            }
+            None => Condition::False,
+        },
+    }
+}
+
+fn compile(expr: &Expr, entity: Entity) -> Condition {
+    match expr {
+        Expr::Term(term) => compile_term(term, entity),
+        Expr::Not(expr) => compile(expr, entity).not(),
+        Expr::And(operands) => Condition::combine(
+            operands.iter().map(|expr| compile(expr, entity)).collect(),
+            true,
+        ),
+        Expr::Or(operands) => Condition::combine(
+            operands.iter().map(|expr| compile(expr, entity)).collect(),
+            false,
+        ),
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchQuery {
+    expr: Expr,
+    /// No field, quotation, negation or OR is used, so the query is a plain keyword search
+    plain: bool,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Result<Self, QuerySyntaxError> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Ok(SearchQuery {
+                expr: Expr::And(vec![]),
+                plain: true,
+            });
+        }
+        let plain = tokens
+            .iter()
+            .all(|(token, _)| matches!(token, Token::Text { quoted: false, .. }));
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            end: query.chars().count(),
+        };
+        let expr = parser.parse_or()?;
+        if parser.peek().is_some() {
+            return Err(syntax_error(
+                "Unmatched closing parenthesis".to_string(),
+                parser.position(),
+            ));
+        }
+        Ok(SearchQuery { expr, plain })
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.plain
+    }
+
+    /// Condition on the table of the entity
+    pub fn compile(&self, entity: Entity) -> Condition {
+        compile(&self.expr, entity)
+    }
+
+    /// Regex matching the text searched for, to be highlighted in the results
+    pub fn highlight_pattern(&self) -> Option<String> {
+        fn collect(expr: &Expr, negated: bool, values: &mut Vec<String>) {
+            match expr {
+                Expr::Term(Term::Text(value))
+                | Expr::Term(Term::Field {
+                    operator: Operator::Match,
+                    value,
+                    ..
+                }) if !negated => values.push(regex::escape(value)),
+                Expr::Not(expr) => collect(expr, !negated, values),
+                Expr::And(operands) | Expr::Or(operands) => {
+                    for operand in operands {
+                        collect(operand, negated, values);
+                    }
+                }
+                _ => (),
+            }
+        }
+        let mut values = vec![];
+        collect(&self.expr, false, &mut values);
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join("|"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(query: &str) -> QuerySyntaxError {
+        SearchQuery::parse(query).unwrap_err()
+    }
+
+    /// Clause and bound values of the query compiled for the entity
+    fn compile_sql(query: &str, entity: Entity) -> (String, Vec<String>) {
+        match SearchQuery::parse(query).unwrap().compile(entity) {
+            Condition::Sql { clause, values } => (clause, values),
+            condition => panic!("{query} compiled into {condition:?} for {entity:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_unclosed_quotation_marks() {
+        let error = parse_error("\"Hefei Subbranch");
+        assert_eq!(error.info, "Unclosed quotation mark");
+        assert_eq!(error.position, 1);
+
+        let error = parse_error("type:loan subbranch:\"Hefei");
+        assert_eq!(error.info, "Unclosed quotation mark");
+        assert_eq!(error.position, 21);
+    }
+
+    #[test]
+    fn reports_unbalanced_parentheses() {
+        let error = parse_error("(zhang OR li");
+        assert_eq!(error.info, "Unclosed parenthesis");
+        assert_eq!(error.position, 1);
+
+        let error = parse_error("zhang)");
+        assert_eq!(error.info, "Unmatched closing parenthesis");
+        assert_eq!(error.position, 6);
+
+        assert_eq!(parse_error("()").info, "Expecting a search term");
+    }
+
+    #[test]
+    fn reports_missing_values_and_unknown_fields() {
+        assert_eq!(parse_error("name:").info, "Missing value after name:");
+        assert!(parse_error("colour:red")
+            .info
+            .starts_with("Unknown field colour"));
+        assert_eq!(
+            parse_error("name>zhang").info,
+            "name cannot be compared with >"
+        );
+    }
+
+    #[test]
+    fn negates_with_a_dash_or_not() {
+        let expected = Expr::Not(Box::new(Expr::Term(Term::Text("zhang".to_string()))));
+        for query in ["-zhang", "NOT zhang"] {
+            let search_query = SearchQuery::parse(query).unwrap();
+            assert_eq!(search_query.expr, expected, "{query}");
+            assert!(!search_query.is_plain());
+        }
+        // A dash inside a word or followed by a space is searched for
+        assert_eq!(
+            SearchQuery::parse("zhang-san").unwrap().expr,
+            Expr::Term(Term::Text("zhang-san".to_string()))
+        );
+
+        let (clause, values) = compile_sql("-zhang", Entity::Client);
+        assert_eq!(
+            clause,
+            "NOT ((clientID LIKE ?) OR (clientName LIKE ?) OR (clientAddr LIKE ?))"
+        );
+        assert_eq!(values, vec!["%zhang%"; 3]);
+    }
+
+    #[test]
+    fn folds_negated_type_terms() {
+        assert!(matches!(
+            SearchQuery::parse("-type:loan")
+                .unwrap()
+                .compile(Entity::Loan),
+            Condition::False
+        ));
+        assert!(matches!(
+            SearchQuery::parse("NOT type:loan")
+                .unwrap()
+                .compile(Entity::Client),
+            Condition::True
+        ));
+    }
+
+    #[test]
+    fn binds_a_value_for_each_placeholder_of_a_relation() {
+        let (clause, values) = compile_sql("client:Zhang", Entity::Account);
+        assert_eq!(clause.matches('?').count(), 2);
+        assert_eq!(values, vec!["%Zhang%"; 2]);
+
+        let (clause, values) = compile_sql("department:Loans", Entity::Client);
+        assert_eq!(clause.matches('?').count(), 2);
+        assert_eq!(values, vec!["%Loans%"; 2]);
+
+        let (clause, values) = compile_sql("department=D01", Entity::Employee);
+        assert_eq!(clause.matches('?').count(), 2);
+        assert_eq!(values, vec!["D01"; 2]);
+    }
+
+    #[test]
+    fn binds_as_many_values_as_placeholders_for_every_field() {
+        let queries = [
+            "id:1",
+            "name:a",
+            "addr:a",
+            "tel:1",
+            "email:a",
+            "manager:E1",
+            "content:a",
+            "author:E1",
+            "date>=2022-01-01",
+            "balance<100",
+            "currency:CNY",
+            "amount>100",
+            "subbranch:a",
+            "city:a",
+            "client:a",
+            "department:a",
+            "loan:L1",
+            "zhang",
+        ];
+        for entity in Entity::ALL {
+            for query in queries {
+                if let Condition::Sql { clause, values } =
+                    SearchQuery::parse(query).unwrap().compile(entity)
+                {
+                    assert_eq!(
+                        clause.matches('?').count(),
+                        values.len(),
+                        "{query} for {entity:?}: {clause}"
+                    );
+                    assert!(!clause.contains("{}"), "{query} for {entity:?}: {clause}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(escape_like("zhang"), "%zhang%");
+        assert_eq!(escape_like(r"50%_off\"), r"%50\%\_off\\%");
+
+        let (clause, values) = compile_sql("name:100%", Entity::Client);
+        assert_eq!(clause, "clientName LIKE ?");
+        assert_eq!(values, vec![r"%100\%%"]);
+
+        // Exact matches are not patterns
+        let (clause, values) = compile_sql("name=100%", Entity::Client);
+        assert_eq!(clause, "clientName = ?");
+        assert_eq!(values, vec!["100%"]);
+    }
+}
@@ -0,0 +1,106 @@
+use super::preludes::rocket_prelude::*;
+use crate::utility::GenericError;
+
+/// Services a client uses, derived from the accounts and loans it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceType {
+    None,
+    Account,
+    Loan,
+    Both,
+}
+
+impl ServiceType {
+    pub const ALL: [ServiceType; 4] = [Self::None, Self::Account, Self::Loan, Self::Both];
+
+    pub fn new(holds_account: bool, holds_loan: bool) -> Self {
+        match (holds_account, holds_loan) {
+            (false, false) => Self::None,
+            (true, false) => Self::Account,
+            (false, true) => Self::Loan,
+            (true, true) => Self::Both,
+        }
+    }
+
+    /// Value stored in `client`.`serviceType`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Account => "account",
+            Self::Loan => "loan",
+            Self::Both => "both",
+        }
+    }
+
+    pub fn parse(value: Option<&str>) -> Result<Self, ServiceTypeError> {
+        let value = value.unwrap_or_default();
+        Self::ALL
+            .into_iter()
+            .find(|service_type| service_type.as_str() == value)
+            .ok_or_else(|| ServiceTypeError {
+                value: value.to_string(),
+            })
+    }
+}
+
+impl std::fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::None => "None",
+                Self::Account => "Account service",
+                Self::Loan => "Loan service",
+                Self::Both => "Account and loan service",
+            }
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ServiceTypeError {
+    pub value: String,
+}
+
+impl std::fmt::Display for ServiceTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unexpected service type {}", self.value)
+    }
+}
+
+impl std::error::Error for ServiceTypeError {}
+
+pub async fn derive_service_type(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<ServiceType, GenericError> {
+    let row = sqlx::query(
+        "SELECT EXISTS(SELECT * FROM own WHERE clientID=?), EXISTS(SELECT * FROM receiveloan WHERE clientID=?)",
+    )
+    .bind(client_id)
+    .bind(client_id)
+    .fetch_one(&mut **db)
+    .await?;
+    Ok(ServiceType::new(
+        row.try_get::<'_, i64, _>(0)? != 0,
+        row.try_get::<'_, i64, _>(1)? != 0,
+    ))
+}
+
+/// Stores the service type derived from what the client holds. Called whenever an account or a loan changes hands.
+pub async fn refresh_service_type(
+    db: &mut Connection<BankManage>,
+    client_id: &str,
+) -> Result<ServiceType, GenericError> {
+    let service_type = derive_service_type(db, client_id).await?;
+    sqlx::query("UPDATE client SET serviceType=? WHERE clientID=?")
+        .bind(match service_type {
+            ServiceType::None => None,
+            service_type => Some(service_type.as_str()),
+        })
+        .bind(client_id)
+        .execute(&mut **db)
+        .await?;
+    Ok(service_type)
+}
@@ -1,211 +1,407 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
-use chrono::Datelike;
+use bigdecimal::{ToPrimitive, Zero};
+use rocket::http::RawStr;
 use sqlx::types::BigDecimal;
 
 use super::preludes::rocket_prelude::*;
-use crate::unwrap_or_return;
+use crate::account_manage::{
+    delete::AccountType,
+    movement::{query_subbranch_movements, MovementKind},
+};
+use crate::chart::{self, ChartKind, ChartPoint};
+use crate::export::{Export, ExportFormat, Table};
+use crate::reporting_period::{Granularity, GranularityOption, ReportingPeriod};
 use crate::utility::GenericError;
+use crate::{error_template, unwrap_or, unwrap_or_return};
 use sqlx::types::chrono::NaiveDate;
 
-const DISPLAY_DURATION_YEAR: usize = 5;
+#[derive(Serialize)]
+struct PeriodStatistic {
+    label: String,
+    amount: String,
+    count: String,
+}
 
-// statistic =  [year; Number of year]
-// year = (year title, year total, [season; 4])
-// season = (season title, season total, [month; 3])
-// month = (month title, month amount, month number)
-type DisplayedStatistic = [(
-    String,
-    (String, String),
-    [(String, (String, String), [(String, (String, String)); 3]); 4],
-); DISPLAY_DURATION_YEAR];
+/// Statistic of each period, the latest first
+#[derive(Serialize)]
+struct DisplayedStatistic {
+    id: &'static str,
+    description: &'static str,
+    /// Whether a period shows a level at its end rather than a flow during it
+    is_balance: bool,
+    periods: Vec<PeriodStatistic>,
+    /// Inline SVG of the periods, the earliest first
+    chart: String,
+    /// Sum over the range, only for flows
+    total_amount: Option<String>,
+    total_count: Option<String>,
+}
 
 #[derive(Serialize)]
 struct SubbranchProfileContext {
     subbranch_name: String,
+    /// The name as it is put in a query string
+    subbranch_query: String,
     subbranch_city: String,
     subbranch_asset: String,
-    statistics: HashMap<String, DisplayedStatistic>,
+    from: String,
+    to: String,
+    granularity: &'static str,
+    granularities: Vec<GranularityOption>,
+    statistics: Vec<DisplayedStatistic>,
 }
 
-type Statistic<T> = [[[T; 3]; 4]; DISPLAY_DURATION_YEAR];
-
-fn get_statistics<InputIter, T, F>(
+/// Accumulates the items into the periods their dates fall in. Items out of the range are ignored.
+pub fn get_statistics<InputIter, T, F>(
     input_iter: InputIter,
-    start_date: &NaiveDate,
+    reporting_period: &ReportingPeriod,
     f: F,
-) -> Statistic<T>
+) -> Vec<T>
 where
     T: Default,
     InputIter: Iterator<Item = (T, NaiveDate)>,
     F: Fn(&mut T, &T),
 {
-    let mut result = Statistic::<T>::default();
-    for (item, date) in input_iter.filter(|(_, date)| date >= start_date) {
-        let (year, season, offset) = (date.year(), date.month0() / 3u32, date.month0() % 3);
-        f(
-            &mut result[(year - start_date.year()) as usize][season as usize][offset as usize],
-            &item,
-        );
+    let mut result: Vec<T> = reporting_period
+        .periods
+        .iter()
+        .map(|_| T::default())
+        .collect();
+    for (item, date) in input_iter {
+        if let Some(index) = reporting_period.index_of(date) {
+            f(&mut result[index], &item);
+        }
     }
     result
 }
 
-#[get("/profile/subbranch?<name>")]
-pub async fn subbranch_profile(mut db: Connection<BankManage>, name: &str) -> Template {
-    // subbranch info
-    let subbranch = unwrap_or_return!(
-        query_subbranch(&mut db, name).await,
-        "Error querying subbranch"
-    );
-
-    // associated accounts
-    let mut accounts: [HashSet<Account>; 2] = Default::default();
-    for (i, specific_account_ids) in unwrap_or_return!(
-        query_associated_account(&mut db, name).await,
-        "Error querying account"
-    )
-    .into_iter()
-    .enumerate()
-    {
-        let mut specific_accounts = HashSet::new();
-        for specific_account_id in specific_account_ids {
-            specific_accounts.insert(Account::from(
-                unwrap_or_return!(
-                    crate::account_manage::query::query_account_by_id(
-                        &mut db,
-                        &specific_account_id
-                    )
-                    .await,
-                    "Error querying account details"
-                )
-                .0,
-            ));
+/// Balance and number of open accounts at the end of each period, following the movements of the accounts
+pub fn get_balances<'a, InputIter>(
+    movements: InputIter,
+    reporting_period: &ReportingPeriod,
+) -> Vec<(BigDecimal, u32)>
+where
+    InputIter: Iterator<Item = &'a AccountMovement>,
+{
+    let mut movements: Vec<_> = movements.collect();
+    movements.sort_by_key(|movement| movement.date);
+    let mut movements = movements.into_iter().peekable();
+    let (mut balance, mut open_accounts) = (BigDecimal::zero(), 0i64);
+    let mut result = vec![];
+    for period in &reporting_period.periods {
+        while let Some(movement) = movements.next_if(|movement| movement.date < period.end) {
+            balance += &movement.amount;
+            if movement.kind == MovementKind::Open.as_str() {
+                open_accounts += 1;
+            } else if movement.kind == MovementKind::Close.as_str() {
+                open_accounts -= 1;
+            }
         }
-        accounts[i] = specific_accounts;
+        result.push((balance.clone(), open_accounts.max(0) as u32));
     }
+    result
+}
+
+/// Values of one statistic in each period, the earliest first
+struct Series {
+    id: &'static str,
+    description: &'static str,
+    /// Whether a period shows a level at its end rather than a flow during it
+    is_balance: bool,
+    values: Vec<(BigDecimal, u32)>,
+}
+
+/// Statistics of the subbranch over the reporting period
+async fn query_statistics(
+    db: &mut Connection<BankManage>,
+    name: &str,
+    reporting_period: &ReportingPeriod,
+) -> Result<Vec<Series>, GenericError> {
+    // movements of the associated accounts, including the closed ones
+    let movements = query_subbranch_movements(db, name).await?;
 
     //associated loans
-    let loans = unwrap_or_return!(
-        query_associated_loans(&mut db, name).await,
-        "Error querying loans"
-    );
+    let loans = query_associated_loans(db, name).await?;
 
-    let mut loans_and_payment = HashSet::<(Loan, Vec<Payment>)>::new();
+    let mut disbursements = vec![];
+    let mut payments = vec![];
+    let mut write_offs = vec![];
+    let mut recoveries = vec![];
     for loan in loans {
-        let (_, _, payments) = unwrap_or_return!(
-            crate::loan_profile::query_loan(&mut db, &loan.loanID).await,
-            "Error querying payments"
-        );
-        loans_and_payment.insert((loan, payments));
+        // The first version of the agreement is made when the loan is granted
+        if let Some(agreement) = crate::loan_agreement::query_agreements(db, &loan.loanID)
+            .await?
+            .pop()
+        {
+            disbursements.push(agreement);
+        }
+        let (_, _, loan_payments) = crate::loan_profile::query_loan(db, &loan.loanID).await?;
+        payments.extend(loan_payments);
+        if let Some(write_off) = crate::loan_write_off::query_write_off(db, &loan.loanID).await? {
+            write_offs.push(write_off);
+            recoveries.extend(crate::loan_write_off::query_recoveries(db, &loan.loanID).await?);
+        }
     }
 
-    let (cur_year, _) = (
-        chrono::Local::today().year(),
-        chrono::Local::today().month0(),
-    );
-    let start_date = NaiveDate::from_yo(cur_year - DISPLAY_DURATION_YEAR as i32 + 1, 1);
-
-    let mut account_stat: [Statistic<(BigDecimal, u32)>; 2] = Default::default();
-
     let tuple_add_assign = |left: &mut (BigDecimal, u32), right: &(BigDecimal, u32)| {
         left.0 += &right.0;
         left.1 += &right.1;
     };
 
-    for i in 0..2 {
-        account_stat[i] = get_statistics(
-            accounts[i]
-                .iter()
-                .map(|item| ((item.balance.clone(), 1), item.openDate)),
-            &start_date,
-            tuple_add_assign,
-        );
-    }
+    let [saving_balances, checking_balances] =
+        [AccountType::SavingAccount, AccountType::CheckingAccount].map(|account_type| {
+            let account_type = account_type.to_string();
+            get_balances(
+                movements
+                    .iter()
+                    .filter(|movement| movement.accountType == account_type),
+                reporting_period,
+            )
+        });
 
-    let payment_statistic: Statistic<(BigDecimal, u32)> = get_statistics(
-        loans_and_payment
+    // Opening and closing balances are paid in and out as well
+    let is_deposit = |movement: &&AccountMovement| movement.amount > BigDecimal::zero();
+    let deposit_statistic = get_statistics(
+        movements
             .iter()
-            .map(|(_, payments)| {
-                payments
-                    .iter()
-                    .map(|payment| ((payment.amount.clone(), 1), payment.date))
-            })
-            .flatten(),
-        &start_date,
+            .filter(is_deposit)
+            .map(|movement| ((movement.amount.clone(), 1), movement.date)),
+        reporting_period,
+        tuple_add_assign,
+    );
+    let withdrawal_statistic = get_statistics(
+        movements
+            .iter()
+            .filter(|movement| !movement.amount.is_zero() && !is_deposit(movement))
+            .map(|movement| ((-movement.amount.clone(), 1), movement.date)),
+        reporting_period,
+        tuple_add_assign,
+    );
+
+    let new_account_statistic = get_statistics(
+        movements
+            .iter()
+            .filter(|movement| movement.kind == MovementKind::Open.as_str())
+            .map(|movement| ((movement.amount.clone(), 1), movement.date)),
+        reporting_period,
+        tuple_add_assign,
+    );
+
+    let disbursement_statistic = get_statistics(
+        disbursements
+            .iter()
+            .map(|agreement| ((agreement.amount.clone(), 1), agreement.effectiveDate)),
+        reporting_period,
+        tuple_add_assign,
+    );
+
+    // Reversals are negative payments, cancelling the reversed ones out
+    let repayment_statistic = get_statistics(
+        payments
+            .iter()
+            .map(|payment| ((payment.amount.clone(), 1), payment.date)),
+        reporting_period,
+        tuple_add_assign,
+    );
+
+    let write_off_statistic = get_statistics(
+        write_offs
+            .iter()
+            .map(|write_off| ((write_off.amount.clone(), 1), write_off.date)),
+        reporting_period,
+        tuple_add_assign,
+    );
+
+    let recovery_statistic = get_statistics(
+        recoveries
+            .iter()
+            .map(|recovery| ((recovery.amount.clone(), 1), recovery.date)),
+        reporting_period,
         tuple_add_assign,
     );
 
-    fn statistic_context<'a>(
-        statistic: &'a Statistic<(BigDecimal, u32)>,
-        start_year: i32,
-    ) -> DisplayedStatistic {
-        let mut result: DisplayedStatistic = Default::default();
-        let season_label = ["Q1", "Q2", "Q3", "Q4"];
-        let month_label = [
-            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sept", "Oct", "Nov", "Dec",
-        ];
-        for i in 0..statistic.len() {
-            result[i].0 = (start_year as usize + i).to_string();
-            for j in 0..statistic[i].len() {
-                result[i].2[j].0 = season_label[j].to_string();
-                for k in 0..statistic[i][j].len() {
-                    result[i].2[j].2[k].0 = month_label[j * 3 + k].to_string();
-                    result[i].2[j].2[k].1 .0 = statistic[i][j][k].0.to_string();
-                    result[i].2[j].2[k].1 .1 = statistic[i][j][k].1.to_string();
-                }
-                result[i].2[j].1 .0 = statistic[i][j]
+    let series = |id, description, is_balance, values| Series {
+        id,
+        description,
+        is_balance,
+        values,
+    };
+    Ok(vec![
+        series(
+            "saving_balance",
+            "Saving account balance",
+            true,
+            saving_balances,
+        ),
+        series(
+            "checking_balance",
+            "Checking account balance",
+            true,
+            checking_balances,
+        ),
+        series("deposit", "Deposits", false, deposit_statistic),
+        series("withdrawal", "Withdrawals", false, withdrawal_statistic),
+        series("new_account", "New accounts", false, new_account_statistic),
+        series(
+            "disbursement",
+            "Loans disbursed",
+            false,
+            disbursement_statistic,
+        ),
+        series("repayment", "Loan repayments", false, repayment_statistic),
+        series(
+            "written_off",
+            "Written-off loans",
+            false,
+            write_off_statistic,
+        ),
+        series("recovery", "Recoveries", false, recovery_statistic),
+    ])
+}
+
+#[get("/profile/subbranch?<name>&<from>&<to>&<granularity>")]
+pub async fn subbranch_profile(
+    mut db: Connection<BankManage>,
+    name: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    granularity: Option<Granularity>,
+) -> Template {
+    let reporting_period = unwrap_or_return!(
+        ReportingPeriod::new(from, to, granularity),
+        "Invalid reporting period"
+    );
+
+    // subbranch info
+    let subbranch = unwrap_or_return!(
+        query_subbranch(&mut db, name).await,
+        "Error querying subbranch"
+    );
+
+    let statistics = unwrap_or_return!(
+        query_statistics(&mut db, name, &reporting_period).await,
+        "Error querying statistics"
+    );
+
+    let statistic_context = |series: Series| {
+        let Series {
+            id,
+            description,
+            is_balance,
+            values: statistic,
+        } = series;
+        DisplayedStatistic {
+            id,
+            description,
+            is_balance,
+            periods: reporting_period
+                .periods
+                .iter()
+                .zip(&statistic)
+                .rev()
+                .map(|(period, (amount, count))| PeriodStatistic {
+                    label: period.label.clone(),
+                    amount: amount.to_string(),
+                    count: count.to_string(),
+                })
+                .collect(),
+            chart: chart::render(
+                if is_balance {
+                    ChartKind::Line
+                } else {
+                    ChartKind::Bar
+                },
+                description,
+                &reporting_period
+                    .periods
+                    .iter()
+                    .zip(&statistic)
+                    .map(|(period, (amount, count))| ChartPoint {
+                        label: period.label.clone(),
+                        value: amount.to_f64().unwrap_or_default(),
+                        display: format!("{amount} ({count})"),
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            total_amount: (!is_balance).then(|| {
+                statistic
                     .iter()
                     .map(|elem| &elem.0)
                     .sum::<BigDecimal>()
-                    .to_string();
-                result[i].2[j].1 .1 = statistic[i][j]
+                    .to_string()
+            }),
+            total_count: (!is_balance).then(|| {
+                statistic
                     .iter()
                     .map(|elem| &elem.1)
                     .sum::<u32>()
-                    .to_string();
-            }
-            result[i].1 .0 = statistic[i]
-                .iter()
-                .flatten()
-                .map(|elem| &elem.0)
-                .sum::<BigDecimal>()
-                .to_string();
-            result[i].1 .1 = statistic[i]
-                .iter()
-                .flatten()
-                .map(|elem| &elem.1)
-                .sum::<u32>()
-                .to_string();
+                    .to_string()
+            }),
         }
-        result.reverse();
-        result
-    }
+    };
 
     Template::render(
         "subbranch-profile",
         &SubbranchProfileContext {
+            subbranch_query: RawStr::new(&subbranch.subbranchName)
+                .percent_encode()
+                .to_string(),
             subbranch_name: subbranch.subbranchName,
             subbranch_city: subbranch.city,
             subbranch_asset: subbranch.subbranchAsset.to_string(),
-            statistics: HashMap::from([
-                (
-                    "saving_account".to_string(),
-                    statistic_context(&account_stat[0], start_date.year()),
-                ),
-                (
-                    "checking_account".to_string(),
-                    statistic_context(&account_stat[1], start_date.year()),
-                ),
-                (
-                    "payment".to_string(),
-                    statistic_context(&payment_statistic, start_date.year()),
-                ),
-            ]),
+            from: reporting_period.from.to_string(),
+            to: reporting_period.to.to_string(),
+            granularity: reporting_period.granularity.as_str(),
+            granularities: reporting_period.granularity_options(),
+            statistics: statistics.into_iter().map(statistic_context).collect(),
         },
     )
 }
 
+/// Every statistic of the subbranch, one row per period
+#[get("/export/subbranch?<name>&<from>&<to>&<granularity>&<format>")]
+pub async fn export_statistics(
+    mut db: Connection<BankManage>,
+    name: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    granularity: Option<Granularity>,
+    format: ExportFormat,
+) -> Result<Export, Template> {
+    let reporting_period = unwrap_or!(ReportingPeriod::new(from, to, granularity), e, {
+        return Err(error_template!(e, "Invalid reporting period"));
+    });
+    unwrap_or!(query_subbranch(&mut db, name).await, e, {
+        return Err(error_template!(e, "Error querying subbranch"));
+    });
+    let statistics = unwrap_or!(
+        query_statistics(&mut db, name, &reporting_period).await,
+        e,
+        { return Err(error_template!(e, "Error querying statistics")) }
+    );
+
+    let mut table = Table::new(
+        format!("{name} statistics"),
+        vec!["Statistic", "Period", "From", "To", "Amount", "Count"],
+    );
+    for series in statistics {
+        for (period, (amount, count)) in reporting_period.periods.iter().zip(series.values) {
+            table.push(vec![
+                series.description.into(),
+                period.label.clone().into(),
+                period.start.to_string().into(),
+                period.end.pred().to_string().into(),
+                amount.into(),
+                (count as i64).into(),
+            ]);
+        }
+    }
+    table
+        .export(format)
+        .map_err(|e| error_template!(e, "Error exporting statistics"))
+}
+
 pub async fn set_subbranch_asset(
     db: &mut Connection<BankManage>,
     subbranch: &str,
@@ -232,30 +428,6 @@ pub async fn query_subbranch(
     .await?)
 }
 
-/// Returns [SavingAccountIDs, CheckingAccountIDs]
-async fn query_associated_account(
-    db: &mut Connection<BankManage>,
-    subbranch: &str,
-) -> Result<[HashSet<String>; 2], GenericError> {
-    let account_manage = sqlx::query_as!(
-        AccountManagement,
-        "SELECT * FROM accountmanagement WHERE subbranchName=?",
-        subbranch
-    )
-    .fetch_all(&mut **db)
-    .await?;
-    let mut account_ids = [HashSet::<String>::new(), HashSet::<String>::new()];
-    account_manage.into_iter().for_each(|account_manage| {
-        if let Some(id) = account_manage.savingAccountID {
-            account_ids[0].insert(id);
-        }
-        if let Some(id) = account_manage.checkingAccountID {
-            account_ids[1].insert(id);
-        }
-    });
-    Ok(account_ids)
-}
-
 async fn query_associated_loans(
     db: &mut Connection<BankManage>,
     subbranch: &str,
@@ -18,6 +18,92 @@ pub fn get_list_from_input<Container: std::iter::FromIterator<std::string::Strin
         .collect::<Container>()
 }
 
+/// Splits a whitespace-separated list of resident ID numbers, validating and normalizing each of them
+pub fn get_client_id_list_from_input<Container: std::iter::FromIterator<std::string::String>>(
+    input: &str,
+) -> Result<Container, ClientIDError> {
+    get_list_from_input::<Vec<_>>(input)
+        .iter()
+        .map(|id| validate_client_id(id))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct ClientIDError {
+    pub id: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ClientIDError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid resident ID number {}: {}", self.id, self.reason)
+    }
+}
+
+impl std::error::Error for ClientIDError {}
+
+// Province-level administrative division codes of GB/T 2260
+const PROVINCE_CODES: [&str; 34] = [
+    "11", "12", "13", "14", "15", "21", "22", "23", "31", "32", "33", "34", "35", "36", "37", "41",
+    "42", "43", "44", "45", "46", "50", "51", "52", "53", "54", "61", "62", "63", "64", "65", "71",
+    "81", "82",
+];
+
+/// Validates a resident ID number against GB 11643 and returns it with a lowercase check digit `x` normalized to `X`.
+/// The region code, the embedded birth date and the check digit are verified.
+pub fn validate_client_id(id: &str) -> Result<String, ClientIDError> {
+    let id = id.trim().to_uppercase();
+    let error = |reason: &str| ClientIDError {
+        id: id.clone(),
+        reason: reason.to_string(),
+    };
+    if !id.is_ascii()
+        || id.len() != 18
+        || !id[..17].bytes().all(|c| c.is_ascii_digit())
+        || !(id.as_bytes()[17].is_ascii_digit() || id.as_bytes()[17] == b'X')
+    {
+        return Err(error("should be 17 digits followed by a digit or X"));
+    }
+
+    if !PROVINCE_CODES.contains(&&id[..2]) {
+        return Err(error("unknown region code"));
+    }
+
+    let birth_date = chrono::NaiveDate::parse_from_str(&id[6..14], "%Y%m%d")
+        .map_err(|_| error("invalid birth date"))?;
+    if birth_date < chrono::NaiveDate::from_ymd(1900, 1, 1)
+        || birth_date > chrono::Local::today().naive_local()
+    {
+        return Err(error("birth date out of range"));
+    }
+
+    const WEIGHTS: [u32; 17] = [7, 9, 10, 5, 8, 4, 2, 1, 6, 3, 7, 9, 10, 5, 8, 4, 2];
+    const CHECK_DIGITS: [u8; 11] = *b"10X98765432";
+    let sum: u32 = id[..17]
+        .bytes()
+        .zip(WEIGHTS.iter())
+        .map(|(c, weight)| (c - b'0') as u32 * weight)
+        .sum();
+    if CHECK_DIGITS[(sum % 11) as usize] != id.as_bytes()[17] {
+        return Err(error("check digit mismatch"));
+    }
+    Ok(id)
+}
+
+/// Returns the same day `months` months later, clamped to the end of shorter months
+pub fn add_months(date: &chrono::NaiveDate, months: u32) -> chrono::NaiveDate {
+    use chrono::Datelike;
+    let month0 = date.month0() + months;
+    let (year, month) = (date.year() + (month0 / 12) as i32, month0 % 12 + 1);
+    let mut day = date.day();
+    loop {
+        match chrono::NaiveDate::from_ymd_opt(year, month, day) {
+            Some(result) => return result,
+            None => day -= 1,
+        }
+    }
+}
+
 // If `value` is not empty, wraps it with "'"; else returns "NULL"
 pub fn validate_string_value(value: &str) -> String {
     if value.is_empty() {
@@ -49,7 +135,8 @@ pub fn get_restriction() -> Restriction {
         "id_list", format!(r"\s*{clientID}(\s+{clientID})*\s*");
         "amount", r"[0-9]{1,62}(\.[0-9]{1,2})?";
         "currency_type", r"[a-zA-Z]+";
-        "float", r"[0-9]{1,}(\.[0-9]{1,})?"
+        "float", r"[0-9]{1,}(\.[0-9]{1,})?";
+        "term", r"[1-9][0-9]{0,2}"
     )
 }
 
@@ -121,3 +208,71 @@ macro_rules! unwrap_or_return {
         $crate::unwrap_or!($result, e, { return crate::error_template!(e, $info) })
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_months_clamped_to_the_end_of_the_month() {
+        let date = |text: &str| text.parse::<chrono::NaiveDate>().unwrap();
+        assert_eq!(add_months(&date("2022-01-15"), 0), date("2022-01-15"));
+        assert_eq!(add_months(&date("2022-01-31"), 1), date("2022-02-28"));
+        assert_eq!(add_months(&date("2024-01-31"), 1), date("2024-02-29"));
+        assert_eq!(add_months(&date("2022-03-31"), 1), date("2022-04-30"));
+        assert_eq!(add_months(&date("2022-11-30"), 3), date("2023-02-28"));
+        assert_eq!(add_months(&date("2022-12-15"), 25), date("2025-01-15"));
+    }
+
+    #[test]
+    fn accepts_valid_client_ids() {
+        assert_eq!(
+            validate_client_id("11010519491231002x").unwrap(),
+            "11010519491231002X"
+        );
+        assert_eq!(
+            validate_client_id(" 440304199001011233 ").unwrap(),
+            "440304199001011233"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_client_ids() {
+        for id in [
+            "",
+            "1101051949123100",
+            "1101051949123100211",
+            "11010519491231002Y",
+            "1101051949123100X2",
+        ] {
+            assert!(validate_client_id(id).is_err(), "{id}");
+        }
+    }
+
+    #[test]
+    fn rejects_non_ascii_client_ids_without_panicking() {
+        // 18 bytes, of which the last three are a single character
+        assert!(validate_client_id("123456789012345中").is_err());
+        assert!(validate_client_id("1101051949123100中").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_region_date_and_check_digit() {
+        assert_eq!(
+            validate_client_id("99010519491231002X").unwrap_err().reason,
+            "unknown region code"
+        );
+        assert_eq!(
+            validate_client_id("110105194913310028").unwrap_err().reason,
+            "invalid birth date"
+        );
+        assert_eq!(
+            validate_client_id("110105189912310021").unwrap_err().reason,
+            "birth date out of range"
+        );
+        assert_eq!(
+            validate_client_id("110105194912310021").unwrap_err().reason,
+            "check digit mismatch"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::utility::{get_restriction, Restriction};
+
+/// Field name -> error message
+pub type FieldErrors = HashMap<String, String>;
+
+/// Forms whose input is checked on the server with the same rules as the `pattern` attributes of the templates
+pub trait Validate {
+    fn validate(&self) -> FieldErrors;
+}
+
+/// Context to re-render a form with the input of the user and the errors of each field
+#[derive(Serialize)]
+pub struct FormContext<'a, T: Serialize> {
+    pub value: &'a T,
+    pub errors: FieldErrors,
+    pub restriction: Restriction,
+}
+
+impl<'a, T: Serialize + Validate> FormContext<'a, T> {
+    pub fn new(value: &'a T) -> Self {
+        FormContext {
+            value,
+            errors: value.validate(),
+            restriction: get_restriction(),
+        }
+    }
+}
+
+pub struct Validator {
+    restriction: Restriction,
+    pub errors: FieldErrors,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator {
+            restriction: get_restriction(),
+            errors: FieldErrors::new(),
+        }
+    }
+}
+
+impl Validator {
+    fn fail(&mut self, field: &str, message: String) {
+        self.errors.entry(field.to_string()).or_insert(message);
+    }
+
+    pub fn required(&mut self, field: &str, value: &str) -> &mut Self {
+        if value.trim().is_empty() {
+            self.fail(field, "This field is required".to_string());
+        }
+        self
+    }
+
+    /// Matches the whole `value` against the restriction named `rule`, as an HTML `pattern` attribute does.
+    /// Empty values are left to `required`.
+    pub fn pattern(&mut self, field: &str, value: &str, rule: &str) -> &mut Self {
+        if value.is_empty() {
+            return self;
+        }
+        let re = Regex::new(&format!("^(?:{})$", self.restriction[rule]))
+            .expect("Regex pattern error during parsing restrictions");
+        if !re.is_match(value) {
+            self.fail(
+                field,
+                format!("Invalid format of {}", rule.replace('_', " ")),
+            );
+        }
+        self
+    }
+
+    pub fn one_of(&mut self, field: &str, value: &str, options: &[&str]) -> &mut Self {
+        if !options.contains(&value) {
+            self.fail(field, format!("Should be one of {}", options.join(", ")));
+        }
+        self
+    }
+
+    pub fn check<E: std::fmt::Display>(
+        &mut self,
+        field: &str,
+        result: Result<impl Sized, E>,
+    ) -> &mut Self {
+        if let Err(e) = result {
+            self.fail(field, e.to_string());
+        }
+        self
+    }
+
+    pub fn finish(&mut self) -> FieldErrors {
+        std::mem::take(&mut self.errors)
+    }
+}